@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mdict::{MDictHeader, MDictMode};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = MDictHeader::new(Cursor::new(data), MDictMode::Mdx);
+    let _ = MDictHeader::new(Cursor::new(data), MDictMode::Mdd);
+});
@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mdict::{read_key_block, MDictIndex, MDictMode};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let mut index = match MDictIndex::new(Cursor::new(data), MDictMode::Mdx) {
+        Ok(index) => index,
+        Err(_) => return,
+    };
+    let (key_blocks, _) = match index.make_key_index() {
+        Ok(result) => result,
+        Err(_) => return,
+    };
+    let header = index.into_header();
+    for (i, block) in key_blocks.iter().enumerate().take(64) {
+        let _ = read_key_block(Cursor::new(data), &header, block, i);
+    }
+});
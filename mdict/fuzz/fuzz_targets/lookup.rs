@@ -0,0 +1,21 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mdict::{lookup, MDictIndex, MDictMode};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let mut index = match MDictIndex::new(Cursor::new(data), MDictMode::Mdx) {
+        Ok(index) => index,
+        Err(_) => return,
+    };
+    let (blocks, keys) = match index.make_index() {
+        Ok(result) => result,
+        Err(_) => return,
+    };
+    let header = index.into_header();
+    for (_, key) in keys.iter().take(64) {
+        if let Ok(record) = lookup(Cursor::new(data), key, &blocks) {
+            let _ = header.decode_string(record);
+        }
+    }
+});
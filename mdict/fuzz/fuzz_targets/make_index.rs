@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mdict::{MDictIndex, MDictMode};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    for mode in [MDictMode::Mdx, MDictMode::Mdd].iter() {
+        if let Ok(mut index) = MDictIndex::new(Cursor::new(data), *mode) {
+            let _ = index.make_index();
+        }
+    }
+});
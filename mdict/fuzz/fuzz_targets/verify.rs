@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use mdict::{MDictIndex, MDictMode};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let mut index = match MDictIndex::new(Cursor::new(data), MDictMode::Mdx) {
+        Ok(index) => index,
+        Err(_) => return,
+    };
+    let _ = index.verify();
+});
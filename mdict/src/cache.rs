@@ -0,0 +1,160 @@
+// Cache of decompressed record blocks of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use bytes::Bytes;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// The number of file and the offset of block in the file
+type BlockKey = (u64, u64);
+
+/// Counters of a [`MDictBlockCache`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MDictCacheStats {
+    /// Number of blocks found in the cache.
+    pub hits: u64,
+    /// Number of blocks not in the cache, which are read and decompressed.
+    pub misses: u64,
+    /// Number of blocks in the cache.
+    pub blocks: usize,
+    /// Total size of the blocks in the cache.
+    pub size: usize,
+}
+
+#[derive(Default)]
+struct CacheState {
+    // the block and when it is used last time
+    blocks: HashMap<BlockKey, (Bytes, u64)>,
+    // blocks by the time they are used last time, the least recently used comes first
+    used: BTreeMap<u64, BlockKey>,
+    clock: u64,
+    size: usize,
+}
+
+impl CacheState {
+    fn touch(&mut self, key: BlockKey) -> Option<Bytes> {
+        self.clock += 1;
+        let clock = self.clock;
+        let (block, used) = self.blocks.get_mut(&key)?;
+        self.used.remove(used);
+        self.used.insert(clock, key);
+        *used = clock;
+        Some(block.clone())
+    }
+
+    fn remove(&mut self, key: BlockKey) {
+        if let Some((block, used)) = self.blocks.remove(&key) {
+            self.used.remove(&used);
+            self.size -= block.len();
+        }
+    }
+}
+
+/// A LRU cache of decompressed record blocks shared by lookups.
+///
+/// A record block is decompressed as a whole even if only a small record in it is needed, so the
+/// lookups of nearby keywords, or the resources of one entry, decompress the same block again and
+/// again without this cache. See [`lookup_cached`](crate::lookup_cached).
+///
+/// Blocks are identified by the number of file and their offset in the file. A cache can be shared
+/// by many files, each file should take its own number from [`MDictBlockCache::new_file_id`].
+/// The least recently used blocks are dropped when the total size of blocks exceeds the budget.
+pub struct MDictBlockCache {
+    budget: usize,
+    state: Mutex<CacheState>,
+    next_file: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl MDictBlockCache {
+    /// Create a cache which keeps at most `budget` bytes of decompressed blocks.
+    ///
+    /// A block larger than the budget is never cached.
+    pub fn new(budget: usize) -> MDictBlockCache {
+        MDictBlockCache {
+            budget,
+            state: Mutex::new(CacheState::default()),
+            next_file: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Take a number which identifies a file in this cache.
+    pub fn new_file_id(&self) -> u64 {
+        self.next_file.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The budget in bytes given to [`MDictBlockCache::new`].
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// The counters of hits and misses, and the blocks in the cache now.
+    pub fn stats(&self) -> MDictCacheStats {
+        let state = self.state.lock().unwrap();
+        MDictCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            blocks: state.blocks.len(),
+            size: state.size,
+        }
+    }
+
+    /// Drop all blocks in the cache, the counters are kept.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.blocks.clear();
+        state.used.clear();
+        state.size = 0;
+    }
+
+    pub(crate) fn get(&self, file: u64, offset: u64) -> Option<Bytes> {
+        let block = self.state.lock().unwrap().touch((file, offset));
+        let counter = if block.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        block
+    }
+
+    pub(crate) fn insert(&self, file: u64, offset: u64, block: Bytes) {
+        if block.len() > self.budget {
+            return;
+        }
+        let key = (file, offset);
+        let mut state = self.state.lock().unwrap();
+        // another lookup may have put this block while it was decompressed here
+        state.remove(key);
+        while state.size + block.len() > self.budget {
+            let oldest = match state.used.values().next() {
+                Some(key) => *key,
+                None => break,
+            };
+            state.remove(oldest);
+        }
+        state.clock += 1;
+        let clock = state.clock;
+        state.size += block.len();
+        state.used.insert(clock, key);
+        state.blocks.insert(key, (block, clock));
+    }
+}
@@ -0,0 +1,232 @@
+// Encryption of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2012, 2013, 2015 Xiaoqiang Wang <xiaoqiangwang AT gmail DOT com>
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::MDictRegisterBy;
+use ripemd128::{Digest, Ripemd128};
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+/// The key to read a registration-locked MDict file.
+///
+/// The publisher of a locked dictionary gives every user a registration code, usually as
+/// a `.key` file next to the `.mdx` file. The code is bound to the email or the device ID of
+/// the user, as indicated by the `RegisterBy` attribute of the header.
+#[derive(Clone)]
+pub struct MDictKey([u8; 16]);
+
+impl MDictKey {
+    /// Derive the key from the registration code in hex and the email or device ID it is bound to.
+    ///
+    /// `register_by` is the `RegisterBy` attribute of the header, see [`MDictMetadata::register_by`].
+    /// An email is hashed in UTF-16LE while a device ID is hashed as it is.
+    ///
+    /// [`MDictMetadata::register_by`]: crate::MDictMetadata::register_by
+    ///
+    /// # Error
+    ///
+    /// [`io::Error`] with [`ErrorKind::InvalidInput`] will return if the registration code is not
+    /// 32 hex digits.
+    pub fn new(
+        reg_code: &str,
+        user_id: &str,
+        register_by: MDictRegisterBy,
+    ) -> io::Result<MDictKey> {
+        let reg_code = reg_code.trim();
+        let invalid = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid registration code: {}", reg_code),
+            )
+        };
+        if reg_code.len() != 32 || !reg_code.is_ascii() {
+            return Err(invalid());
+        }
+        let mut key = [0; 16];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&reg_code[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        let digest = match register_by {
+            MDictRegisterBy::Email => {
+                let email: Vec<u8> = user_id.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                ripemd128(&email)
+            }
+            MDictRegisterBy::DeviceId => ripemd128(user_id.as_bytes()),
+        };
+        salsa20_8(&mut key, &digest);
+        Ok(MDictKey(key))
+    }
+
+    /// Read the registration code from a `.key` file and derive the key like [`MDictKey::new`].
+    pub fn from_key_file<P: AsRef<Path>>(
+        path: P,
+        user_id: &str,
+        register_by: MDictRegisterBy,
+    ) -> io::Result<MDictKey> {
+        let content = fs::read_to_string(path)?;
+        let reg_code = content
+            .lines()
+            .map(|l| l.trim())
+            .find(|l| !l.is_empty())
+            .unwrap_or_default();
+        MDictKey::new(reg_code, user_id, register_by)
+    }
+
+    /// Use the derived key directly, this is the key MDict stores after registration.
+    pub fn from_bytes(key: [u8; 16]) -> MDictKey {
+        MDictKey(key)
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+// RIPEMD-128 digest, which is used to derive the key of encrypted blocks
+pub(crate) fn ripemd128(data: &[u8]) -> [u8; 16] {
+    let mut hasher = Ripemd128::new();
+    hasher.input(data);
+    hasher.result().as_slice().try_into().unwrap()
+}
+
+// The simple encryption of the keywords block index of v2 and the blocks of v3
+pub(crate) fn fast_decrypt(data: &mut [u8], key: &[u8]) {
+    let mut previous = 0x36;
+    for (i, v) in data.iter_mut().enumerate() {
+        let t = v.rotate_left(4) ^ previous ^ (i as u8) ^ key[i % key.len()];
+        previous = *v;
+        *v = t;
+    }
+}
+
+// Salsa20/8 with 128 bits key and zero nonce, encryption and decryption are the same.
+pub(crate) fn salsa20_8(data: &mut [u8], key: &[u8; 16]) {
+    // "expand 16-byte k"
+    const TAU: [u32; 4] = [0x6170_7865, 0x3120_646e, 0x7962_2d36, 0x6b20_6574];
+    let k: Vec<u32> = key
+        .chunks(4)
+        .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+        .collect();
+    let mut input = [
+        TAU[0], k[0], k[1], k[2], k[3], TAU[1], 0, 0, 0, 0, TAU[2], k[0], k[1], k[2], k[3], TAU[3],
+    ];
+    for chunk in data.chunks_mut(64) {
+        let mut x = input;
+        for _ in 0..4 {
+            // column round
+            quarter_round(&mut x, 0, 4, 8, 12);
+            quarter_round(&mut x, 5, 9, 13, 1);
+            quarter_round(&mut x, 10, 14, 2, 6);
+            quarter_round(&mut x, 15, 3, 7, 11);
+            // row round
+            quarter_round(&mut x, 0, 1, 2, 3);
+            quarter_round(&mut x, 5, 6, 7, 4);
+            quarter_round(&mut x, 10, 11, 8, 9);
+            quarter_round(&mut x, 15, 12, 13, 14);
+        }
+        let stream = x
+            .iter()
+            .zip(input.iter())
+            .flat_map(|(x, i)| x.wrapping_add(*i).to_le_bytes());
+        for (d, s) in chunk.iter_mut().zip(stream) {
+            *d ^= s;
+        }
+        // 64 bits block counter
+        input[8] = input[8].wrapping_add(1);
+        if input[8] == 0 {
+            input[9] = input[9].wrapping_add(1);
+        }
+    }
+}
+
+fn quarter_round(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+    x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+    x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+    x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ripemd128_vectors() {
+        assert_eq!(
+            ripemd128(b"")[..],
+            hex("cdf26213a150dc3ecb610f18f6b38b46")[..]
+        );
+        assert_eq!(
+            ripemd128(b"abc")[..],
+            hex("c14a12199c66e4ba84636b0f69144c77")[..]
+        );
+    }
+
+    #[test]
+    fn salsa20_8_vector() {
+        let key: Vec<u8> = (0..16).collect();
+        // more than a block of 64 bytes to check the block counter
+        let mut data = [0; 80];
+        salsa20_8(&mut data, key[..].try_into().unwrap());
+        let expected = hex(concat!(
+            "b944f975fa4f57828cd395ec502244c7ec8cc5c03b0855362712093a0f3e4d7f",
+            "ad253a30ae26c6a4d5533581684e4c9299f95b4a04d0e0fb045eecad98b9bed9",
+            "f97609f4e5fe4c02d4a864a50ae7bb58",
+        ));
+        assert_eq!(data[..], expected[..]);
+        salsa20_8(&mut data, key[..].try_into().unwrap());
+        assert_eq!(data, [0; 80]);
+    }
+
+    #[test]
+    fn fast_decrypt_vector() {
+        let key = ripemd128(&[1, 2, 3, 4]);
+        assert_eq!(key[..], hex("f772b6086df2e870dc914416fcbfdaf9")[..]);
+        let mut data: Vec<u8> = (0..160).step_by(10).collect();
+        fast_decrypt(&mut data, &key);
+        assert_eq!(data, hex("c1d3fffef5fc1f2f976d529f19e29e13"));
+    }
+
+    #[test]
+    fn derive_key() {
+        let reg_code = "00112233445566778899AABBCCDDEEFF";
+        let key = MDictKey::new(reg_code, "user@example.com", MDictRegisterBy::Email).unwrap();
+        assert_eq!(
+            key.as_bytes()[..],
+            hex("cffdf4220cabd4f5f0e52e3c92a04468")[..]
+        );
+        let key = MDictKey::new(reg_code, "ABCD-1234", MDictRegisterBy::DeviceId).unwrap();
+        assert_eq!(
+            key.as_bytes()[..],
+            hex("03863c6299ffc1cab5e30e567a735c01")[..]
+        );
+
+        for reg_code in ["0011", &"zz".repeat(16)] {
+            let error = MDictKey::new(reg_code, "ABCD-1234", MDictRegisterBy::DeviceId).err();
+            assert_eq!(error.unwrap().kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
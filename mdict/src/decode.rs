@@ -0,0 +1,148 @@
+// Decoding of strings of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use encoding_rs::{Encoding, BIG5, GB18030, GBK, UTF_16BE, UTF_16LE, UTF_8};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// How to decode keywords and records which are invalid in the encoding of the file.
+///
+/// Some dictionaries have a few keywords or records in another encoding, or with broken
+/// byte sequences. They fail the whole keyword block or lookup with [`MDictError::Decode`](crate::MDictError::Decode)
+/// by default. See [`MDictIndex::set_decode_policy`](crate::MDictIndex::set_decode_policy).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum MDictDecodePolicy {
+    /// Fail with [`MDictError::Decode`](crate::MDictError::Decode), the default.
+    #[default]
+    Strict,
+    /// Replace the invalid byte sequences with U+FFFD REPLACEMENT CHARACTER.
+    Lossy,
+    /// Try the encodings in order, such as GBK for a file in UTF-8 with a few keywords
+    /// left in GBK by the tool which converted it.
+    /// The invalid byte sequences are replaced like `Lossy` if none of them can decode the string.
+    ///
+    /// The keywords are split by the null terminator of the encoding of the file, so the
+    /// encodings should have the same code unit size, don't mix UTF-16 with others.
+    Fallback(Vec<&'static Encoding>),
+}
+
+/// Counters of strings which are invalid in the encoding of the file, see [`MDictDecodePolicy`].
+///
+/// Strings decoded by [`MDictHeader::decode_string`](crate::MDictHeader::decode_string) are
+/// counted as records.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MDictDecodeStats {
+    /// Number of keywords decoded by an alternative encoding.
+    pub keys_fallback: u64,
+    /// Number of keywords with invalid byte sequences replaced.
+    pub keys_replaced: u64,
+    /// Number of records decoded by an alternative encoding.
+    pub records_fallback: u64,
+    /// Number of records with invalid byte sequences replaced.
+    pub records_replaced: u64,
+}
+
+// How a string is decoded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Decoded {
+    Valid,
+    Fallback,
+    Replaced,
+}
+
+#[derive(Default)]
+pub(crate) struct DecodeCounters {
+    keys_fallback: AtomicU64,
+    keys_replaced: AtomicU64,
+    records_fallback: AtomicU64,
+    records_replaced: AtomicU64,
+}
+
+impl DecodeCounters {
+    pub(crate) fn count_key(&self, decoded: Decoded) {
+        Self::count(decoded, &self.keys_fallback, &self.keys_replaced);
+    }
+
+    pub(crate) fn count_record(&self, decoded: Decoded) {
+        Self::count(decoded, &self.records_fallback, &self.records_replaced);
+    }
+
+    fn count(decoded: Decoded, fallback: &AtomicU64, replaced: &AtomicU64) {
+        let counter = match decoded {
+            Decoded::Valid => return,
+            Decoded::Fallback => fallback,
+            Decoded::Replaced => replaced,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> MDictDecodeStats {
+        MDictDecodeStats {
+            keys_fallback: self.keys_fallback.load(Ordering::Relaxed),
+            keys_replaced: self.keys_replaced.load(Ordering::Relaxed),
+            records_fallback: self.records_fallback.load(Ordering::Relaxed),
+            records_replaced: self.records_replaced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Decode `src` in `encoding`, then by `policy` if it is invalid.
+// Returns `None` if it is invalid and the policy is strict.
+pub(crate) fn decode(
+    encoding: &'static Encoding,
+    policy: &MDictDecodePolicy,
+    src: &[u8],
+) -> Option<(String, Decoded)> {
+    let (cow, _encoding_used, had_errors) = encoding.decode(src);
+    if !had_errors {
+        return Some((cow.into_owned(), Decoded::Valid));
+    }
+    match policy {
+        MDictDecodePolicy::Strict => None,
+        MDictDecodePolicy::Lossy => Some((cow.into_owned(), Decoded::Replaced)),
+        MDictDecodePolicy::Fallback(encodings) => {
+            for alternative in encodings {
+                let (alt, _encoding_used, had_errors) = alternative.decode(src);
+                if !had_errors {
+                    return Some((alt.into_owned(), Decoded::Fallback));
+                }
+            }
+            Some((cow.into_owned(), Decoded::Replaced))
+        }
+    }
+}
+
+// The encoding of the `Encoding` attribute of header. Besides the labels of the Encoding Standard,
+// the variants written by other tools such as "UTF_8", "UTF16" and "GB-2312" are recognized.
+pub(crate) fn encoding_for_label(label: &str) -> Option<&'static Encoding> {
+    if let Some(encoding) = Encoding::for_label(label.trim().as_bytes()) {
+        return Some(encoding);
+    }
+    let normalized: String = label
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    match normalized.as_str() {
+        "UTF8" => Some(UTF_8),
+        "UTF16" | "UTF16LE" => Some(UTF_16LE),
+        "UTF16BE" => Some(UTF_16BE),
+        "GBK" | "GB2312" | "CP936" => Some(GBK),
+        "GB18030" => Some(GB18030),
+        "BIG5" | "CP950" => Some(BIG5),
+        _ => None,
+    }
+}
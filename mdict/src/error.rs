@@ -0,0 +1,298 @@
+// Errors of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd) Praser
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+/// A specialized `Result` type for parsing MDict files.
+pub type MDictResult<T> = Result<T, MDictError>;
+
+/// Kind of the block where an error occurs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MDictBlockKind {
+    /// The header of keyword blocks, which contains the number and size of keyword blocks.
+    KeyBlockHeader,
+    /// The compressed index of keyword blocks.
+    KeyBlockIndex,
+    /// A keyword block.
+    KeyBlock,
+    /// A record block.
+    RecordBlock,
+}
+
+impl fmt::Display for MDictBlockKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MDictBlockKind::KeyBlockHeader => "keywords block header",
+            MDictBlockKind::KeyBlockIndex => "keywords block index",
+            MDictBlockKind::KeyBlock => "keywords block",
+            MDictBlockKind::RecordBlock => "record block",
+        })
+    }
+}
+
+/// The error type of parsing and looking up MDict files.
+///
+/// This can be converted into [`io::Error`], the original `MDictError` is kept as its inner error.
+#[derive(Debug)]
+pub enum MDictError {
+    /// An I/O operation on the underlying reader failed.
+    Io(io::Error),
+    /// The file ends before the expected number of bytes can be read.
+    Truncated { expected: u64, actual: u64 },
+    /// The checksum of the MDict header is incorrect.
+    HeaderChecksum { expected: u32, actual: u32 },
+    /// The checksum of the `index`th block of `kind` is incorrect.
+    BlockChecksum {
+        kind: MDictBlockKind,
+        index: usize,
+        expected: u32,
+        actual: u32,
+    },
+    /// The `index`th block of `kind` is compressed by an unknown method.
+    UnknownCompression {
+        kind: MDictBlockKind,
+        index: usize,
+        magic: u32,
+    },
+    /// The `index`th block of `kind` is encrypted by an unknown method.
+    UnknownEncryption {
+        kind: MDictBlockKind,
+        index: usize,
+        method: u32,
+    },
+    /// The `index`th block of `kind` can't be decompressed.
+    Decompress {
+        kind: MDictBlockKind,
+        index: usize,
+        message: String,
+    },
+    /// The `index`th block of `kind` is corrupted and dropped in the salvage mode,
+    /// see [`MDictIndex::set_salvage`](crate::MDictIndex::set_salvage).
+    DroppedBlock { kind: MDictBlockKind, index: usize },
+    /// A string can't be decoded in the encoding of the file.
+    ///
+    /// `offset` is the offset of the string in the uncompressed keyword blocks or keyword block index.
+    /// It is `None` if the string is given by the caller, see [`MDictHeader::decode_string`](crate::MDictHeader::decode_string).
+    Decode {
+        encoding: &'static str,
+        offset: Option<u64>,
+    },
+    /// The `GeneratedByEngineVersion` attribute of header is missing or not supported.
+    UnsupportedVersion(String),
+    /// The file uses a feature which is not supported by this crate.
+    Unsupported(String),
+    /// The header of keyword blocks is encrypted and no registration key is given.
+    KeyRequired,
+    /// The given registration key can't decrypt the file.
+    WrongKey,
+    /// A size or number in the file doesn't match the actual content.
+    Mismatch {
+        what: &'static str,
+        expected: u64,
+        actual: u64,
+    },
+    /// The file is not a valid MDict file.
+    Malformed(String),
+    /// A size or offset is too large for the integer type where it is kept,
+    /// such as a record block larger than `usize` on 32-bit targets.
+    Overflow { what: &'static str, value: u64 },
+}
+
+impl fmt::Display for MDictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MDictError::Io(e) => write!(f, "{}", e),
+            MDictError::Truncated { expected, actual } => write!(
+                f,
+                "Unexpected end of file: expect {} bytes but only {} bytes left",
+                expected, actual
+            ),
+            MDictError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "MDict header checksum mismatch: {:#010x} != {:#010x}",
+                actual, expected
+            ),
+            MDictError::BlockChecksum {
+                kind,
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum of {} {} mismatch: {:#010x} != {:#010x}",
+                kind, index, actual, expected
+            ),
+            MDictError::UnknownCompression { kind, index, magic } => write!(
+                f,
+                "Unknown compression {:#X} of {} {}",
+                magic, kind, index
+            ),
+            MDictError::UnknownEncryption {
+                kind,
+                index,
+                method,
+            } => write!(f, "Unknown encryption {:#X} of {} {}", method, kind, index),
+            MDictError::Decompress {
+                kind,
+                index,
+                message,
+            } => write!(f, "Failed to decompress {} {}: {}", kind, index, message),
+            MDictError::DroppedBlock { kind, index } => {
+                write!(f, "The {} {} is corrupted and dropped", kind, index)
+            }
+            MDictError::Decode {
+                encoding,
+                offset: Some(offset),
+            } => write!(f, "Cannot decode {} string at offset {}", encoding, offset),
+            MDictError::Decode {
+                encoding,
+                offset: None,
+            } => write!(f, "Cannot decode {} string", encoding),
+            MDictError::UnsupportedVersion(version) => {
+                write!(f, "Unknown or unsupported format version: {:?}", version)
+            }
+            MDictError::Unsupported(message) => f.write_str(message),
+            MDictError::KeyRequired => f.write_str(
+                "Keywords block header is encrypted, the registration key is required",
+            ),
+            MDictError::WrongKey => f.write_str("The registration key is incorrect"),
+            MDictError::Mismatch {
+                what,
+                expected,
+                actual,
+            } => write!(f, "{} mismatch: {} != {}", what, actual, expected),
+            MDictError::Malformed(message) => f.write_str(message),
+            MDictError::Overflow { what, value } => {
+                write!(f, "{} {} is out of range", what, value)
+            }
+        }
+    }
+}
+
+impl Error for MDictError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MDictError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MDictError {
+    fn from(e: io::Error) -> MDictError {
+        MDictError::Io(e)
+    }
+}
+
+impl From<MDictError> for io::Error {
+    fn from(e: MDictError) -> io::Error {
+        let kind = match e {
+            MDictError::Io(e) => return e,
+            MDictError::Truncated { .. } => ErrorKind::UnexpectedEof,
+            MDictError::UnsupportedVersion(_) | MDictError::Unsupported(_) => {
+                ErrorKind::Unsupported
+            }
+            MDictError::KeyRequired | MDictError::WrongKey => ErrorKind::PermissionDenied,
+            _ => ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+impl MDictError {
+    // A copy of this error, returned again for every record of a failed block.
+    // `io::Error` can't be cloned, so only its kind and message are copied.
+    pub(crate) fn duplicate(&self) -> MDictError {
+        match self {
+            MDictError::Io(e) => MDictError::Io(io::Error::new(e.kind(), e.to_string())),
+            MDictError::Truncated { expected, actual } => MDictError::Truncated {
+                expected: *expected,
+                actual: *actual,
+            },
+            MDictError::HeaderChecksum { expected, actual } => MDictError::HeaderChecksum {
+                expected: *expected,
+                actual: *actual,
+            },
+            MDictError::BlockChecksum {
+                kind,
+                index,
+                expected,
+                actual,
+            } => MDictError::BlockChecksum {
+                kind: *kind,
+                index: *index,
+                expected: *expected,
+                actual: *actual,
+            },
+            MDictError::UnknownCompression { kind, index, magic } => {
+                MDictError::UnknownCompression {
+                    kind: *kind,
+                    index: *index,
+                    magic: *magic,
+                }
+            }
+            MDictError::UnknownEncryption {
+                kind,
+                index,
+                method,
+            } => MDictError::UnknownEncryption {
+                kind: *kind,
+                index: *index,
+                method: *method,
+            },
+            MDictError::Decompress {
+                kind,
+                index,
+                message,
+            } => MDictError::Decompress {
+                kind: *kind,
+                index: *index,
+                message: message.clone(),
+            },
+            MDictError::DroppedBlock { kind, index } => MDictError::DroppedBlock {
+                kind: *kind,
+                index: *index,
+            },
+            MDictError::Decode { encoding, offset } => MDictError::Decode {
+                encoding,
+                offset: *offset,
+            },
+            MDictError::UnsupportedVersion(version) => {
+                MDictError::UnsupportedVersion(version.clone())
+            }
+            MDictError::Unsupported(message) => MDictError::Unsupported(message.clone()),
+            MDictError::KeyRequired => MDictError::KeyRequired,
+            MDictError::WrongKey => MDictError::WrongKey,
+            MDictError::Mismatch {
+                what,
+                expected,
+                actual,
+            } => MDictError::Mismatch {
+                what,
+                expected: *expected,
+                actual: *actual,
+            },
+            MDictError::Malformed(message) => MDictError::Malformed(message.clone()),
+            MDictError::Overflow { what, value } => MDictError::Overflow {
+                what,
+                value: *value,
+            },
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// Layout of blocks of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{read_len, MDictCompression, MDictError, MDictFormatVersion, MDictIndex, MDictResult};
+use bytes::Buf;
+use std::fmt;
+use std::io::{self, prelude::*};
+
+/// How a block is compressed and encrypted, stored in the first 4 bytes of the block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MDictBlockCompression {
+    /// The compression method, 0 for none, 1 for LZO and 2 for zlib
+    pub magic: u32,
+    /// The encryption method of v3, 0 for none. Blocks of v1 and v2 are never encrypted.
+    pub encryption: u32,
+}
+
+impl MDictBlockCompression {
+    /// The compression method, `None` if it is unknown.
+    pub fn method(&self) -> Option<MDictCompression> {
+        match self.magic {
+            0x0 => Some(MDictCompression::None),
+            0x1 => Some(MDictCompression::Lzo),
+            0x2 => Some(MDictCompression::Zlib),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MDictBlockCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.method() {
+            Some(MDictCompression::None) => f.write_str("uncompressed")?,
+            Some(MDictCompression::Lzo) => f.write_str("lzo")?,
+            Some(MDictCompression::Zlib) => f.write_str("zlib")?,
+            None => write!(f, "unknown compression {:#X}", self.magic)?,
+        }
+        if self.encryption != 0 {
+            write!(f, ", encryption {:#X}", self.encryption)?;
+        }
+        Ok(())
+    }
+}
+
+/// A keyword block in [`MDictLayout`].
+#[derive(Clone, Debug)]
+pub struct MDictKeyBlockLayout {
+    /// Number of keywords in this keyword block
+    pub block_entries: u64,
+    /// The first keyword in this keyword block
+    pub first_word: String,
+    /// The last keyword in this keyword block
+    pub last_word: String,
+    /// Offset of this keyword block from the start of the file
+    pub offset: u64,
+    /// Compressed size of this keyword block
+    pub comp_size: u64,
+    /// Uncompressed size of this keyword block
+    pub uncomp_size: u64,
+    /// Compression of this keyword block
+    pub compression: MDictBlockCompression,
+}
+
+/// A record block in [`MDictLayout`].
+#[derive(Clone, Copy, Debug)]
+pub struct MDictRecordBlockLayout {
+    /// Offset of this record block from the start of the file
+    pub offset: u64,
+    /// Compressed size of this record block
+    pub comp_size: u64,
+    /// Uncompressed size of this record block
+    pub uncomp_size: u64,
+    /// Compression of this record block
+    pub compression: MDictBlockCompression,
+}
+
+/// The blocks of a file, returned by [`MDictIndex::layout`].
+#[derive(Clone, Debug)]
+pub struct MDictLayout {
+    /// Format version of the file
+    pub version: MDictFormatVersion,
+    /// The keyword blocks in the order of the file
+    pub key_blocks: Vec<MDictKeyBlockLayout>,
+    /// The record blocks in the order of the file
+    pub record_blocks: Vec<MDictRecordBlockLayout>,
+}
+
+impl<R: Read + Seek> MDictIndex<R> {
+    /// List the keyword blocks and record blocks of this file, with their sizes and compression.
+    ///
+    /// This reads the index of blocks like [`MDictIndex::make_key_index`] and the first 4 bytes
+    /// of every block, the blocks are not decompressed. It is meant for tools which inspect
+    /// or repack files, for example to find files with blocks too large for fast lookups.
+    ///
+    /// # Error
+    ///
+    /// This function returns [`MDictError`] like [`MDictIndex::make_key_index`] do,
+    /// or if a block is too small to contain its compression method.
+    pub fn layout(&mut self) -> MDictResult<MDictLayout> {
+        let (key_blocks, record_blocks) = self.make_key_index()?;
+        let mut layout = MDictLayout {
+            version: self.header.version(),
+            key_blocks: Vec::with_capacity(key_blocks.len()),
+            record_blocks: Vec::with_capacity(record_blocks.len()),
+        };
+        for block in key_blocks {
+            let compression = self.read_block_compression(block.offset, block.comp_size)?;
+            layout.key_blocks.push(MDictKeyBlockLayout {
+                block_entries: block.block_entries,
+                first_word: block.first_word,
+                last_word: block.last_word,
+                offset: block.offset,
+                comp_size: block.comp_size,
+                uncomp_size: block.uncomp_size,
+                compression,
+            });
+        }
+        for block in record_blocks {
+            let compression = self.read_block_compression(block.offset, block.comp_size)?;
+            layout.record_blocks.push(MDictRecordBlockLayout {
+                offset: block.offset,
+                comp_size: block.comp_size,
+                uncomp_size: block.uncomp_size,
+                compression,
+            });
+        }
+        Ok(layout)
+    }
+
+    // Read the compression and encryption method from the start of a block
+    fn read_block_compression(
+        &mut self,
+        offset: u64,
+        comp_size: u64,
+    ) -> MDictResult<MDictBlockCompression> {
+        if comp_size < 4 {
+            return Err(MDictError::Malformed(format!(
+                "Size of block is too small: {}",
+                comp_size
+            )));
+        }
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        let info = read_len(&mut self.file, 4)?.as_slice().get_u32_le();
+        Ok(match self.header.version() {
+            MDictFormatVersion::V3 => MDictBlockCompression {
+                magic: info & 0xf,
+                encryption: (info >> 4) & 0xf,
+            },
+            _ => MDictBlockCompression {
+                magic: info,
+                encryption: 0,
+            },
+        })
+    }
+}
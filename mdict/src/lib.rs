@@ -34,15 +34,27 @@ or `b_dict.mdx`, `b_dict.mdd` and `b_dict.1.mdd`.
 
 This is a low level crate for writing indexer of MDict Dictionary.
 This crate can parse a mdx or mdx file, dump the keywords and record indexes and lookup the
-record content of gaving record index.
+record content of gaving record index. It can also write a new mdx or mdd file by [`MDictWriter`].
 A indexer will provide high level API to lookup keyword and resource in a MDict Dictionary.
 
+The API is blocking. The "async" crate feature adds asynchronous versions of the functions
+which do I/O, such as `MDictHeader::new_async` and `lookup_async`, along with the blocking ones.
+
+A file can also be read from a [`MDictSource`], such as a buffer in memory or a part of a larger
+file, by [`MDictSourceReader`] and `lookup_source`.
+
+The "mmap" crate feature adds `MDictMmap` to map the files into memory, and the record blocks
+are sliced from the mapping by `lookup_slice` rather than read from the file.
+
+The "parallel" crate feature decompresses and decodes keywords blocks, and checks record blocks,
+across the thread pool of [rayon](https://docs.rs/rayon). The results are the same as without it.
+
 For more information of the format of MDict file, see [mdict-analysis](https://bitbucket.org/xwang/mdict-analysis/)
 and [fileformat](https://github.com/zhansliu/writemdict/blob/master/fileformat.md)
 
 ## Example
 
-```
+```no_run
 use std::fs::File;
 use std::collections::HashMap;
 use mdict::*;
@@ -55,7 +67,7 @@ fn main() -> std::io::Result<()> {
     let key_map: HashMap<String, MDictRecordIndex> = keys.into_iter().collect();
     match key_map.get("rust") {
         Some(idx) => {
-            let record = lookup(file, idx, &blocks[idx.block as usize])?;
+            let record = lookup(file, idx, &blocks)?;
             let record = header.decode_string(record)?;
             println!("{}", record);
         }
@@ -69,20 +81,56 @@ fn main() -> std::io::Result<()> {
 */
 
 use bytes::{Buf, Bytes};
-use encoding_rs::{Encoding, UTF_16LE};
-use log::info;
-use miniz_oxide::inflate::decompress_to_vec_zlib;
+use encoding_rs::{Encoding, UTF_16LE, UTF_8};
+use log::{info, warn};
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+use once_cell::sync::Lazy;
 use regex::Regex;
-use ripemd128::{Digest, Ripemd128};
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
-use std::io::{self, prelude::*, Error, ErrorKind};
+use std::io::{self, prelude::*};
+use std::ops::Range;
+
+mod cache;
+mod crypto;
+mod decode;
+mod error;
+mod layout;
+mod lzo;
+mod metadata;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod records;
+mod source;
+mod verify;
+mod writer;
+
+use crypto::{fast_decrypt, ripemd128, salsa20_8};
+use decode::{decode, encoding_for_label, DecodeCounters, Decoded};
+pub use cache::{MDictBlockCache, MDictCacheStats};
+pub use crypto::MDictKey;
+pub use decode::{MDictDecodePolicy, MDictDecodeStats};
+pub use error::{MDictBlockKind, MDictError, MDictResult};
+pub use layout::{MDictBlockCompression, MDictKeyBlockLayout, MDictLayout, MDictRecordBlockLayout};
+pub use metadata::{
+    MDictDate, MDictMetadata, MDictRecordFormat, MDictRegisterBy, MDictStyle, MDictStyleSheet,
+};
+#[cfg(feature = "mmap")]
+pub use mmap::MDictMmap;
+pub use records::MDictRecords;
+pub use source::{MDictFileSource, MDictSource, MDictSourceReader, MDictSubSource};
+pub use verify::{MDictBadBlock, MDictVerifyReport};
+pub use writer::{MDictCompression, MDictWriter};
+
+// Compressed size of blocks read at a time by `MDictIndex::check_record_blocks` and `MDictIndex::verify`
+const CHECK_BATCH_SIZE: u64 = 64 << 20;
 
 // The `Encrypted` field of MDict file header.
 // The possible is 0, 1, 2, 3.
 //
 // If the lower bit is set, indicates that the header of keyword block is encrypted.
-// This is checked in `read_keys` and passby in `search_key_block_index_size`
+// This is checked in `read_keys`, decrypted by the registration key of user,
+// or passby in `search_key_block_index_size` if the key is not given.
 //
 // If the upper bit is set, indicates that the index of keyword block is encrypted.
 // This is checked in `read_keys` and decrypted in `decrypt_key_block_index`
@@ -91,16 +139,13 @@ struct MDictEncryptionMode(u8);
 
 // Prase from attribute `Encrypted` of MDict header
 impl TryFrom<&str> for MDictEncryptionMode {
-    type Error = io::Error;
-    fn try_from(s: &str) -> io::Result<MDictEncryptionMode> {
+    type Error = MDictError;
+    fn try_from(s: &str) -> MDictResult<MDictEncryptionMode> {
         let mode = match s {
             "No" | "" => 0,
             "Yes" => 1,
             _ => s.parse().map_err(|_| {
-                Error::new(
-                    ErrorKind::InvalidData,
-                    format!("Invalid or unknown encryption mode: {}", s),
-                )
+                MDictError::Malformed(format!("Invalid or unknown encryption mode: {}", s))
             })?,
         };
         Ok(MDictEncryptionMode(mode))
@@ -121,24 +166,30 @@ impl MDictEncryptionMode {
 /// There are two difference between `mdx` and `mdd`:
 ///
 /// 1. The encoding of `mdd`'s keyword is always UTF-16LE, while
-/// The encoding of `mdx` is specified in header's `Encoding` feild.
+///    The encoding of `mdx` is specified in header's `Encoding` feild.
 ///
 /// 2. The record of `mdx` is text or HTML, while the record of `mdd`
-/// is compressed file.
-#[derive(Clone, Copy, Debug)]
+///    is compressed file.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MDictMode {
     Mdx,
     Mdd,
 }
 
+/// Version of the file format.
+///
 /// There are some differences in file format between v1.2 and v2
 /// v1 use 32 bit and 8 bit integer but v2 use 64 bit and 16 bit integer
 /// to represent offset/size and length of string.
 /// v2 also have a extra field in the header of key block
+///
+/// v3 is used by MDict 3.x, its header is encoded in UTF-8 and the content is split
+/// into tagged sections, every block carries its own compression and encryption method.
 #[derive(Clone, Copy, Debug, PartialEq)]
-enum MDictFormatVersion {
+pub enum MDictFormatVersion {
     V1,
     V2,
+    V3,
 }
 
 // Prase from attribute `GeneratedByEngineVersion` of MDict header
@@ -147,8 +198,10 @@ impl From<&str> for MDictFormatVersion {
         let version: f32 = s.parse().unwrap_or(1.0);
         if version < 2.0 {
             MDictFormatVersion::V1
-        } else {
+        } else if version < 3.0 {
             MDictFormatVersion::V2
+        } else {
+            MDictFormatVersion::V3
         }
     }
 }
@@ -159,8 +212,11 @@ impl From<&str> for MDictFormatVersion {
 /// Its attributes contains useful information such as "Title", "Description" and "CreationDate".
 pub struct MDictHeader {
     encoding: &'static Encoding,
+    decode_policy: MDictDecodePolicy,
+    decode_counters: DecodeCounters,
     encryption_mode: MDictEncryptionMode,
     version: MDictFormatVersion,
+    metadata: MDictMetadata,
     /// Attributes of this header.
     pub attrs: HashMap<String, String>,
     /// This MDict file is a mdx or mdd file.
@@ -176,29 +232,60 @@ impl MDictHeader {
     ///
     /// # Error
     ///
-    /// This function returns [`io::Error`] if any io operations failed.
+    /// This function returns [`MDictError::Io`] if any io operations failed.
     ///
-    /// [`io::Error`] with [`ErrorKind::InvalidData`] will return if the header is invalid, checksum is incorrect
-    /// or can't be decoded to UTF-8.
-    pub fn new<R: Read + Seek>(mut reader: R, mode: MDictMode) -> io::Result<MDictHeader> {
+    /// [`MDictError::HeaderChecksum`] will return if checksum is incorrect, [`MDictError::Decode`]
+    /// if the header can't be decoded to UTF-8, and [`MDictError::UnsupportedVersion`] if the
+    /// header has no format version.
+    pub fn new<R: Read + Seek>(mut reader: R, mode: MDictMode) -> MDictResult<MDictHeader> {
         reader.seek(io::SeekFrom::Start(0))?;
         let size = read_len(&mut reader, 4)?.as_slice().get_u32() as usize;
         let header_buf = read_len(&mut reader, size)?;
         let checksum = read_len(&mut reader, 4)?.as_slice().get_u32_le();
-        let calc_checksum = adler::adler32_slice(&header_buf);
-        check_eq(calc_checksum, checksum, "MDict header checksum")?;
-        // two 0x0 in the end of the content
-        let attrs = Self::parse_header(&header_buf[0..size - 2])?;
+        Self::from_bytes(&header_buf, checksum, mode)
+    }
+
+    #[cfg(feature = "async")]
+    /// Prase MDict header from asynchronous `reader`.
+    ///
+    /// This is the asynchronous version of [`MDictHeader::new`], and has the same behavior and errors.
+    pub async fn new_async<AR>(mut reader: AR, mode: MDictMode) -> MDictResult<MDictHeader>
+    where
+        AR: AsyncReadExt + AsyncSeekExt + Unpin,
+    {
+        reader.seek(io::SeekFrom::Start(0)).await?;
+        let size = read_len_async(&mut reader, 4).await?.as_slice().get_u32() as usize;
+        let header_buf = read_len_async(&mut reader, size).await?;
+        let checksum = read_len_async(&mut reader, 4).await?.as_slice().get_u32_le();
+        Self::from_bytes(&header_buf, checksum, mode)
+    }
+
+    // Check and parse the XML tag of header
+    fn from_bytes(header_buf: &[u8], checksum: u32, mode: MDictMode) -> MDictResult<MDictHeader> {
+        let calc_checksum = adler::adler32_slice(header_buf);
+        if calc_checksum != checksum {
+            return Err(MDictError::HeaderChecksum {
+                expected: checksum,
+                actual: calc_checksum,
+            });
+        }
+        let attrs = Self::parse_header(header_buf)?;
         info!("MDict header: {:#?}", attrs);
+        let version: MDictFormatVersion = attrs
+            .get("GeneratedByEngineVersion")
+            .map(|e| e.as_str().into())
+            .ok_or_else(|| MDictError::UnsupportedVersion(String::new()))?;
         let encoding = match mode {
-            MDictMode::Mdx => Encoding::for_label(
-                attrs
-                    .get("Encoding")
-                    .map(|s| s.as_str())
-                    .unwrap_or("UTF-16LE")
-                    .as_bytes(),
-            )
-            .unwrap_or(encoding_rs::UTF_16LE),
+            // v3 always use UTF-8
+            _ if version == MDictFormatVersion::V3 => encoding_rs::UTF_8,
+            MDictMode::Mdx => match attrs.get("Encoding").map(|s| s.trim()) {
+                // MDict uses UTF-16LE if the encoding is not given
+                None | Some("") => encoding_rs::UTF_16LE,
+                Some(label) => encoding_for_label(label).unwrap_or_else(|| {
+                    warn!("Unknown encoding {:?}, use UTF-16LE", label);
+                    encoding_rs::UTF_16LE
+                }),
+            },
             MDictMode::Mdd => encoding_rs::UTF_16LE,
         };
         info!("Using encoding: {}", encoding.name());
@@ -206,47 +293,41 @@ impl MDictHeader {
             Some(e) => e.as_str().try_into()?,
             None => MDictEncryptionMode::none(),
         };
-        let version = attrs
-            .get("GeneratedByEngineVersion")
-            .map(|e| e.as_str().into())
-            .ok_or_else(|| {
-                Error::new(
-                    ErrorKind::InvalidData,
-                    format!(
-                        "Unknown format version: {:?}",
-                        attrs.get("GeneratedByEngineVersion")
-                    ),
-                )
-            })?;
-        // TODO: deal with Title Description StyleSheet Compact Left2Right
-        // StyleSheet format:
-        // 3 lines per StyleSheet
-        //   style_number # 1-255
-        //   style_begin  # or ''
-        //   style_end    # or ''
-        // {'number' : ('style_begin', 'style_end')}
-        // Title: meaningless title: "Title (No HTML code allowed)"
+        let metadata = MDictMetadata::new(&attrs);
         Ok(MDictHeader {
             encoding,
+            decode_policy: MDictDecodePolicy::Strict,
+            decode_counters: DecodeCounters::default(),
             encryption_mode,
             version,
+            metadata,
             attrs,
             mode,
         })
     }
 
     // parse the original XML tag from header and decode them into UTF-8
-    fn parse_header(header_buf: &[u8]) -> io::Result<HashMap<String, String>> {
-        let (cow, _encoding_used, had_errors) = UTF_16LE.decode(&header_buf);
+    fn parse_header(header_buf: &[u8]) -> MDictResult<HashMap<String, String>> {
+        // The XML tag is encoded in UTF-16LE with two 0x0 in the end before v3,
+        // and in UTF-8 with one 0x0 in the end since v3.
+        let (encoding, null_term) = if header_buf.get(1) == Some(&0) {
+            (UTF_16LE, 2)
+        } else {
+            (UTF_8, 1)
+        };
+        let header_buf = &header_buf[..header_buf.len().saturating_sub(null_term)];
+        let (cow, _encoding_used, had_errors) = encoding.decode(header_buf);
         if had_errors {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "Cannot decode MDict Header to UTF-8",
-            ));
+            // the XML tag starts after its length
+            return Err(MDictError::Decode {
+                encoding: encoding.name(),
+                offset: Some(4),
+            });
         }
-        let re = Regex::new(r#"(\w+)="([^"]*?)""#).unwrap();
+        // compiled once rather than for every file
+        static ATTRIBUTE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(\w+)="([^"]*?)""#).unwrap());
         let mut result = HashMap::new();
-        for kv in re.captures_iter(&cow) {
+        for kv in ATTRIBUTE.captures_iter(&cow) {
             let key = kv[1].to_string();
             let val = html_escape::decode_html_entities(&kv[2]);
             result.insert(key, val.to_string());
@@ -256,37 +337,168 @@ impl MDictHeader {
 
     // The code unit size is the smallest size of char (in bytes) in this encoding
     fn unit_size(&self) -> usize {
-        let name = self.encoding.name().to_ascii_lowercase();
-        // Anyone still using BIG-5 ?
-        if name.contains("utf-16") || name.contains("big5") {
-            2
-        } else {
-            1
-        }
+        encoding_unit_size(self.encoding)
     }
 
     /// Decode bytes into UTF-8 based on the encoding of this header.
     ///
+    /// The invalid strings are decoded by the [`MDictDecodePolicy`] of this header,
+    /// and counted as records in [`MDictHeader::decode_stats`].
+    ///
     /// # Error
     ///
-    /// [`io::Error`] with [`ErrorKind::InvalidData`] will return if src can't be decoded to UTF-8.
-    pub fn decode_string(&self, src: Bytes) -> io::Result<String> {
-        let (cow, _encoding_used, had_errors) = self.encoding.decode(&src);
-        if had_errors {
-            Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("{} ({:X?}) cannot decode to UTF-8", cow, src),
-            ))
+    /// [`MDictError::Decode`] will return if src can't be decoded to UTF-8 by the policy.
+    pub fn decode_string(&self, src: Bytes) -> MDictResult<String> {
+        let (string, decoded) = self.decode(src, None)?;
+        self.decode_counters.count_record(decoded);
+        Ok(string)
+    }
+
+    /// Set how to decode the keywords and records which are invalid in the encoding of this file.
+    ///
+    /// The policy is [`MDictDecodePolicy::Strict`] by default.
+    pub fn set_decode_policy(&mut self, policy: MDictDecodePolicy) {
+        self.decode_policy = policy;
+    }
+
+    /// The policy to decode invalid strings, see [`MDictHeader::set_decode_policy`].
+    pub fn decode_policy(&self) -> &MDictDecodePolicy {
+        &self.decode_policy
+    }
+
+    /// The counters of keywords and records which are invalid in the encoding of this file,
+    /// and decoded by the policy.
+    ///
+    /// Keywords are counted when the keyword blocks are decoded, so a keyword may be counted again
+    /// when its block is read again by [`read_key_block`].
+    pub fn decode_stats(&self) -> MDictDecodeStats {
+        self.decode_counters.stats()
+    }
+
+    /// Normalize a keyword for matching, in the same way as MDict.
+    ///
+    /// Keywords are matched case-insensitively unless `KeyCaseSensitive` is set, and punctuation
+    /// and spaces are ignored if `StripKey` is set. A keyword made up of punctuation only is kept
+    /// as it is, so that it can still be found.
+    pub fn normalize_key(&self, key: &str) -> String {
+        normalize_key(
+            key,
+            self.metadata.key_case_sensitive,
+            self.metadata.strip_key,
+        )
+    }
+
+    /// Decode a record of mdx file into UTF-8, and expand its style markers if the header
+    /// asks for it, see [`MDictStyleSheet::expand`].
+    ///
+    /// # Error
+    ///
+    /// [`MDictError::Decode`] will return if src can't be decoded to UTF-8.
+    pub fn decode_record(&self, src: Bytes) -> MDictResult<String> {
+        let record = self.decode_string(src)?;
+        let stylesheet = &self.metadata.stylesheet;
+        if self.metadata.compact && !stylesheet.is_empty() {
+            Ok(stylesheet.expand(&record))
+        } else {
+            Ok(record)
+        }
+    }
+
+    // Decode keywords, the offset is reported if the keyword can't be decoded
+    fn decode(&self, src: Bytes, offset: Option<u64>) -> MDictResult<(String, Decoded)> {
+        decode(self.encoding, &self.decode_policy, &src).ok_or(MDictError::Decode {
+            encoding: self.encoding.name(),
+            offset,
+        })
+    }
+
+    // get u32 in v1, u64 in v2 and v3
+    fn read_int<B: Buf>(&self, buf: &mut B) -> MDictResult<u64> {
+        match self.version {
+            MDictFormatVersion::V1 => {
+                check_len(buf.remaining(), 4)?;
+                Ok(buf.get_u32() as u64)
+            }
+            _ => {
+                check_len(buf.remaining(), 8)?;
+                Ok(buf.get_u64())
+            }
+        }
+    }
+
+    // get u8 in v1, u16 in v2 and v3
+    fn read_short<B: Buf>(&self, buf: &mut B) -> MDictResult<u16> {
+        match self.version {
+            MDictFormatVersion::V1 => {
+                check_len(buf.remaining(), 1)?;
+                Ok(buf.get_u8() as u16)
+            }
+            _ => {
+                check_len(buf.remaining(), 2)?;
+                Ok(buf.get_u16())
+            }
+        }
+    }
+
+    // Decode keywords and offsets of their records in a keyword block
+    fn decode_key_block(
+        &self,
+        compressed: Bytes,
+        index: &MDictKeyBlockIndex,
+        block: usize,
+    ) -> MDictResult<Vec<(String, u64)>> {
+        // basically strlen+strcpy, but support 2 bytes encoding like UTF-16LE
+        let split_null = if self.unit_size() == 2 {
+            split_dual_null
         } else {
-            Ok(String::from(cow))
+            split_single_null
+        };
+        let mut uncompressed = uncompress(
+            &compressed,
+            index.uncomp_size as usize,
+            self.version,
+            MDictBlockKind::KeyBlock,
+            block,
+        )?;
+        check_eq(
+            uncompressed.len() as u64,
+            index.uncomp_size,
+            "Size of uncompressed content",
+        )?;
+        let mut words = Vec::new();
+        while !uncompressed.is_empty() {
+            let offset = self.read_int(&mut uncompressed)?;
+            let string_offset = index
+                .uncomp_offset
+                .saturating_add(index.uncomp_size - uncompressed.len() as u64);
+            let string_encoded = split_null(&mut uncompressed)?;
+            let (string_decoded, decoded) = self.decode(string_encoded, Some(string_offset))?;
+            self.decode_counters.count_key(decoded);
+            words.push((string_decoded, offset));
         }
+        // the number of entries of v3 is only known after decoding
+        if self.version != MDictFormatVersion::V3 {
+            check_eq(
+                words.len() as u64,
+                index.block_entries,
+                "Number of entries in keywords block",
+            )?;
+        }
+        Ok(words)
     }
 
     #[inline]
-    fn version(&self) -> MDictFormatVersion {
+    /// get format version of this header.
+    pub fn version(&self) -> MDictFormatVersion {
         self.version
     }
 
+    #[inline]
+    /// get typed metadata parsed from the attributes of this header.
+    pub fn metadata(&self) -> &MDictMetadata {
+        &self.metadata
+    }
+
     #[inline]
     /// get mode of This header.
     pub fn mode(&self) -> MDictMode {
@@ -313,28 +525,38 @@ pub struct MDictIndex<R: Read + Seek> {
     file: io::BufReader<R>,
     key_block_offset: u64,
     header: MDictHeader,
+    key: Option<MDictKey>,
+    salvage: bool,
 }
 
-/// A keywords block
+/// Index to a compressed block which contains keywords
 ///
-/// Maybe the original auther of MDict want to use this as a index,
-/// But a index only using `first_word` and `last_word` is inefficient even through the keywords is sorted.
-/// So we only store this internally.
-#[derive(Debug)]
-struct MDictKeyBlockIndex {
+/// The keywords are sorted, so the `first_word` and `last_word` of blocks can be used to find
+/// the block which may contain a keyword, without decoding all keywords. See [`MDictIndex::make_key_index`].
+#[derive(Clone, Debug)]
+pub struct MDictKeyBlockIndex {
     /// Number of keywords in this keyword block
-    block_entries: u64,
-    /// The first keyword in thos keyword block
-    first_word: String,
-    /// The last keyword in thos keyword block
-    last_word: String,
+    pub block_entries: u64,
+    /// The first keyword in this keyword block
+    pub first_word: String,
+    /// The last keyword in this keyword block
+    pub last_word: String,
+    /// Offset of this keyword block from the start of the file
+    pub offset: u64,
     /// Compressed size of this keyword block
-    comp_size: u64,
+    pub comp_size: u64,
     /// Uncompressed size of this keyword block
-    uncomp_size: u64,
-    /// Words list, keyword and offset of its record
-    /// from the begin of totally uncompressed record blcoks
-    words: Vec<(String, u64)>,
+    pub uncomp_size: u64,
+    /// Offset of the uncompressed content of this keyword block from the start of
+    /// the uncompressed keyword blocks
+    pub uncomp_offset: u64,
+}
+
+// Offsets of the content of data sections in a v3 file
+#[derive(Default)]
+struct MDictSections {
+    key_data: Option<u64>,
+    record_data: Option<u64>,
 }
 
 /// Index to a compressed block which contains records
@@ -344,19 +566,33 @@ pub struct MDictRecordBlockIndex {
     pub offset: u64,
     /// Compressed size of this record block
     pub comp_size: u64,
+    /// Uncompressed size of this record block
+    pub uncomp_size: u64,
+    /// Format version of the file containing this record block
+    pub version: MDictFormatVersion,
+    /// Whether this record block is corrupted and dropped in the salvage mode,
+    /// see [`MDictIndex::set_salvage`]
+    pub dropped: bool,
 }
 
 /// Index to a record
-#[derive(Copy, Clone, Debug)]
+///
+/// A record may be longer than the rest of the block where it starts,
+/// then it continues from the start of the following blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct MDictRecordIndex {
-    /// which block this record belongs to
-    pub block: u32,
+    /// which block this record starts in
+    pub block: u64,
     /// the offset of this record from the start of the uncompressed block
-    pub offset: u32,
+    pub offset: u64,
     /// length of this record
-    pub len: u32,
+    pub len: u64,
 }
 
+/// The index built by [`MDictIndex::make_index`]: the record blocks, and the keywords with
+/// the indexes of their records.
+pub type MDictRecordIndexes = (Vec<MDictRecordBlockIndex>, Vec<(String, MDictRecordIndex)>);
+
 impl<R: Read + Seek> MDictIndex<R> {
     /// Build a new `MDictIndex`.
     ///
@@ -367,44 +603,108 @@ impl<R: Read + Seek> MDictIndex<R> {
     ///
     /// # Error
     ///
-    /// This function returns [`io::Error`] if any io operations failed just like [`MDictHeader`] do.
-    pub fn new(reader: R, mode: MDictMode) -> io::Result<MDictIndex<R>> {
+    /// This function returns [`MDictError`] if any io operations failed just like [`MDictHeader`] do.
+    pub fn new(reader: R, mode: MDictMode) -> MDictResult<MDictIndex<R>> {
         let mut file = io::BufReader::with_capacity(0x10000, reader);
         let header = MDictHeader::new(&mut file, mode)?;
-        let key_block_offset = file.seek(io::SeekFrom::Current(0))?;
+        let key_block_offset = file.stream_position()?;
         Ok(MDictIndex {
             file,
             key_block_offset,
             header,
+            key: None,
+            salvage: false,
         })
     }
 
-    /// Read the keywords block.
-    fn read_keys(&mut self) -> io::Result<Vec<MDictKeyBlockIndex>> {
-        let unencrypted = self.header.encryption_mode.mode() & 0x1 == 0x0;
+    /// Build a new `MDictIndex` of a registration-locked MDict file.
+    ///
+    /// The header of keyword block of such file is encrypted, and the `key` is derived from
+    /// the registration code of user, see [`MDictKey`]. Without the key, [`MDictIndex::new`] can
+    /// still try to guess the layout of the keyword block, but it may fail.
+    ///
+    /// # Error
+    ///
+    /// This function returns [`MDictError`] like [`MDictIndex::new`] do.
+    /// The key is checked in [`MDictIndex::make_index`].
+    pub fn with_key(reader: R, mode: MDictMode, key: MDictKey) -> MDictResult<MDictIndex<R>> {
+        let mut index = Self::new(reader, mode)?;
+        index.key = Some(key);
+        Ok(index)
+    }
+
+    /// Set how to decode the keywords and records which are invalid in the encoding of this file,
+    /// instead of failing the whole keyword block or record.
+    ///
+    /// The policy is kept by the header, see [`MDictHeader::set_decode_policy`]. The numbers of
+    /// keywords and records decoded by the policy are counted by [`MDictHeader::decode_stats`].
+    pub fn set_decode_policy(&mut self, policy: MDictDecodePolicy) {
+        self.header.set_decode_policy(policy);
+    }
+
+    /// Skip the corrupted keyword blocks and record blocks in [`MDictIndex::make_index`],
+    /// rather than failing the whole file.
+    ///
+    /// The keywords of a corrupted keyword block are lost, their range is logged. So is the
+    /// keyword before the block, whose record would run over the lost records. The keywords
+    /// whose records are in a corrupted record block are kept, the block is marked as `dropped`
+    /// and looking up their records fails with [`MDictError::DroppedBlock`]. The header and
+    /// the index of blocks must still be readable. See [`MDictIndex::verify`] to find the
+    /// corrupted blocks without building the index.
+    pub fn set_salvage(&mut self, salvage: bool) {
+        self.salvage = salvage;
+    }
+
+    /// The header of this file.
+    pub fn header(&self) -> &MDictHeader {
+        &self.header
+    }
+
+    /// Read the index of keywords blocks, the reader stops at the start of keywords blocks.
+    fn read_keys(&mut self) -> MDictResult<Vec<MDictKeyBlockIndex>> {
+        let encrypted = self.header.encryption_mode.mode() & 0x1 != 0x0;
         let block_size = match self.header.version() {
             MDictFormatVersion::V1 => 4 * 4,
-            MDictFormatVersion::V2 => 5 * 8,
+            _ => 5 * 8,
         };
-        let key_block_header = read_len(&mut self.file, block_size)?;
+        let mut key_block_header = read_len(&mut self.file, block_size)?;
+        if let (true, Some(key)) = (encrypted, &self.key) {
+            salsa20_8(&mut key_block_header, key.as_bytes());
+        }
+        let unencrypted = !encrypted || self.key.is_some();
         if self.header.version() == MDictFormatVersion::V2 {
             let checksum = read_len(&mut self.file, 4)?.as_slice().get_u32();
             if unencrypted {
                 let calc_checksum = adler::adler32_slice(&key_block_header);
-                check_eq(calc_checksum, checksum, "Keywords block header checksum")?;
+                if encrypted && calc_checksum != checksum {
+                    return Err(MDictError::WrongKey);
+                }
+                check_checksum(calc_checksum, checksum, MDictBlockKind::KeyBlockHeader, 0)?;
             }
         }
         // This closure will map those 5 number to None if header of key block is encrypted.
         let opt = |x| if unencrypted { Some(x) } else { None };
         let mut reader = key_block_header.as_slice();
-        let key_block_num = opt(self.read_int(&mut reader));
-        let entries_num = opt(self.read_int(&mut reader));
+        let key_block_num = opt(self.header.read_int(&mut reader)?);
+        let entries_num = opt(self.header.read_int(&mut reader)?);
         let key_block_index_decomp_size = match self.header.version() {
             MDictFormatVersion::V1 => None,
-            MDictFormatVersion::V2 => Some(opt(self.read_int(&mut reader))),
+            _ => Some(opt(self.header.read_int(&mut reader)?)),
         };
-        let key_block_index_size = opt(self.read_int(&mut reader));
-        let key_block_size = opt(self.read_int(&mut reader));
+        let key_block_index_size = opt(self.header.read_int(&mut reader)?);
+        let key_block_size = opt(self.header.read_int(&mut reader)?);
+        if encrypted && unencrypted {
+            // v1 has no checksum, so check whether the sizes are meaningful
+            let pos = self.file.stream_position()?;
+            let len = self.file.seek(io::SeekFrom::End(0))?;
+            self.file.seek(io::SeekFrom::Start(pos))?;
+            let size = key_block_index_size
+                .unwrap_or(0)
+                .saturating_add(key_block_size.unwrap_or(0));
+            if size > len.saturating_sub(pos) {
+                return Err(MDictError::WrongKey);
+            }
+        }
         info!("number of entries: {:?}", entries_num);
         let now = std::time::Instant::now();
         let key_block_index_buf = match key_block_index_size {
@@ -417,11 +717,19 @@ impl<R: Read + Seek> MDictIndex<R> {
             // v2
             Some(decmp_size) => {
                 let key_block_index_buf = if self.header.encryption_mode.mode() & 0x2 != 0 {
-                    self.decrypt_key_block_index(key_block_index_buf)
+                    self.decrypt_key_block_index(key_block_index_buf)?
                 } else {
                     key_block_index_buf
                 };
-                let block = uncompress(key_block_index_buf.into()).unwrap();
+                // the size is unknown if the header of keyword block is encrypted
+                let size = decmp_size.map_or(usize::MAX, |s| s as usize);
+                let block = uncompress(
+                    &key_block_index_buf,
+                    size,
+                    self.header.version(),
+                    MDictBlockKind::KeyBlockIndex,
+                    0,
+                )?;
                 check_option_eq(
                     block.len() as u64,
                     decmp_size,
@@ -436,34 +744,36 @@ impl<R: Read + Seek> MDictIndex<R> {
             key_block_num,
             "Number of keyword blocks",
         )?;
-        let entries_calc: u64 = key_block_index.iter().map(|i| i.block_entries).sum();
+        let entries_calc = sum(key_block_index.iter().map(|i| i.block_entries));
         check_option_eq(
             entries_calc,
             entries_num,
             "Number entries in keywords block index",
         )?;
-        let key_block_size_calc: u64 = key_block_index.iter().map(|i| i.comp_size).sum();
+        let key_block_size_calc = sum(key_block_index.iter().map(|i| i.comp_size));
         check_option_eq(
             key_block_size_calc,
             key_block_size,
             "Size of keyword blocks",
         )?;
         info!("Decode keywords block index in {:?}", now.elapsed());
-        let now = std::time::Instant::now();
-        let key_block = read_len(&mut self.file, key_block_size_calc as usize)?.into();
-        let keys = self.read_key_block(key_block, key_block_index)?;
-        info!("Decode keywords blocks in {:?}", now.elapsed());
-        Ok(keys)
+        Ok(key_block_index)
     }
 
     /// Search magic number 0x{0,1,2},0x0,0x0,0x0 as start of keywords block
-    fn search_key_block_index_size(&mut self) -> io::Result<Vec<u8>> {
+    fn search_key_block_index_size(&mut self) -> MDictResult<Vec<u8>> {
         let now = std::time::Instant::now();
         // Ship possible magic number of keywords block index in v2
         let mut block = read_len(&mut self.file, 4)?;
         loop {
-            self.file.read_until(0x0, &mut block)?;
-            let next = read_len(&mut self.file, 2)?;
+            // reach the end of file, the guess is failed
+            if self.file.read_until(0x0, &mut block)? == 0 {
+                return Err(MDictError::KeyRequired);
+            }
+            let next = read_len(&mut self.file, 2).map_err(|e| match e {
+                MDictError::Truncated { .. } => MDictError::KeyRequired,
+                e => e,
+            })?;
             if next.as_slice() == [0, 0]
                 && block[block.len() - 1] == 0
                 && block[block.len() - 2] <= 2
@@ -480,93 +790,77 @@ impl<R: Read + Seek> MDictIndex<R> {
         Ok(block)
     }
 
-    fn decrypt_key_block_index(&mut self, block: Vec<u8>) -> Vec<u8> {
+    fn decrypt_key_block_index(&mut self, mut block: Vec<u8>) -> MDictResult<Vec<u8>> {
+        check_len(block.len(), 8)?;
         let mut key = Vec::from(&block[4..8]);
         key.extend(&0x3695u32.to_le_bytes());
-        let mut hasher = Ripemd128::new();
-        hasher.input(key);
-        let hash_result = hasher.result();
-        let key = hash_result.as_slice();
-        let mut previous = 0x36;
-        let mut result = vec![0; block.len()];
-        result[..8].clone_from_slice(&block[..8]);
-        let rest = &mut result[8..];
-        for (i, v) in block.iter().skip(8).enumerate() {
-            let mut t = (v >> 4) | (v << 4);
-            t = t ^ previous ^ (i as u8) ^ key[i % key.len()];
-            previous = *v;
-            rest[i] = t;
-        }
-        result
-    }
-
-    fn read_key_block_index(&mut self, mut block: Bytes) -> io::Result<Vec<MDictKeyBlockIndex>> {
+        let key = ripemd128(&key);
+        fast_decrypt(&mut block[8..], &key);
+        Ok(block)
+    }
+
+    fn read_key_block_index(&mut self, mut block: Bytes) -> MDictResult<Vec<MDictKeyBlockIndex>> {
         let mut list = Vec::new();
         let unit_size = self.header.unit_size();
         // string in v2 end with unit_size \0
-        let null_term = if self.header.version() == MDictFormatVersion::V2 {
-            unit_size as usize
-        } else {
+        let null_term = if self.header.version() == MDictFormatVersion::V1 {
             0
+        } else {
+            unit_size
         };
         // Map the number of char to the real size in bytes.
         let map = |x| unit_size * x as usize + null_term;
+        let len = block.len();
+        // keywords blocks follow their index
+        let mut block_offset = self.file.stream_position()?;
+        let mut uncomp_offset = 0u64;
         while !block.is_empty() {
-            let block_entries = self.read_int(&mut block);
-            let first_size = map(self.read_short(&mut block));
-            let first_bytes = block.split_to(first_size);
-            let first_word = self.header.decode_string(first_bytes)?;
-            let last_size = map(self.read_short(&mut block));
-            let last_bytes = block.split_to(last_size);
-            let last_word = self.header.decode_string(last_bytes)?;
-            let comp_size = self.read_int(&mut block);
-            let uncomp_size = self.read_int(&mut block);
+            let block_entries = self.header.read_int(&mut block)?;
+            let first_size = map(self.header.read_short(&mut block)?);
+            let offset = (len - block.len()) as u64;
+            // without the null terminator
+            let first_bytes = split_len(&mut block, first_size)?.slice(..first_size - null_term);
+            let (first_word, _) = self.header.decode(first_bytes, Some(offset))?;
+            let last_size = map(self.header.read_short(&mut block)?);
+            let offset = (len - block.len()) as u64;
+            let last_bytes = split_len(&mut block, last_size)?.slice(..last_size - null_term);
+            let (last_word, _) = self.header.decode(last_bytes, Some(offset))?;
+            let comp_size = self.header.read_int(&mut block)?;
+            let uncomp_size = self.header.read_int(&mut block)?;
             list.push(MDictKeyBlockIndex {
                 block_entries,
                 first_word,
                 last_word,
+                offset: block_offset,
                 comp_size,
                 uncomp_size,
-                // write in `read_key_block`
-                words: Vec::with_capacity(block_entries as usize),
+                uncomp_offset,
             });
+            block_offset = block_offset.saturating_add(comp_size);
+            uncomp_offset = uncomp_offset.saturating_add(uncomp_size);
         }
         Ok(list)
     }
 
-    fn read_key_block(
-        &mut self,
-        mut block: Bytes,
-        mut index: Vec<MDictKeyBlockIndex>,
-    ) -> io::Result<Vec<MDictKeyBlockIndex>> {
-        // basically strlen+strcpy, but support 2 bytes encoding like UTF-16LE
-        let split_null = if self.header.unit_size() == 2 {
-            split_dual_null
-        } else {
-            split_single_null
-        };
-        for idx in index.iter_mut() {
-            let compressed = block.split_to(idx.comp_size as usize);
-            let mut uncompressed = uncompress(compressed)?;
-            check_eq(
-                uncompressed.len() as u64,
-                idx.uncomp_size,
-                "Size of uncompressed content",
-            )?;
-            for _ in 0..idx.block_entries {
-                let offset = self.read_int(&mut uncompressed);
-                let string_encoded = split_null(&mut uncompressed);
-                let string_decoded = self.header.decode_string(string_encoded)?;
-                idx.words.push((string_decoded, offset));
-            }
-            if !uncompressed.is_empty() {
-                return Err(Error::new(
-                    ErrorKind::InvalidData,
-                    "Unexpected extra content at the end of keyword block".to_owned(),
-                ));
-            }
+    // Read and decode all keywords blocks of v1 and v2
+    fn read_key_blocks(&mut self, index: &[MDictKeyBlockIndex]) -> MDictResult<Vec<(String, u64)>> {
+        let now = std::time::Instant::now();
+        let size = sum(index.iter().map(|i| i.comp_size));
+        let mut block: Bytes = read_len(&mut self.file, size as usize)?.into();
+        let mut compressed = Vec::with_capacity(index.len());
+        for (i, idx) in index.iter().enumerate() {
+            compressed.push((i, idx, split_len(&mut block, idx.comp_size as usize)?));
         }
-        Ok(index)
+        let header = &self.header;
+        let blocks = map_blocks(compressed, |(i, idx, compressed)| {
+            header.decode_key_block(compressed, idx, i)
+        });
+        let mut keys = Vec::new();
+        for words in blocks {
+            keys.extend(words?);
+        }
+        info!("Decode keywords blocks in {:?}", now.elapsed());
+        Ok(keys)
     }
 
     /// Read keywords blocks and records blocks index, and generate the Index
@@ -579,37 +873,95 @@ impl<R: Read + Seek> MDictIndex<R> {
     ///
     /// # Error
     ///
-    /// This function returns [`io::Error`] if any operations failed.
+    /// This function returns [`MDictError`] if any operations failed.
     ///
-    /// [`io::Error`] with [`ErrorKind::InvalidData`] will return if uncompression is failed, checksum is incorrect,
+    /// The variant tells why: uncompression is failed, checksum is incorrect,
     /// length of blocks or header is incorrect or string can't be decoded to UTF-8.
-    // TODO: Simplify return type
-    pub fn make_index(
-        &mut self,
-    ) -> io::Result<(Vec<MDictRecordBlockIndex>, Vec<(String, MDictRecordIndex)>)> {
+    pub fn make_index(&mut self) -> MDictResult<MDictRecordIndexes> {
+        if self.salvage {
+            return self.salvage_index();
+        }
         self.file.seek(io::SeekFrom::Start(self.key_block_offset))?;
         info!("key block offset = {}", self.key_block_offset);
-        // read keywords block is done in `read_keys`, this function is actually read record block index.
-        let keys = self.read_keys()?;
+        let (keys, blocks) = match self.header.version() {
+            MDictFormatVersion::V3 => {
+                let sections = self.read_sections()?;
+                let (_, keys) = self.read_keys_v3(&sections, true)?;
+                (keys, self.read_record_blocks_v3(&sections)?)
+            }
+            _ => {
+                // record blocks index follows the keywords blocks.
+                let key_block_index = self.read_keys()?;
+                let keys = self.read_key_blocks(&key_block_index)?;
+                (keys, self.read_record_blocks()?)
+            }
+        };
+
+        let now = std::time::Instant::now();
+        let indexes = record_indexes(keys, &blocks, |key, _| {
+            Err(MDictError::Malformed(format!(
+                "Record of {} is out of record blocks",
+                key
+            )))
+        })?;
+        info!("Generate index of keyword to record in {:?}", now.elapsed());
+        Ok((blocks, indexes))
+    }
+
+    /// Read only the keywords blocks index and records blocks index.
+    ///
+    /// This is the low-memory alternative of [`MDictIndex::make_index`]. Keywords are not decoded
+    /// here, a keyword can be found by searching the `first_word` and `last_word` of keyword blocks,
+    /// and then decoding the keyword block which may contain it by [`read_key_block`].
+    ///
+    /// The keyword blocks of v3 have no such index, so they are still decoded once to find
+    /// their first and last keywords, but the keywords are not kept.
+    ///
+    /// # Error
+    ///
+    /// This function returns [`MDictError`] like [`MDictIndex::make_index`] do.
+    pub fn make_key_index(
+        &mut self,
+    ) -> MDictResult<(Vec<MDictKeyBlockIndex>, Vec<MDictRecordBlockIndex>)> {
+        self.file.seek(io::SeekFrom::Start(self.key_block_offset))?;
+        match self.header.version() {
+            MDictFormatVersion::V3 => {
+                let sections = self.read_sections()?;
+                let (key_block_index, _) = self.read_keys_v3(&sections, false)?;
+                Ok((key_block_index, self.read_record_blocks_v3(&sections)?))
+            }
+            _ => {
+                let key_block_index = self.read_keys()?;
+                // skip the keywords blocks, record blocks index follows them.
+                let size = sum(key_block_index.iter().map(|i| i.comp_size));
+                let offset = self.file.stream_position()?.saturating_add(size);
+                self.file.seek(io::SeekFrom::Start(offset))?;
+                Ok((key_block_index, self.read_record_blocks()?))
+            }
+        }
+    }
+
+    /// Read the records blocks index of v1 and v2.
+    fn read_record_blocks(&mut self) -> MDictResult<Vec<MDictRecordBlockIndex>> {
         let header_size = match self.header.version() {
             MDictFormatVersion::V1 => 4 * 4,
-            MDictFormatVersion::V2 => 4 * 8,
+            _ => 4 * 8,
         };
         let header_buf = read_len(&mut self.file, header_size)?;
         let mut header = header_buf.as_slice();
-        let num_blocks = self.read_int(&mut header);
+        let num_blocks = self.header.read_int(&mut header)?;
         info!("record block num: {}", num_blocks);
-        let num_entries = self.read_int(&mut header) as usize;
-        let block_index_size = self.read_int(&mut header);
+        let _num_entries = self.header.read_int(&mut header)?;
+        let block_index_size = self.header.read_int(&mut header)?;
         info!("record block index size: {}", block_index_size);
-        let blocks_size = self.read_int(&mut header);
+        let blocks_size = self.header.read_int(&mut header)?;
         info!("record blocks size: {}", blocks_size);
-        let block_index_size_calc = num_blocks
-            * 2
-            * match self.header.version() {
+        let block_index_size_calc = num_blocks.saturating_mul(
+            2 * match self.header.version() {
                 MDictFormatVersion::V1 => 4,
-                MDictFormatVersion::V2 => 8,
-            };
+                _ => 8,
+            },
+        );
         check_eq(
             block_index_size_calc,
             block_index_size,
@@ -618,79 +970,252 @@ impl<R: Read + Seek> MDictIndex<R> {
         let now = std::time::Instant::now();
         let block_index_bytes = read_len(&mut self.file, block_index_size as usize)?;
         let block_index = self.read_record_block_info(block_index_bytes.into())?;
-        let blocks_size_calc: u64 = block_index.iter().map(|(c, _)| *c).sum();
+        let blocks_size_calc = sum(block_index.iter().map(|(c, _)| *c));
         check_eq(blocks_size_calc, blocks_size, "Size of record block")?;
         info!("Decode record block index in {:?}", now.elapsed());
-
-        let now = std::time::Instant::now();
-        // collect pairs of (keywords, offset in uncompressed records), drop others
-        let mut keys: Vec<_> = keys.into_iter().flat_map(|i| i.words.into_iter()).collect();
-        // This should be already sorted.
-        keys.sort_by_key(|(_, o)| *o);
         // take the start of record blocks
-        let record_block_offset = self.file.seek(io::SeekFrom::Current(0))?;
-        let mut indexes = Vec::with_capacity(num_entries as usize);
-        let mut blocks = Vec::with_capacity(num_blocks as usize);
-        let mut comp_offset = 0;
-        let mut uncomp_offset = 0;
-        let mut keys = keys.into_iter().peekable();
-        for (bi, (comp_size, uncomp_size)) in block_index.into_iter().enumerate() {
-            let record_block = MDictRecordBlockIndex {
-                comp_size,
-                offset: record_block_offset + comp_offset,
-            };
-            let next_comp_offset = comp_offset + comp_size;
-            let next_uncomp_offset = uncomp_offset + uncomp_size;
-            while let Some((key, o)) = keys.next() {
-                let offset = o - uncomp_offset;
-                assert!(offset <= uncomp_size);
-                let end = match keys.peek() {
-                    Some((_, next_offset)) => *next_offset,
-                    None => next_uncomp_offset,
+        let mut offset = self.file.stream_position()?;
+        let blocks = block_index
+            .into_iter()
+            .map(|(comp_size, uncomp_size)| {
+                let block = MDictRecordBlockIndex {
+                    offset,
+                    comp_size,
+                    uncomp_size,
+                    version: self.header.version(),
+                    dropped: false,
                 };
-                let len = end - o;
-                let index = MDictRecordIndex {
-                    block: bi as u32,
-                    offset: offset as u32,
-                    len: len as u32,
-                };
-                indexes.push((key, index));
-                if end >= next_uncomp_offset {
-                    break;
-                }
-            }
-            comp_offset = next_comp_offset;
-            uncomp_offset = next_uncomp_offset;
-            blocks.push(record_block);
-        }
-        info!("Generate index of keyword to record in {:?}", now.elapsed());
-        Ok((blocks, indexes))
+                offset = offset.saturating_add(comp_size);
+                block
+            })
+            .collect();
+        Ok(blocks)
     }
 
-    fn read_record_block_info(&mut self, mut block: Bytes) -> io::Result<Vec<(u64, u64)>> {
+    fn read_record_block_info(&mut self, mut block: Bytes) -> MDictResult<Vec<(u64, u64)>> {
         let mut result = Vec::new();
         while !block.is_empty() {
-            let comp_size = self.read_int(&mut block);
-            let uncomp_size = self.read_int(&mut block);
+            let comp_size = self.header.read_int(&mut block)?;
+            let uncomp_size = self.header.read_int(&mut block)?;
             result.push((comp_size, uncomp_size));
         }
         Ok(result)
     }
 
-    // get u32 in v1, u64 in v2
-    fn read_int<B: Buf>(&self, buf: &mut B) -> u64 {
-        match self.header.version() {
-            MDictFormatVersion::V1 => buf.get_u32() as u64,
-            MDictFormatVersion::V2 => buf.get_u64(),
+    /// Search the sections of v3, each section begins with its type and size.
+    fn read_sections(&mut self) -> MDictResult<MDictSections> {
+        let mut sections = MDictSections::default();
+        // until reach the end of file
+        while !self.file.fill_buf()?.is_empty() {
+            let section_header = read_len(&mut self.file, 12)?;
+            let mut section_header = section_header.as_slice();
+            let section_type = section_header.get_u32();
+            let section_size = section_header.get_u64();
+            let offset = self.file.stream_position()?;
+            match section_type {
+                0x0100_0000 => sections.record_data = Some(offset),
+                0x0300_0000 => sections.key_data = Some(offset),
+                // indexes of record blocks and keywords blocks,
+                // both data sections are readable without them.
+                0x0200_0000 | 0x0400_0000 => {}
+                _ => {
+                    return Err(MDictError::Malformed(format!(
+                        "Unknown section {:#X}",
+                        section_type
+                    )))
+                }
+            }
+            info!(
+                "section {:#X} at {}, size {}",
+                section_type, offset, section_size
+            );
+            // seek from the start to keep moving forward even if the size is corrupted
+            self.file
+                .seek(io::SeekFrom::Start(offset.saturating_add(section_size)))?;
         }
+        Ok(sections)
     }
 
-    // get u8 in v1, u16 in v2
-    fn read_short<B: Buf>(&self, buf: &mut B) -> u16 {
-        match self.header.version() {
-            MDictFormatVersion::V1 => buf.get_u8() as u16,
-            MDictFormatVersion::V2 => buf.get_u16(),
+    /// Read the keywords blocks of v3, and keep the keywords if `with_words` is set.
+    #[allow(clippy::type_complexity)]
+    fn read_keys_v3(
+        &mut self,
+        sections: &MDictSections,
+        with_words: bool,
+    ) -> MDictResult<(Vec<MDictKeyBlockIndex>, Vec<(String, u64)>)> {
+        let now = std::time::Instant::now();
+        let blocks = self.read_key_block_index_v3(sections)?;
+        // read all blocks before decoding them, errors are kept in the order of blocks
+        let mut compressed = Vec::with_capacity(blocks.len());
+        for (i, index) in blocks.into_iter().enumerate() {
+            let data = self
+                .file
+                .seek(io::SeekFrom::Start(index.offset))
+                .map_err(MDictError::from)
+                .and_then(|_| read_len(&mut self.file, index.comp_size as usize));
+            let failed = data.is_err();
+            compressed.push((i, index, data));
+            if failed {
+                break;
+            }
+        }
+        let header = &self.header;
+        let blocks = map_blocks(compressed, |(i, mut index, data)| -> MDictResult<_> {
+            // v3 is always encoded in UTF-8
+            let words = header.decode_key_block(data?.into(), &index, i)?;
+            index.block_entries = words.len() as u64;
+            index.first_word = words.first().map(|w| w.0.clone()).unwrap_or_default();
+            index.last_word = words.last().map(|w| w.0.clone()).unwrap_or_default();
+            Ok((index, words))
+        });
+        let mut list = Vec::with_capacity(blocks.len());
+        let mut keys = Vec::new();
+        for block in blocks {
+            let (index, words) = block?;
+            if with_words {
+                keys.extend(words);
+            }
+            list.push(index);
+        }
+        info!("Decode keywords blocks in {:?}", now.elapsed());
+        Ok((list, keys))
+    }
+
+    /// Read the index of keywords blocks of v3 without decoding them,
+    /// the number of keywords and the first and last keywords are unknown.
+    fn read_key_block_index_v3(
+        &mut self,
+        sections: &MDictSections,
+    ) -> MDictResult<Vec<MDictKeyBlockIndex>> {
+        let offset = sections
+            .key_data
+            .ok_or_else(|| MDictError::Malformed("Missing keywords data section".to_owned()))?;
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        let mut uncomp_offset = 0;
+        let list = self
+            .read_blocks_v3()?
+            .into_iter()
+            .map(|block| {
+                let index = MDictKeyBlockIndex {
+                    block_entries: 0,
+                    first_word: String::new(),
+                    last_word: String::new(),
+                    offset: block.offset,
+                    comp_size: block.comp_size,
+                    uncomp_size: block.uncomp_size,
+                    uncomp_offset,
+                };
+                uncomp_offset = uncomp_offset.saturating_add(block.uncomp_size);
+                index
+            })
+            .collect();
+        Ok(list)
+    }
+
+    /// Read the records blocks index of v3.
+    fn read_record_blocks_v3(
+        &mut self,
+        sections: &MDictSections,
+    ) -> MDictResult<Vec<MDictRecordBlockIndex>> {
+        let offset = sections
+            .record_data
+            .ok_or_else(|| MDictError::Malformed("Missing records data section".to_owned()))?;
+        self.file.seek(io::SeekFrom::Start(offset))?;
+        let now = std::time::Instant::now();
+        let blocks = self.read_blocks_v3()?;
+        info!("record block num: {}", blocks.len());
+        info!("Decode record block index in {:?}", now.elapsed());
+        Ok(blocks)
+    }
+
+    // Read the number of blocks, the total size, and then the size of each block
+    // in a data section of v3, the content of blocks is skipped.
+    fn read_blocks_v3(&mut self) -> MDictResult<Vec<MDictRecordBlockIndex>> {
+        let buf = read_len(&mut self.file, 12)?;
+        let num_blocks = buf.as_slice().get_u32();
+        let uuid = self.header.attrs.contains_key("UUID");
+        let mut blocks = Vec::new();
+        for _ in 0..num_blocks {
+            let buf = read_len(&mut self.file, 12)?;
+            let mut buf = buf.as_slice();
+            let uncomp_size = buf.get_u32() as u64;
+            let comp_size = buf.get_u32() as u64;
+            let info = buf.get_u32_le();
+            // a block contains at least its info and checksum
+            if comp_size < 8 {
+                return Err(MDictError::Malformed(format!(
+                    "Size of block is too small: {}",
+                    comp_size
+                )));
+            }
+            if uuid && (info >> 4) & 0xf != 0 {
+                return Err(MDictError::Unsupported(
+                    "MDict 3.0 blocks encrypted with UUID are not supported".to_owned(),
+                ));
+            }
+            let offset = self.file.stream_position()? - 4;
+            self.file.seek_relative(comp_size as i64 - 4)?;
+            blocks.push(MDictRecordBlockIndex {
+                offset,
+                comp_size,
+                uncomp_size,
+                version: MDictFormatVersion::V3,
+                dropped: false,
+            });
         }
+        Ok(blocks)
+    }
+
+    /// Decompress all record blocks to check their checksums and sizes.
+    ///
+    /// The `blocks` should be provided from [`MDictIndex::make_index`] or [`MDictIndex::make_key_index`].
+    /// Lookups only decompress the blocks of the records they need, so this finds the corrupted
+    /// blocks of a file beforehand. With the "parallel" crate feature, the blocks are decompressed
+    /// across the thread pool of rayon, a batch of blocks at a time.
+    ///
+    /// # Error
+    ///
+    /// This function returns the [`MDictError`] of the first block which is corrupted.
+    pub fn check_record_blocks(&mut self, blocks: &[MDictRecordBlockIndex]) -> MDictResult<()> {
+        let now = std::time::Instant::now();
+        let ranges: Vec<_> = blocks.iter().map(|b| (b.offset, b.comp_size)).collect();
+        check_blocks(
+            &mut self.file,
+            &ranges,
+            |i, compressed| check_record_block(&compressed, &blocks[i], i),
+            |_, result| result,
+        )?;
+        info!("Check record blocks in {:?}", now.elapsed());
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    /// Asynchronous version of [`MDictIndex::make_index`].
+    ///
+    /// Reading and decompressing keywords blocks of a large file takes seconds, so this runs
+    /// [`MDictIndex::make_index`] in the blocking thread pool of tokio rather than blocking the runtime.
+    /// This consumes the `MDictIndex` and also returns its header, like [`MDictIndex::into_header`] do.
+    ///
+    /// # Error
+    ///
+    /// This function returns [`MDictError`] like [`MDictIndex::make_index`] do.
+    #[allow(clippy::type_complexity)]
+    pub async fn make_index_async(
+        mut self,
+    ) -> MDictResult<(
+        MDictHeader,
+        Vec<MDictRecordBlockIndex>,
+        Vec<(String, MDictRecordIndex)>,
+    )>
+    where
+        R: Send + 'static,
+    {
+        tokio::task::spawn_blocking(move || {
+            let (blocks, keys) = self.make_index()?;
+            Ok((self.into_header(), blocks, keys))
+        })
+        .await
+        .map_err(io::Error::other)?
     }
 
     /// Consume this MDictIndex and return its header.
@@ -703,163 +1228,715 @@ impl<R: Read + Seek> MDictIndex<R> {
     }
 }
 
+// Map keywords and offsets of their records in the uncompressed record blocks to indexes of records.
+// A keyword whose record is out of record blocks is passed to `out_of_range` and skipped,
+// unless it returns an error.
+fn record_indexes<F>(
+    mut keys: Vec<(String, u64)>,
+    blocks: &[MDictRecordBlockIndex],
+    mut out_of_range: F,
+) -> MDictResult<Vec<(String, MDictRecordIndex)>>
+where
+    F: FnMut(String, u64) -> MDictResult<()>,
+{
+    // This should be already sorted.
+    keys.sort_by_key(|(_, o)| *o);
+    let records_size = sum(blocks.iter().map(|b| b.uncomp_size));
+    // A record ends where the next record starts,
+    // keywords sharing one record have the same offset.
+    let mut ends = vec![records_size; keys.len()];
+    for i in (1..keys.len()).rev() {
+        ends[i - 1] = if keys[i].1 > keys[i - 1].1 {
+            keys[i].1
+        } else {
+            ends[i]
+        };
+    }
+    let mut indexes = Vec::with_capacity(keys.len());
+    // the block where the record starts, and its offset in the uncompressed records
+    let mut bi = 0;
+    let mut uncomp_offset = 0u64;
+    for ((key, o), end) in keys.into_iter().zip(ends) {
+        // The record may span several blocks, so the next record may start in any of them.
+        while bi + 1 < blocks.len() && o >= uncomp_offset.saturating_add(blocks[bi].uncomp_size) {
+            uncomp_offset = uncomp_offset.saturating_add(blocks[bi].uncomp_size);
+            bi += 1;
+        }
+        let offset = o - uncomp_offset;
+        match blocks.get(bi) {
+            Some(block) if offset <= block.uncomp_size => {}
+            _ => {
+                out_of_range(key, o)?;
+                continue;
+            }
+        }
+        let index = MDictRecordIndex {
+            block: bi as u64,
+            offset,
+            len: end - o,
+        };
+        indexes.push((key, index));
+    }
+    Ok(indexes)
+}
+
+// Read the blocks at `(offset, size)` of the file in batches, and check them by `check` across
+// the thread pool of rayon with the "parallel" feature. The results are passed to `report` in
+// the order of blocks, the walk stops at the first error returned by `report`.
+fn check_blocks<R, T, C, F>(
+    file: &mut R,
+    blocks: &[(u64, u64)],
+    check: C,
+    mut report: F,
+) -> MDictResult<()>
+where
+    R: Read + Seek,
+    T: Send,
+    C: Fn(usize, Vec<u8>) -> MDictResult<T> + Sync + Send,
+    F: FnMut(usize, MDictResult<T>) -> MDictResult<()>,
+{
+    let mut start = 0;
+    while start < blocks.len() {
+        let mut batch = Vec::new();
+        let mut batch_size = 0u64;
+        for (i, &(offset, size)) in blocks.iter().enumerate().skip(start) {
+            if !batch.is_empty() && batch_size >= CHECK_BATCH_SIZE {
+                break;
+            }
+            let data = file
+                .seek(io::SeekFrom::Start(offset))
+                .map_err(MDictError::from)
+                .and_then(|_| read_len(file, to_usize(size, "Block size")?));
+            batch_size = batch_size.saturating_add(size);
+            batch.push((i, data));
+        }
+        start += batch.len();
+        let results = map_blocks(batch, |(i, data)| (i, data.and_then(|data| check(i, data))));
+        for (i, result) in results {
+            report(i, result)?;
+        }
+    }
+    Ok(())
+}
+
+// Map the blocks in order, across the thread pool of rayon with the "parallel" feature
+#[cfg(feature = "parallel")]
+fn map_blocks<T, U, F>(blocks: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Sync + Send,
+{
+    use rayon::prelude::*;
+    blocks.into_par_iter().map(f).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn map_blocks<T, U, F>(blocks: Vec<T>, f: F) -> Vec<U>
+where
+    F: Fn(T) -> U,
+{
+    blocks.into_iter().map(f).collect()
+}
+
+// See `MDictHeader::normalize_key`, the writer sorts keywords by this too
+fn normalize_key(key: &str, case_sensitive: bool, strip_key: bool) -> String {
+    let key = if strip_key {
+        let stripped: String = key.chars().filter(|c| c.is_alphanumeric()).collect();
+        if stripped.is_empty() {
+            key.to_owned()
+        } else {
+            stripped
+        }
+    } else {
+        key.to_owned()
+    };
+    if case_sensitive {
+        key
+    } else {
+        key.to_lowercase()
+    }
+}
+
+// The code unit size is the smallest size of char (in bytes) in this encoding
+fn encoding_unit_size(encoding: &'static Encoding) -> usize {
+    let name = encoding.name().to_ascii_lowercase();
+    // Anyone still using BIG-5 ?
+    if name.contains("utf-16") || name.contains("big5") {
+        2
+    } else {
+        1
+    }
+}
+
 // read until one \0
-fn split_single_null(buf: &mut Bytes) -> Bytes {
+fn split_single_null(buf: &mut Bytes) -> MDictResult<Bytes> {
     for i in 0..buf.len() {
         if buf[i] == 0x0 {
             let string = buf.split_to(i);
             let _ = buf.split_to(1);
-            return string;
+            return Ok(string);
         }
     }
-    Bytes::new()
+    Err(unterminated())
 }
 
 // read two bytes echo time until two \0
-fn split_dual_null(buf: &mut Bytes) -> Bytes {
-    if buf.len() > 2 {
-        let mut i = 0;
-        while i < buf.len() {
-            if buf[i] == 0x0 && buf[i + 1] == 0x0 {
-                let string = buf.split_to(i);
-                let _ = buf.split_to(2);
-                return string;
-            }
-            i += 2;
+fn split_dual_null(buf: &mut Bytes) -> MDictResult<Bytes> {
+    let mut i = 0;
+    while i + 1 < buf.len() {
+        if buf[i] == 0x0 && buf[i + 1] == 0x0 {
+            let string = buf.split_to(i);
+            let _ = buf.split_to(2);
+            return Ok(string);
         }
+        i += 2;
     }
-    Bytes::new()
+    Err(unterminated())
+}
+
+fn unterminated() -> MDictError {
+    MDictError::Malformed("Keyword is not terminated by \\0".to_owned())
+}
+
+// split len bytes from the front of buf
+fn split_len(buf: &mut Bytes, len: usize) -> MDictResult<Bytes> {
+    check_len(buf.len(), len)?;
+    Ok(buf.split_to(len))
 }
 
-fn check_option_eq(a: u64, b: Option<u64>, msg: &str) -> io::Result<()> {
-    if let Some(b) = b {
-        check_eq(a, b, msg)?;
+// sum of sizes or numbers read from file, which may overflow if the file is corrupted
+fn sum<I: Iterator<Item = u64>>(iter: I) -> u64 {
+    iter.fold(0, u64::saturating_add)
+}
+
+fn check_option_eq(actual: u64, expected: Option<u64>, what: &'static str) -> MDictResult<()> {
+    if let Some(expected) = expected {
+        check_eq(actual, expected, what)?;
     }
     Ok(())
 }
 
-fn check_eq<T: PartialEq + std::fmt::Display>(a: T, b: T, msg: &str) -> io::Result<()> {
-    if a != b {
-        Err(Error::new(
-            ErrorKind::InvalidData,
-            format!("{} mismatch: {} != {}", msg, a, b),
-        ))
+fn check_eq(actual: u64, expected: u64, what: &'static str) -> MDictResult<()> {
+    if actual != expected {
+        Err(MDictError::Mismatch {
+            what,
+            expected,
+            actual,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_checksum(
+    actual: u32,
+    expected: u32,
+    kind: MDictBlockKind,
+    index: usize,
+) -> MDictResult<()> {
+    if actual != expected {
+        Err(MDictError::BlockChecksum {
+            kind,
+            index,
+            expected,
+            actual,
+        })
     } else {
         Ok(())
     }
 }
 
 // Uncompress block
-fn uncompress(mut block: Bytes) -> io::Result<Bytes> {
-    assert!(block.len() > 8);
-    let magic = block.get_u32_le();
-    let checksum = block.get_u32();
-    let decompressed = match magic {
+//
+// Since v3, the first 4 bytes of a block contain both compression and encryption method,
+// and the checksum is calculated from the decrypted data rather than the uncompressed data.
+//
+// `kind` and `index` locate this block in the file and are only used to report errors.
+fn uncompress(
+    block: &[u8],
+    uncomp_size: usize,
+    version: MDictFormatVersion,
+    kind: MDictBlockKind,
+    index: usize,
+) -> MDictResult<Bytes> {
+    // info and checksum, the data of an uncompressed block is empty in an empty dictionary
+    check_len(block.len(), 8)?;
+    let (mut header, block) = block.split_at(8);
+    let checksum_bytes = &header[4..];
+    let info = header.get_u32_le();
+    let checksum = header.get_u32();
+    let (magic, encryption, encrypted_size) = match version {
+        MDictFormatVersion::V3 => (info & 0xf, (info >> 4) & 0xf, (info >> 8 & 0xff) as usize),
+        _ => (info, 0, 0),
+    };
+    let decrypted;
+    let block = match encryption {
         0x0 => block,
-        0x1 => minilzo::decompress(&block, 0x10000)
-            .map_err(|e| {
-                Error::new(
-                    ErrorKind::InvalidData,
-                    format!("Lzo decompress failed: {:?}", e),
-                )
+        0x1 | 0x2 => {
+            let mut data = block.to_vec();
+            let size = encrypted_size.min(data.len());
+            let key = ripemd128(checksum_bytes);
+            if encryption == 0x1 {
+                fast_decrypt(&mut data[..size], &key);
+            } else {
+                salsa20_8(&mut data[..size], &key);
+            }
+            decrypted = data;
+            &decrypted
+        }
+        _ => {
+            return Err(MDictError::UnknownEncryption {
+                kind,
+                index,
+                method: encryption,
+            })
+        }
+    };
+    if version == MDictFormatVersion::V3 {
+        let calc_checksum = adler::adler32_slice(block);
+        check_checksum(calc_checksum, checksum, kind, index)?;
+    }
+    let decompressed = match magic {
+        0x0 => Bytes::copy_from_slice(block),
+        // LZO can't expand a byte to more than 255 bytes, so a corrupted size can't
+        // make us allocate arbitrary memory
+        0x1 => lzo::decompress(block, uncomp_size.min(block.len().saturating_mul(256)))
+            .map_err(|e| MDictError::Decompress {
+                kind,
+                index,
+                message: format!("Lzo decompress failed: {}", e),
             })?
             .into(),
-        0x2 => decompress_to_vec_zlib(&block)
-            .map_err(|e| {
-                Error::new(
-                    ErrorKind::InvalidData,
-                    format!("Lzo decompress failed: {:?}", e),
-                )
+        // miniz_oxide doubles its buffer and fails if the doubled size exceeds the limit,
+        // even though the data fits in the limit
+        0x2 => decompress_to_vec_zlib_with_limit(block, uncomp_size.saturating_mul(2))
+            .map_err(|e| MDictError::Decompress {
+                kind,
+                index,
+                message: format!("Zlib decompress failed: {:?}", e),
             })?
             .into(),
-        _ => {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                format!("Unknown compression {:#X}", magic),
-            ))
-        }
+        _ => return Err(MDictError::UnknownCompression { kind, index, magic }),
     };
-    let calc_checksum = adler::adler32_slice(&decompressed);
-    check_eq(calc_checksum, checksum, "Checksum of uncompressed data")?;
+    if version != MDictFormatVersion::V3 {
+        let calc_checksum = adler::adler32_slice(&decompressed);
+        check_checksum(calc_checksum, checksum, kind, index)?;
+    }
     Ok(decompressed)
 }
 
 // read len bytes from this reader and return it as `Vec<u8>`
-fn read_len<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
-    let mut buf = Vec::with_capacity(len);
+//
+// The buffer grows as the content is read, so a corrupted `len` can't exhaust memory.
+fn read_len<R: Read>(reader: &mut R, len: usize) -> MDictResult<Vec<u8>> {
+    let mut buf = Vec::new();
     reader.take(len as u64).read_to_end(&mut buf)?;
+    check_len(buf.len(), len)?;
     Ok(buf)
 }
 
+// Sizes and offsets in the file are 64-bit, which may not fit in `usize` on 32-bit targets
+fn to_usize(value: u64, what: &'static str) -> MDictResult<usize> {
+    usize::try_from(value).map_err(|_| MDictError::Overflow { what, value })
+}
+
+fn check_len(actual: usize, expected: usize) -> MDictResult<()> {
+    if actual < expected {
+        Err(MDictError::Truncated {
+            expected: expected as u64,
+            actual: actual as u64,
+        })
+    } else {
+        Ok(())
+    }
+}
+
 #[cfg(feature = "async")]
 use tokio::io::{AsyncSeekExt, AsyncReadExt};
 
 #[cfg(feature = "async")]
 // read len bytes from this reader and return it as `Vec<u8>`
-async fn read_len_async<R: AsyncReadExt + Unpin>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
-    let mut buf = Vec::with_capacity(len);
+async fn read_len_async<R: AsyncReadExt + Unpin>(reader: &mut R, len: usize) -> MDictResult<Vec<u8>> {
+    let mut buf = Vec::new();
     reader.take(len as u64).read_to_end(&mut buf).await?;
+    check_len(buf.len(), len)?;
     Ok(buf)
 }
 
-#[cfg(not(feature = "async"))]
 /// Lookup record of the given record index.
 ///
 /// **This reader should contain valid Mdict file.**
 ///
-/// The `key` should be corresponding to the keyword you want to lookup, and the `block` should be the
-/// corresponding `MDictRecordBlockIndex` by the index of `key.block`.
+/// The `key` should be corresponding to the keyword you want to lookup, and the `blocks` should be
+/// all record blocks of the file. The record is read from the block of index `key.block`, and
+/// the following blocks if the record spans them.
 ///
-/// The gaving `key` and `block` should be provided from `make_index` function, otherwise this lookup
+/// The gaving `key` and `blocks` should be provided from `make_index` function, otherwise this lookup
 /// may failed or return random data.
 ///
-/// This is the blocking version of this function. To use asynchronous version, select the "async" crate feature
+/// This is the blocking version of this function, see `lookup_async` for the asynchronous version.
 pub fn lookup<R>(
+    reader: R,
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+) -> MDictResult<Bytes>
+where
+    R: Read + Seek,
+{
+    lookup_with_cache(reader, key, blocks, None)
+}
+
+/// Lookup record of the given record index, and keep the decompressed record blocks in `cache`.
+///
+/// This is the same as [`lookup`], except that the record blocks already in `cache` are not read
+/// and decompressed again. `file` is the number of this file in `cache`, which should be taken from
+/// [`MDictBlockCache::new_file_id`] once for each file.
+///
+/// This is the blocking version of this function, see `lookup_cached_async` for the asynchronous version.
+pub fn lookup_cached<R>(
+    reader: R,
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+    cache: &MDictBlockCache,
+    file: u64,
+) -> MDictResult<Bytes>
+where
+    R: Read + Seek,
+{
+    lookup_with_cache(reader, key, blocks, Some((cache, file)))
+}
+
+fn lookup_with_cache<R>(
     mut reader: R,
     key: &MDictRecordIndex,
-    block: &MDictRecordBlockIndex,
-) -> io::Result<Bytes>
+    blocks: &[MDictRecordBlockIndex],
+    cache: Option<(&MDictBlockCache, u64)>,
+) -> MDictResult<Bytes>
 where
     R: Read + Seek,
 {
-    reader.seek(io::SeekFrom::Start(block.offset))?;
-    let compressed = read_len(&mut reader, block.comp_size as usize)?;
-    let comp_size = compressed.len();
-    let mut uncompressed = uncompress(compressed.into())?;
-    info!(
-        "uncompress record block {} -> {}",
-        comp_size,
-        uncompressed.len()
-    );
-    let mut data = uncompressed.split_off(key.offset as usize);
-    data.truncate(key.len as usize);
-    Ok(data)
+    lookup_with(key, blocks, cache, |i, block| {
+        reader.seek(io::SeekFrom::Start(block.offset))?;
+        let size = to_usize(block.comp_size, "Record block size")?;
+        let compressed = read_len(&mut reader, size)?;
+        uncompress_record_block(&compressed, block, i)
+    })
 }
 
-#[cfg(feature = "async")]
-/// Lookup record of the given record index.
+/// Lookup record of the given record index from the content of file in memory.
 ///
-/// **This reader should contain valid Mdict file.**
+/// This is the same as [`lookup`], but the record blocks are sliced from `data` rather than read
+/// into a buffer, so there is neither copy nor system call before decompressing the blocks.
+/// The `data` is usually a memory-mapped file, such as `MDictMmap` of the "mmap" crate feature.
+pub fn lookup_slice(
+    data: &[u8],
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+) -> MDictResult<Bytes> {
+    lookup_slice_with_cache(data, key, blocks, None)
+}
+
+/// Lookup record of the given record index from the content of file in memory, and keep the
+/// decompressed record blocks in `cache`.
+///
+/// This is the same as [`lookup_cached`], but the record blocks are sliced from `data` like [`lookup_slice`] do.
+pub fn lookup_slice_cached(
+    data: &[u8],
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+    cache: &MDictBlockCache,
+    file: u64,
+) -> MDictResult<Bytes> {
+    lookup_slice_with_cache(data, key, blocks, Some((cache, file)))
+}
+
+fn lookup_slice_with_cache(
+    data: &[u8],
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+    cache: Option<(&MDictBlockCache, u64)>,
+) -> MDictResult<Bytes> {
+    lookup_with(key, blocks, cache, |i, block| {
+        let start = to_usize(block.offset, "Record block offset")?;
+        let size = to_usize(block.comp_size, "Record block size")?;
+        let compressed = data
+            .get(start..)
+            .and_then(|data| data.get(..size))
+            .ok_or_else(|| MDictError::Truncated {
+                expected: block.comp_size,
+                actual: data.len().saturating_sub(start) as u64,
+            })?;
+        uncompress_record_block(compressed, block, i)
+    })
+}
+
+/// Lookup record of the given record index from a [`MDictSource`].
 ///
-/// The `key` should be corresponding to the keyword you want to lookup, and the `block` should be the
-/// corresponding `MDictRecordBlockIndex` by the index of `key.block`.
+/// This is the same as [`lookup`], but the record blocks are read by their positions, so a source
+/// can be shared by lookups in many threads. The blocks are sliced rather than copied if the
+/// source is in memory, like [`lookup_slice`] do.
+pub fn lookup_source(
+    source: &dyn MDictSource,
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+) -> MDictResult<Bytes> {
+    lookup_source_with_cache(source, key, blocks, None)
+}
+
+/// Lookup record of the given record index from a [`MDictSource`], and keep the decompressed
+/// record blocks in `cache`.
 ///
-/// The gaving `key` and `block` should be provided from `make_index` function, otherwise this lookup
-/// may failed or return random data.
+/// This is the same as [`lookup_cached`], but the record blocks are read like [`lookup_source`] do.
+pub fn lookup_source_cached(
+    source: &dyn MDictSource,
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+    cache: &MDictBlockCache,
+    file: u64,
+) -> MDictResult<Bytes> {
+    lookup_source_with_cache(source, key, blocks, Some((cache, file)))
+}
+
+fn lookup_source_with_cache(
+    source: &dyn MDictSource,
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+    cache: Option<(&MDictBlockCache, u64)>,
+) -> MDictResult<Bytes> {
+    if let Some(data) = source.as_slice() {
+        return lookup_slice_with_cache(data, key, blocks, cache);
+    }
+    lookup_with(key, blocks, cache, |i, block| {
+        let size = to_usize(block.comp_size, "Record block size")?;
+        // don't trust the size before knowing the block is in the source
+        if block.offset.saturating_add(block.comp_size) > source.size() {
+            return Err(MDictError::Truncated {
+                expected: block.comp_size,
+                actual: source.size().saturating_sub(block.offset),
+            });
+        }
+        let mut compressed = vec![0; size];
+        source.read_exact_at(&mut compressed, block.offset)?;
+        uncompress_record_block(&compressed, block, i)
+    })
+}
+
+// Take the record out of the uncompressed record blocks, which are taken from `cache`,
+// or read and decompressed by `read` and then put into `cache`
+fn lookup_with<F>(
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+    cache: Option<(&MDictBlockCache, u64)>,
+    mut read: F,
+) -> MDictResult<Bytes>
+where
+    F: FnMut(usize, &MDictRecordBlockIndex) -> MDictResult<Bytes>,
+{
+    let mut pieces = Vec::new();
+    for (i, range) in record_pieces(key, blocks)? {
+        let block = &blocks[i];
+        let uncompressed = match cache.and_then(|(cache, file)| cache.get(file, block.offset)) {
+            Some(uncompressed) => uncompressed,
+            None => {
+                let uncompressed = read(i, block)?;
+                if let Some((cache, file)) = cache {
+                    cache.insert(file, block.offset, uncompressed.clone());
+                }
+                uncompressed
+            }
+        };
+        pieces.push(record_piece(uncompressed, range)?);
+    }
+    Ok(join_pieces(pieces))
+}
+
+#[cfg(feature = "async")]
+/// Lookup record of the given record index from asynchronous `reader`.
 ///
-/// This is the asynchronous version of this function. To use blocking version, unselect the "async" crate feature
-pub async fn lookup<AR>(
+/// This is the asynchronous version of [`lookup`], and has the same requirements and errors.
+/// It is only available with the "async" crate feature.
+pub async fn lookup_async<AR>(
+    reader: AR,
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+) -> MDictResult<Bytes>
+where
+    AR: AsyncReadExt + AsyncSeekExt + Unpin,
+{
+    lookup_with_cache_async(reader, key, blocks, None).await
+}
+
+#[cfg(feature = "async")]
+/// Lookup record of the given record index from asynchronous `reader`, and keep the decompressed
+/// record blocks in `cache`.
+///
+/// This is the asynchronous version of [`lookup_cached`], and has the same requirements and errors.
+/// It is only available with the "async" crate feature.
+pub async fn lookup_cached_async<AR>(
+    reader: AR,
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+    cache: &MDictBlockCache,
+    file: u64,
+) -> MDictResult<Bytes>
+where
+    AR: AsyncReadExt + AsyncSeekExt + Unpin,
+{
+    lookup_with_cache_async(reader, key, blocks, Some((cache, file))).await
+}
+
+#[cfg(feature = "async")]
+async fn lookup_with_cache_async<AR>(
     mut reader: AR,
     key: &MDictRecordIndex,
-    block: &MDictRecordBlockIndex,
-) -> io::Result<Bytes>
+    blocks: &[MDictRecordBlockIndex],
+    cache: Option<(&MDictBlockCache, u64)>,
+) -> MDictResult<Bytes>
+where
+    AR: AsyncReadExt + AsyncSeekExt + Unpin,
+{
+    let mut pieces = Vec::new();
+    for (i, range) in record_pieces(key, blocks)? {
+        let block = &blocks[i];
+        let uncompressed = match cache.and_then(|(cache, file)| cache.get(file, block.offset)) {
+            Some(uncompressed) => uncompressed,
+            None => {
+                reader.seek(io::SeekFrom::Start(block.offset)).await?;
+                let size = to_usize(block.comp_size, "Record block size")?;
+                let compressed = read_len_async(&mut reader, size).await?;
+                let uncompressed = uncompress_record_block(&compressed, block, i)?;
+                if let Some((cache, file)) = cache {
+                    cache.insert(file, block.offset, uncompressed.clone());
+                }
+                uncompressed
+            }
+        };
+        pieces.push(record_piece(uncompressed, range)?);
+    }
+    Ok(join_pieces(pieces))
+}
+
+/// Read and decode a keyword block.
+///
+/// This returns the keywords in the block and the offsets of their records from the start of
+/// the uncompressed record blocks, the same as [`MDictIndex::make_index`] uses to build the index
+/// of records. The record of a keyword ends at the offset of the next keyword.
+///
+/// The `header` should be the header of this file, and `block` should be the number of `index`
+/// in the list returned by [`MDictIndex::make_key_index`], which is used to report errors.
+///
+/// This is the blocking version of this function, see `read_key_block_async` for the asynchronous version.
+pub fn read_key_block<R>(
+    mut reader: R,
+    header: &MDictHeader,
+    index: &MDictKeyBlockIndex,
+    block: usize,
+) -> MDictResult<Vec<(String, u64)>>
+where
+    R: Read + Seek,
+{
+    reader.seek(io::SeekFrom::Start(index.offset))?;
+    let compressed = read_len(&mut reader, index.comp_size as usize)?;
+    header.decode_key_block(compressed.into(), index, block)
+}
+
+#[cfg(feature = "async")]
+/// Read and decode a keyword block from asynchronous `reader`.
+///
+/// This is the asynchronous version of [`read_key_block`], and has the same requirements and errors.
+/// It is only available with the "async" crate feature.
+pub async fn read_key_block_async<AR>(
+    mut reader: AR,
+    header: &MDictHeader,
+    index: &MDictKeyBlockIndex,
+    block: usize,
+) -> MDictResult<Vec<(String, u64)>>
 where
     AR: AsyncReadExt + AsyncSeekExt + Unpin,
 {
-    reader.seek(io::SeekFrom::Start(block.offset)).await?;
-    let compressed = read_len_async(&mut reader, block.comp_size as usize).await?;
-    let mut uncompressed = uncompress(compressed.into())?;
-    let mut data = uncompressed.split_off(key.offset as usize);
-    data.truncate(key.len as usize);
-    Ok(data)
+    reader.seek(io::SeekFrom::Start(index.offset)).await?;
+    let compressed = read_len_async(&mut reader, index.comp_size as usize).await?;
+    header.decode_key_block(compressed.into(), index, block)
+}
+
+// The blocks which the record spans, and the range of the record in each uncompressed block
+fn record_pieces(
+    key: &MDictRecordIndex,
+    blocks: &[MDictRecordBlockIndex],
+) -> MDictResult<Vec<(usize, Range<usize>)>> {
+    let mut pieces = Vec::new();
+    let mut block = to_usize(key.block, "Record block")?;
+    let mut start = key.offset;
+    let mut left = key.len;
+    loop {
+        let index = blocks.get(block).ok_or_else(|| {
+            MDictError::Malformed(format!("Record block {} is out of record blocks", block))
+        })?;
+        if index.dropped {
+            return Err(MDictError::DroppedBlock {
+                kind: MDictBlockKind::RecordBlock,
+                index: block,
+            });
+        }
+        let len = left.min(index.uncomp_size.saturating_sub(start));
+        let range = to_usize(start, "Record offset")?..to_usize(start + len, "Record offset")?;
+        pieces.push((block, range));
+        left -= len;
+        if left == 0 {
+            return Ok(pieces);
+        }
+        block += 1;
+        start = 0;
+    }
+}
+
+// uncompress the `index`th record block
+fn uncompress_record_block(
+    compressed: &[u8],
+    block: &MDictRecordBlockIndex,
+    index: usize,
+) -> MDictResult<Bytes> {
+    let comp_size = compressed.len();
+    let uncompressed = uncompress(
+        compressed,
+        to_usize(block.uncomp_size, "Record block size")?,
+        block.version,
+        MDictBlockKind::RecordBlock,
+        index,
+    )?;
+    info!(
+        "uncompress record block {} -> {}",
+        comp_size,
+        uncompressed.len()
+    );
+    Ok(uncompressed)
+}
+
+// uncompress the `index`th record block and check its size
+fn check_record_block(
+    compressed: &[u8],
+    block: &MDictRecordBlockIndex,
+    index: usize,
+) -> MDictResult<()> {
+    let uncompressed = uncompress_record_block(compressed, block, index)?;
+    check_eq(
+        uncompressed.len() as u64,
+        block.uncomp_size,
+        "Size of uncompressed content",
+    )
+}
+
+// take the part of record out of the uncompressed record block
+fn record_piece(uncompressed: Bytes, range: Range<usize>) -> MDictResult<Bytes> {
+    check_len(uncompressed.len(), range.end)?;
+    Ok(uncompressed.slice(range))
+}
+
+// Join the parts of a record, a record in one block is returned without copying
+fn join_pieces(mut pieces: Vec<Bytes>) -> Bytes {
+    if pieces.len() == 1 {
+        return pieces.pop().unwrap();
+    }
+    pieces.concat().into()
 }
@@ -0,0 +1,396 @@
+// LZO1X compression of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// The format is described in Documentation/staging/lzo.rst of Linux. The compressor is LZO1X-1
+// like `lzo1x_1_compress` of minilzo, and the decompressor accepts the output of all LZO1X
+// compressors.
+
+const M2_MAX_LEN: usize = 8;
+const M3_MAX_LEN: usize = 33;
+const M4_MAX_LEN: usize = 9;
+const M2_MAX_OFFSET: usize = 0x0800;
+const M3_MAX_OFFSET: usize = 0x4000;
+const M4_MAX_OFFSET: usize = 0xbfff;
+const M3_MARKER: u8 = 32;
+const M4_MARKER: u8 = 16;
+
+// Size of the dictionary of the compressor, which maps 4 bytes to the last position they appear
+const D_BITS: u32 = 13;
+const D_SIZE: usize = 1 << D_BITS;
+
+/// Decompress a LZO1X stream into at most `size` bytes.
+///
+/// Fails if the stream is corrupted, or the output would be larger than `size`.
+pub(crate) fn decompress(src: &[u8], size: usize) -> Result<Vec<u8>, &'static str> {
+    let mut out = Vec::with_capacity(size);
+    let mut ip = 0;
+    // the number of literals copied after the last instruction, 4 means a run of more than 3
+    let mut state = 0;
+    let byte = |ip: usize| src.get(ip).copied().ok_or("input overrun");
+    let first = byte(0)?;
+    if first > 17 {
+        ip += 1;
+        let t = (first - 17) as usize;
+        copy_literals(src, &mut ip, &mut out, t, size)?;
+        state = if t < 4 { t } else { 4 };
+    }
+    loop {
+        let t = byte(ip)? as usize;
+        ip += 1;
+        // the length and distance of the match
+        let (len, distance, next) = if t < 16 {
+            if state == 0 {
+                // a run of literals
+                let len = if t == 0 {
+                    read_length(src, &mut ip, 15)?
+                } else {
+                    t
+                };
+                copy_literals(src, &mut ip, &mut out, len + 3, size)?;
+                state = 4;
+                continue;
+            }
+            let low = byte(ip)? as usize;
+            ip += 1;
+            if state != 4 {
+                // a match of 2 bytes within 1 KiB after a few literals
+                (2, 1 + (t >> 2) + (low << 2), t & 3)
+            } else {
+                // a match of 3 bytes after a run of literals
+                (3, 1 + M2_MAX_OFFSET + (t >> 2) + (low << 2), t & 3)
+            }
+        } else if t >= 64 {
+            let low = byte(ip)? as usize;
+            ip += 1;
+            ((t >> 5) + 1, 1 + ((t >> 2) & 7) + (low << 3), t & 3)
+        } else if t >= 32 {
+            let len = match t & 31 {
+                0 => read_length(src, &mut ip, 31)?,
+                len => len,
+            };
+            let next = read_le16(src, &mut ip)?;
+            (len + 2, 1 + (next >> 2), next & 3)
+        } else {
+            let len = match t & 7 {
+                0 => read_length(src, &mut ip, 7)?,
+                len => len,
+            };
+            let next = read_le16(src, &mut ip)?;
+            let distance = ((t & 8) << 11) + (next >> 2);
+            if distance == 0 {
+                // the end of stream
+                if len != 1 {
+                    return Err("corrupted end of stream");
+                }
+                if ip != src.len() {
+                    return Err("input not consumed");
+                }
+                return Ok(out);
+            }
+            (len + 2, distance + 0x4000, next & 3)
+        };
+        copy_match(&mut out, distance, len, size)?;
+        copy_literals(src, &mut ip, &mut out, next, size)?;
+        state = next;
+    }
+}
+
+// Read the length which is extended by zero bytes, `base` is the length of the zero length
+fn read_length(src: &[u8], ip: &mut usize, base: usize) -> Result<usize, &'static str> {
+    let mut len = base;
+    loop {
+        let b = *src.get(*ip).ok_or("input overrun")?;
+        *ip += 1;
+        if b != 0 {
+            return len.checked_add(b as usize).ok_or("length overflow");
+        }
+        len = len.checked_add(255).ok_or("length overflow")?;
+    }
+}
+
+fn read_le16(src: &[u8], ip: &mut usize) -> Result<usize, &'static str> {
+    let bytes = src.get(*ip..*ip + 2).ok_or("input overrun")?;
+    *ip += 2;
+    Ok(bytes[0] as usize | (bytes[1] as usize) << 8)
+}
+
+fn copy_literals(
+    src: &[u8],
+    ip: &mut usize,
+    out: &mut Vec<u8>,
+    len: usize,
+    size: usize,
+) -> Result<(), &'static str> {
+    let literals = src
+        .get(*ip..)
+        .and_then(|src| src.get(..len))
+        .ok_or("input overrun")?;
+    if len > size - out.len() {
+        return Err("output overrun");
+    }
+    out.extend_from_slice(literals);
+    *ip += len;
+    Ok(())
+}
+
+fn copy_match(
+    out: &mut Vec<u8>,
+    distance: usize,
+    len: usize,
+    size: usize,
+) -> Result<(), &'static str> {
+    if distance > out.len() {
+        return Err("lookbehind overrun");
+    }
+    if len > size - out.len() {
+        return Err("output overrun");
+    }
+    let start = out.len() - distance;
+    if distance >= len {
+        out.extend_from_within(start..start + len);
+    } else {
+        // the match overlaps the bytes it produces
+        for i in start..start + len {
+            out.push(out[i]);
+        }
+    }
+    Ok(())
+}
+
+/// Compress `src` into a LZO1X stream by the LZO1X-1 algorithm.
+pub(crate) fn compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len() + src.len() / 16 + 64 + 3);
+    let mut dict = vec![0u16; D_SIZE];
+    let mut start = 0;
+    // the literals which are not written at the end of the last chunk
+    let mut pending = 0;
+    // the offsets in the dictionary are kept in 16 bits, so compress chunks of at most 48 KiB
+    while src.len() - start > 20 {
+        let len = (src.len() - start).min(M4_MAX_OFFSET + 1);
+        dict.iter_mut().for_each(|d| *d = 0);
+        pending = compress_chunk(src, start, len, &mut out, pending, &mut dict);
+        start += len;
+    }
+    pending += src.len() - start;
+    if pending > 0 {
+        let literals = &src[src.len() - pending..];
+        if out.is_empty() && pending <= 238 {
+            out.push(17 + pending as u8);
+        } else {
+            write_literals_len(&mut out, pending);
+        }
+        out.extend_from_slice(literals);
+    }
+    out.extend_from_slice(&[M4_MARKER | 1, 0, 0]);
+    out
+}
+
+// Compress `src[start..start + len]`, with `pending` literals before it which are not written.
+// Returns the number of literals at the end which are not written.
+fn compress_chunk(
+    src: &[u8],
+    start: usize,
+    len: usize,
+    out: &mut Vec<u8>,
+    pending: usize,
+    dict: &mut [u16],
+) -> usize {
+    let end = start + len;
+    let ip_end = end - 20;
+    let read_le32 = |i: usize| u32::from_le_bytes([src[i], src[i + 1], src[i + 2], src[i + 3]]);
+    // the start of the literals which are not written
+    let mut ii = start - pending;
+    let mut ip = start + 4usize.saturating_sub(pending);
+    loop {
+        // skip faster in data which doesn't compress
+        ip += 1 + ((ip - ii) >> 5);
+        if ip >= ip_end {
+            break;
+        }
+        let dv = read_le32(ip);
+        let hash = (dv.wrapping_mul(0x1824_429d) >> (32 - D_BITS)) as usize & (D_SIZE - 1);
+        let m_pos = start + dict[hash] as usize;
+        dict[hash] = (ip - start) as u16;
+        if dv != read_le32(m_pos) {
+            continue;
+        }
+
+        let literals = ip - ii;
+        if literals > 0 {
+            write_literals_len(out, literals);
+            out.extend_from_slice(&src[ii..ip]);
+        }
+
+        let mut m_len = 4;
+        while ip + m_len < ip_end && src[m_pos + m_len] == src[ip + m_len] {
+            m_len += 1;
+        }
+        let m_off = ip - m_pos;
+        ip += m_len;
+        ii = ip;
+        if m_len <= M2_MAX_LEN && m_off <= M2_MAX_OFFSET {
+            let m_off = m_off - 1;
+            out.push((((m_len - 1) << 5) | ((m_off & 7) << 2)) as u8);
+            out.push((m_off >> 3) as u8);
+        } else if m_off <= M3_MAX_OFFSET {
+            let m_off = m_off - 1;
+            write_match_len(out, M3_MARKER, m_len, M3_MAX_LEN);
+            out.push((m_off << 2) as u8);
+            out.push((m_off >> 6) as u8);
+        } else {
+            let m_off = m_off - 0x4000;
+            let marker = M4_MARKER | ((m_off >> 11) & 8) as u8;
+            write_match_len(out, marker, m_len, M4_MAX_LEN);
+            out.push((m_off << 2) as u8);
+            out.push((m_off >> 6) as u8);
+        }
+    }
+    end - ii
+}
+
+// Write the instruction of a run of literals before they are copied
+fn write_literals_len(out: &mut Vec<u8>, len: usize) {
+    if len <= 3 {
+        // in the low bits of the distance of the last match
+        let last = out.len() - 2;
+        out[last] |= len as u8;
+    } else if len <= 18 {
+        out.push((len - 3) as u8);
+    } else {
+        out.push(0);
+        write_zero_extended(out, len - 18);
+    }
+}
+
+// Write the marker of a match with its length, which is extended by zero bytes if it exceeds `max`
+fn write_match_len(out: &mut Vec<u8>, marker: u8, len: usize, max: usize) {
+    if len <= max {
+        out.push(marker | (len - 2) as u8);
+    } else {
+        out.push(marker);
+        write_zero_extended(out, len - max);
+    }
+}
+
+fn write_zero_extended(out: &mut Vec<u8>, mut len: usize) {
+    while len > 255 {
+        len -= 255;
+        out.push(0);
+    }
+    out.push(len as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytes which never repeat in 4 bytes within 251 bytes
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn short_input_vector() {
+        // the output of `lzo1x_1_compress` of minilzo for an input of at most 20 bytes
+        let stream = b"\x16hello\x11\x00\x00";
+        assert_eq!(compress(b"hello"), stream);
+        assert_eq!(decompress(stream, 5).unwrap(), b"hello");
+        assert_eq!(compress(b""), b"\x11\x00\x00");
+        assert_eq!(decompress(b"\x11\x00\x00", 0).unwrap(), b"");
+    }
+
+    #[test]
+    fn instructions_vector() {
+        let stream = [
+            // 3 literals at the start of stream
+            &b"\x14abc"[..],
+            // M2: 3 bytes at distance 3
+            b"\x48\x00",
+            // M3: 6 bytes at distance 3 overlapping its output, then 2 literals
+            b"\x24\x0a\x00xy",
+            // a match of 2 bytes at distance 2 after a few literals
+            b"\x04\x00",
+            b"\x11\x00\x00",
+        ]
+        .concat();
+        assert_eq!(decompress(&stream, 16).unwrap(), b"abcabcabcabcxyxy");
+    }
+
+    #[test]
+    fn long_literals_and_far_match_vector() {
+        // 16400 literals whose length is extended by 64 zero bytes, and M4: 9 bytes at distance
+        // 16400, which is beyond the distance of M3
+        let literals = pattern(16400);
+        let mut stream = vec![0; 65];
+        stream.push(62);
+        stream.extend_from_slice(&literals);
+        stream.extend_from_slice(b"\x17\x40\x00\x11\x00\x00");
+        let out = decompress(&stream, 16409).unwrap();
+        assert_eq!(out[..16400], literals[..]);
+        assert_eq!(out[16400..], literals[..9]);
+    }
+
+    #[test]
+    fn blocks_larger_than_64_kib() {
+        // repeated beyond the distance of M3, in more than a chunk of the compressor
+        let data: Vec<u8> = pattern(20000).repeat(5);
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len() / 2);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        // incompressible data is expanded a little
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect();
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len() + data.len() / 16 + 64 + 3);
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn truncated_input() {
+        let data = pattern(2000).repeat(3);
+        let compressed = compress(&data);
+        for len in 0..compressed.len() {
+            assert!(decompress(&compressed[..len], data.len()).is_err(), "{}", len);
+        }
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+    }
+
+    #[test]
+    fn corrupted_input() {
+        let data = pattern(2000).repeat(3);
+        let compressed = compress(&data);
+        // any byte may be corrupted, the result is an error or some output, but never a panic
+        for i in 0..compressed.len() {
+            for byte in [0, 1, 0x11, 0x7f, 0xff] {
+                let mut corrupted = compressed.clone();
+                corrupted[i] = byte;
+                let _ = decompress(&corrupted, data.len());
+            }
+        }
+
+        let error = |stream: &[u8], size| decompress(stream, size).unwrap_err();
+        assert_eq!(error(b"", 0), "input overrun");
+        // a match before the start of output
+        assert_eq!(error(b"\x14abc\x48\x01\x11\x00\x00", 6), "lookbehind overrun");
+        // the output is larger than expected
+        assert_eq!(error(b"\x16hello\x11\x00\x00", 4), "output overrun");
+        assert_eq!(error(b"\x14abc\x48\x00\x11\x00\x00", 5), "output overrun");
+        assert_eq!(error(b"\x16hello\x12\x00\x00", 5), "corrupted end of stream");
+        assert_eq!(error(b"\x16hello\x11\x00\x00\x00", 5), "input not consumed");
+    }
+}
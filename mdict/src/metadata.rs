@@ -0,0 +1,239 @@
+// Metadata of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::fmt;
+
+// The placeholder of `Title` left by MdxBuilder when the title is not given
+const TITLE_PLACEHOLDER: &str = "Title (No HTML code allowed)";
+
+// The style marker in records of compact dictionaries, such as `` `1` ``
+static STYLE_MARKER: Lazy<Regex> = Lazy::new(|| Regex::new(r"`(\d+)`").unwrap());
+
+/// A date without time, such as the `CreationDate` of a dictionary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MDictDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl MDictDate {
+    // MdxBuilder writes the date like "2020-6-1", some dictionaries use "2020.06.01" or "2020/6/1",
+    // so take the first three numbers whatever the separator is.
+    fn parse(s: &str) -> Option<MDictDate> {
+        let mut numbers = s
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|n| !n.is_empty());
+        let year = numbers.next()?.parse().ok()?;
+        let month = numbers.next()?.parse().ok()?;
+        let day = numbers.next()?.parse().ok()?;
+        if (1..=12).contains(&month) && (1..=31).contains(&day) {
+            Some(MDictDate { year, month, day })
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for MDictDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// The format of records in a mdx file, from the `Format` attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MDictRecordFormat {
+    Html,
+    Text,
+    /// Any other format, as it is written in the header.
+    Other(String),
+}
+
+/// What the registration code of a locked dictionary is bound to, from the `RegisterBy` attribute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MDictRegisterBy {
+    Email,
+    DeviceId,
+}
+
+/// The begin and end HTML of a style.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MDictStyle {
+    pub begin: String,
+    pub end: String,
+}
+
+/// The style table parsed from the `StyleSheet` attribute.
+///
+/// The records of a dictionary with `Compact="Yes"` mark the text with styles by their number
+/// like `` `1`text`2`text ``, rather than repeating the HTML of the styles in every record.
+#[derive(Clone, Debug, Default)]
+pub struct MDictStyleSheet {
+    styles: HashMap<u32, MDictStyle>,
+}
+
+impl MDictStyleSheet {
+    /// Parse the `StyleSheet` attribute, which has 3 lines per style:
+    /// the number of style (1-255), the begin HTML and the end HTML.
+    pub fn parse(s: &str) -> MDictStyleSheet {
+        let lines: Vec<&str> = s.lines().collect();
+        let styles = lines
+            .chunks(3)
+            .filter_map(|style| {
+                let number = style[0].trim().parse().ok()?;
+                let begin = style.get(1).copied().unwrap_or_default().to_owned();
+                let end = style.get(2).copied().unwrap_or_default().to_owned();
+                Some((number, MDictStyle { begin, end }))
+            })
+            .collect();
+        MDictStyleSheet { styles }
+    }
+
+    /// Get the style of the given number.
+    pub fn get(&self, number: u32) -> Option<&MDictStyle> {
+        self.styles.get(&number)
+    }
+
+    /// Returns true if there is no style.
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+
+    /// Expand the style markers in a decoded record into HTML.
+    ///
+    /// The text after a marker is wrapped by the begin and end HTML of the style, until the next
+    /// marker or the end of the record. A marker of unknown style is removed.
+    pub fn expand(&self, record: &str) -> String {
+        let mut result = String::with_capacity(record.len());
+        let mut style: Option<&MDictStyle> = None;
+        let mut last = 0;
+        for marker in STYLE_MARKER.captures_iter(record) {
+            let m = marker.get(0).unwrap();
+            self.push_styled(&mut result, style, &record[last..m.start()]);
+            style = self.style_of(&marker);
+            last = m.end();
+        }
+        self.push_styled(&mut result, style, &record[last..]);
+        result
+    }
+
+    fn style_of(&self, marker: &Captures) -> Option<&MDictStyle> {
+        marker[1].parse().ok().and_then(|n| self.get(n))
+    }
+
+    // Same as `_substitute_stylesheet` of readmdict, the end of style is put before
+    // the line break at the end of text
+    fn push_styled(&self, result: &mut String, style: Option<&MDictStyle>, text: &str) {
+        match style {
+            None => result.push_str(text),
+            Some(style) if text.ends_with('\n') => {
+                result.push_str(&style.begin);
+                result.push_str(text.trim_end());
+                result.push_str(&style.end);
+                result.push_str("\r\n");
+            }
+            Some(style) => {
+                result.push_str(&style.begin);
+                result.push_str(text);
+                result.push_str(&style.end);
+            }
+        }
+    }
+}
+
+/// Typed view of the well-known attributes of a MDict header.
+///
+/// The attributes are parsed once when the header is read, see [`MDictHeader::metadata`](crate::MDictHeader::metadata).
+/// Any attribute which is missing or can't be parsed is `None`, or `false` for flags.
+#[derive(Clone, Debug, Default)]
+pub struct MDictMetadata {
+    /// `Title`, the placeholder left by MdxBuilder is ignored.
+    pub title: Option<String>,
+    /// `Description`, which may contain HTML.
+    pub description: Option<String>,
+    /// `CreationDate`.
+    pub creation_date: Option<MDictDate>,
+    /// `GeneratedByEngineVersion`, the version of MDict which created this file.
+    pub engine_version: Option<String>,
+    /// `RequiredEngineVersion`, the lowest version of MDict which can read this file.
+    pub required_engine_version: Option<String>,
+    /// `Format` of records of a mdx file.
+    pub format: Option<MDictRecordFormat>,
+    /// `KeyCaseSensitive`, keywords are matched case-insensitively if not set.
+    pub key_case_sensitive: bool,
+    /// `StripKey`, punctuation and spaces in keywords are ignored when matching if set.
+    pub strip_key: bool,
+    /// `Compact`, records are compressed by the stylesheet if set.
+    pub compact: bool,
+    /// `Left2Right`, the text of records is written from left to right.
+    pub left_to_right: bool,
+    /// `DataSourceFormat`, the format of the source file used to build this dictionary.
+    pub data_source_format: Option<String>,
+    /// `RegisterBy`, set if this is a registration-locked dictionary.
+    pub register_by: Option<MDictRegisterBy>,
+    /// `StyleSheet`, the styles used by the records if `compact` is set.
+    pub stylesheet: MDictStyleSheet,
+}
+
+impl MDictMetadata {
+    pub(crate) fn new(attrs: &HashMap<String, String>) -> MDictMetadata {
+        let get = |key: &str| {
+            attrs
+                .get(key)
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.to_owned())
+        };
+        let flag = |key: &str| {
+            attrs.get(key).is_some_and(|v| {
+                let v = v.trim();
+                v.eq_ignore_ascii_case("yes") || v.eq_ignore_ascii_case("true") || v == "1"
+            })
+        };
+        MDictMetadata {
+            title: get("Title").filter(|t| t != TITLE_PLACEHOLDER),
+            description: get("Description"),
+            creation_date: get("CreationDate").and_then(|d| MDictDate::parse(&d)),
+            engine_version: get("GeneratedByEngineVersion"),
+            required_engine_version: get("RequiredEngineVersion"),
+            format: get("Format").map(|f| match f.to_ascii_lowercase().as_str() {
+                "html" => MDictRecordFormat::Html,
+                "text" => MDictRecordFormat::Text,
+                _ => MDictRecordFormat::Other(f),
+            }),
+            key_case_sensitive: flag("KeyCaseSensitive"),
+            strip_key: flag("StripKey"),
+            // some old dictionaries misspell it as `Compat`
+            compact: flag("Compact") || flag("Compat"),
+            left_to_right: flag("Left2Right"),
+            data_source_format: get("DataSourceFormat"),
+            register_by: get("RegisterBy").and_then(|r| match r.to_ascii_lowercase().as_str() {
+                "email" => Some(MDictRegisterBy::Email),
+                "deviceid" => Some(MDictRegisterBy::DeviceId),
+                _ => None,
+            }),
+            stylesheet: attrs
+                .get("StyleSheet")
+                .map(|s| MDictStyleSheet::parse(s))
+                .unwrap_or_default(),
+        }
+    }
+}
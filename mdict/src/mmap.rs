@@ -0,0 +1,106 @@
+// Memory-mapped Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::MDictSource;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{self, Cursor};
+use std::ops::Deref;
+use std::path::Path;
+
+/// A mdx or mdd file mapped into memory, only available with the "mmap" crate feature.
+///
+/// The record blocks are sliced from the mapping by [`lookup_slice`](crate::lookup_slice),
+/// without opening, seeking and reading the file for every lookup. The file can also be parsed
+/// by [`MDictIndex::new`](crate::MDictIndex::new) through [`MDictMmap::reader`].
+///
+/// ## Example
+///
+/// ```no_run
+/// use mdict::*;
+///
+/// fn main() -> std::io::Result<()> {
+///     let file = unsafe { MDictMmap::open("test.mdx")? };
+///     let mut mdict = MDictIndex::new(file.reader(), MDictMode::Mdx)?;
+///     let (blocks, keys) = mdict.make_index()?;
+///     for (_, idx) in keys.iter() {
+///         let record = lookup_slice(&file, idx, &blocks)?;
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct MDictMmap {
+    map: Mmap,
+}
+
+impl MDictMmap {
+    /// Map the file at `path` into memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated by this process or others while it is mapped.
+    /// Otherwise the content read from the mapping may change at any time, and reading the
+    /// truncated part of the file kills the process with `SIGBUS` on unix.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<MDictMmap> {
+        let file = File::open(path)?;
+        Self::map(&file)
+    }
+
+    /// Map an opened file into memory.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`MDictMmap::open`].
+    pub unsafe fn map(file: &File) -> io::Result<MDictMmap> {
+        let map = Mmap::map(file)?;
+        Ok(MDictMmap { map })
+    }
+
+    /// A reader of the mapped file, which can be passed to [`MDictIndex::new`](crate::MDictIndex::new)
+    /// or [`MDictHeader::new`](crate::MDictHeader::new).
+    pub fn reader(&self) -> Cursor<&[u8]> {
+        Cursor::new(&self.map)
+    }
+}
+
+impl Deref for MDictMmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl AsRef<[u8]> for MDictMmap {
+    fn as_ref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl MDictSource for MDictMmap {
+    fn size(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.map[..].read_exact_at(buf, offset)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(&self.map)
+    }
+}
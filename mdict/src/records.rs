@@ -0,0 +1,199 @@
+// Sequential reading of records of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    join_pieces, map_blocks, read_len, record_piece, record_pieces, to_usize,
+    uncompress_record_block, MDictHeader, MDictIndex, MDictRecordBlockIndex, MDictRecordIndex,
+    MDictResult,
+};
+use bytes::Bytes;
+use std::io::{self, prelude::*, BufReader};
+use std::vec;
+
+#[cfg(feature = "async")]
+use tokio::sync::mpsc;
+
+// Number of records sent by `MDictIndex::records_async` before the receiver takes them
+#[cfg(feature = "async")]
+const RECORD_CHANNEL_SIZE: usize = 256;
+
+/// Iterator of all keywords and their records of a file, created by [`MDictIndex::records`].
+///
+/// The records are in the order they are stored in the file, which is usually the order of
+/// keywords. Each item is the keyword and its raw record, use [`MDictRecords::decoded`] to decode
+/// the records of a mdx file. The record is a slice of the uncompressed record block, so keeping it
+/// keeps the whole block in memory.
+///
+/// With the "parallel" crate feature, the record blocks are read in batches of one block per
+/// thread of rayon, and decompressed across the thread pool.
+pub struct MDictRecords<'a, R: Read + Seek> {
+    file: &'a mut BufReader<R>,
+    header: &'a MDictHeader,
+    blocks: Vec<MDictRecordBlockIndex>,
+    keys: vec::IntoIter<(String, MDictRecordIndex)>,
+    // the last batch of uncompressed record blocks or their errors, and their numbers
+    batch: Vec<(usize, MDictResult<Bytes>)>,
+    // the last record, keywords sharing one record come one after another
+    record: Option<(MDictRecordIndex, Bytes)>,
+}
+
+impl<R: Read + Seek> MDictIndex<R> {
+    /// Iterate all keywords and their records.
+    ///
+    /// Each record block is read and decompressed only once, rather than once for every keyword
+    /// in it like [`lookup`](crate::lookup) do, so this is the way to export or index the whole
+    /// dictionary. The index of records is built by [`MDictIndex::make_index`] first.
+    /// The record blocks are decompressed across the thread pool of rayon with the "parallel"
+    /// crate feature, see [`MDictRecords`].
+    ///
+    /// # Error
+    ///
+    /// This function returns [`MDictError`](crate::MDictError) like [`MDictIndex::make_index`] do.
+    /// The errors of reading and decompressing record blocks are returned by the iterator for every
+    /// keyword in the failed block, without reading the block again, and the iterator goes on with
+    /// the records in the following blocks.
+    pub fn records(&mut self) -> MDictResult<MDictRecords<'_, R>> {
+        let (blocks, keys) = self.make_index()?;
+        Ok(MDictRecords {
+            file: &mut self.file,
+            header: &self.header,
+            blocks,
+            keys: keys.into_iter(),
+            batch: Vec::new(),
+            record: None,
+        })
+    }
+
+    #[cfg(feature = "async")]
+    /// Asynchronous version of [`MDictIndex::records`].
+    ///
+    /// The records are read in the blocking thread pool of tokio, and sent to the returned receiver
+    /// one by one, so this must be called in the context of a tokio runtime. The errors of
+    /// [`MDictIndex::make_index`] are sent as the first item. The reading stops when the receiver
+    /// is dropped.
+    ///
+    /// This consumes the `MDictIndex`, and it is only available with the "async" crate feature.
+    pub fn records_async(self) -> mpsc::Receiver<MDictResult<(String, Bytes)>>
+    where
+        R: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(RECORD_CHANNEL_SIZE);
+        tokio::task::spawn_blocking(move || {
+            let mut index = self;
+            let records = match index.records() {
+                Ok(records) => records,
+                Err(e) => {
+                    let _ = sender.blocking_send(Err(e));
+                    return;
+                }
+            };
+            for record in records {
+                if sender.blocking_send(record).is_err() {
+                    return;
+                }
+            }
+        });
+        receiver
+    }
+}
+
+impl<'a, R: Read + Seek> MDictRecords<'a, R> {
+    /// The header of this file.
+    pub fn header(&self) -> &'a MDictHeader {
+        self.header
+    }
+
+    /// Decode the records by [`MDictHeader::decode_record`], for mdx files.
+    pub fn decoded(self) -> impl Iterator<Item = MDictResult<(String, String)>> + 'a {
+        let header = self.header;
+        self.map(move |record| {
+            let (key, record) = record?;
+            Ok((key, header.decode_record(record)?))
+        })
+    }
+
+    // The uncompressed `index`th record block. The last batch is kept for the following records,
+    // a block which fails is not read again and its error is returned for every record in it.
+    fn block(&mut self, index: usize) -> MDictResult<Bytes> {
+        if !self.batch.iter().any(|(i, _)| *i == index) {
+            self.read_batch(index);
+        }
+        match self.batch.iter().find(|(i, _)| *i == index) {
+            Some((_, Ok(block))) => Ok(block.clone()),
+            Some((_, Err(e))) => Err(e.duplicate()),
+            None => unreachable!("record block {} is read in the batch", index),
+        }
+    }
+
+    // Read the batch of record blocks from the `start`th block and decompress them
+    fn read_batch(&mut self, start: usize) {
+        let end = (start + batch_len()).min(self.blocks.len());
+        let (file, blocks) = (&mut *self.file, &self.blocks);
+        let mut batch = Vec::with_capacity(end - start);
+        for (i, block) in blocks.iter().enumerate().take(end).skip(start) {
+            let compressed = file
+                .seek(io::SeekFrom::Start(block.offset))
+                .map_err(Into::into)
+                .and_then(|_| read_len(file, to_usize(block.comp_size, "Record block size")?));
+            batch.push((i, compressed));
+        }
+        self.batch = map_blocks(batch, |(i, compressed)| {
+            let uncompressed =
+                compressed.and_then(|data| uncompress_record_block(&data, &blocks[i], i));
+            (i, uncompressed)
+        });
+    }
+
+    fn record(&mut self, index: &MDictRecordIndex) -> MDictResult<Bytes> {
+        if let Some((last, record)) = &self.record {
+            if last == index {
+                return Ok(record.clone());
+            }
+        }
+        let mut pieces = Vec::new();
+        for (i, range) in record_pieces(index, &self.blocks)? {
+            pieces.push(record_piece(self.block(i)?, range)?);
+        }
+        let record = join_pieces(pieces);
+        self.record = Some((*index, record.clone()));
+        Ok(record)
+    }
+}
+
+// Number of record blocks read in a batch, one for each thread of rayon
+#[cfg(feature = "parallel")]
+fn batch_len() -> usize {
+    rayon::current_num_threads()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn batch_len() -> usize {
+    1
+}
+
+impl<R: Read + Seek> Iterator for MDictRecords<'_, R> {
+    type Item = MDictResult<(String, Bytes)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, index) = self.keys.next()?;
+        Some(self.record(&index).map(|record| (key, record)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
@@ -0,0 +1,328 @@
+// Sources of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use bytes::Bytes;
+use std::fs::File;
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::sync::Arc;
+
+/// The bytes of a mdx or mdd file, read by their position.
+///
+/// Positioned reads don't move a cursor, so a source can be shared by lookups in many threads.
+/// The file may be a buffer in memory ([`Vec<u8>`], [`Bytes`]), a file ([`MDictFileSource`]),
+/// or a part of a larger file ([`MDictSubSource`]), such as a dictionary embedded in a bundle.
+///
+/// The file is parsed by [`MDictIndex::new`](crate::MDictIndex::new) through
+/// [`MDictSourceReader`], and the records are read by [`lookup_source`](crate::lookup_source).
+pub trait MDictSource: Send + Sync {
+    /// Size of the file in bytes.
+    fn size(&self) -> u64;
+
+    /// Read exactly `buf.len()` bytes at `offset` from the start of the file.
+    ///
+    /// # Error
+    ///
+    /// Returns an error of kind [`UnexpectedEof`](io::ErrorKind::UnexpectedEof)
+    /// if the file ends before the buffer is filled.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+    /// The whole file if it is in memory, then the blocks are sliced from it rather than copied.
+    fn as_slice(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+// Copy `buf.len()` bytes at `offset` of `data`
+fn read_slice_at(data: &[u8], buf: &mut [u8], offset: u64) -> io::Result<()> {
+    let end = offset.checked_add(buf.len() as u64);
+    match end {
+        Some(end) if end <= data.len() as u64 => {
+            buf.copy_from_slice(&data[offset as usize..end as usize]);
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Read beyond the end of source",
+        )),
+    }
+}
+
+impl MDictSource for [u8] {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        read_slice_at(self, buf, offset)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl MDictSource for Vec<u8> {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        read_slice_at(self, buf, offset)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl MDictSource for Bytes {
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        read_slice_at(self, buf, offset)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(self)
+    }
+}
+
+impl<S: MDictSource + ?Sized> MDictSource for &S {
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        (**self).as_slice()
+    }
+}
+
+impl<S: MDictSource + ?Sized> MDictSource for Box<S> {
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        (**self).as_slice()
+    }
+}
+
+impl<S: MDictSource + ?Sized> MDictSource for Arc<S> {
+    fn size(&self) -> u64 {
+        (**self).size()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        (**self).as_slice()
+    }
+}
+
+/// A file read by positioned reads of the operating system, without seeking.
+pub struct MDictFileSource {
+    file: File,
+    size: u64,
+}
+
+impl MDictFileSource {
+    /// Open the file at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MDictFileSource> {
+        MDictFileSource::new(File::open(path)?)
+    }
+
+    /// Read an opened file, the size of file is taken now.
+    pub fn new(file: File) -> io::Result<MDictFileSource> {
+        let size = file.metadata()?.len();
+        Ok(MDictFileSource { file, size })
+    }
+}
+
+impl MDictSource for MDictFileSource {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    #[cfg(unix)]
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(&self.file, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.file.seek_read(buf, offset) {
+                Ok(0) => break,
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if buf.is_empty() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Read beyond the end of source",
+            ))
+        }
+    }
+}
+
+/// A part of another source, such as a dictionary stored in a larger file.
+///
+/// The offsets in the dictionary are from the start of this part.
+pub struct MDictSubSource<S> {
+    source: S,
+    start: u64,
+    len: u64,
+}
+
+impl<S: MDictSource> MDictSubSource<S> {
+    /// The `len` bytes of `source` from `start`.
+    ///
+    /// # Error
+    ///
+    /// Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput)
+    /// if the part is beyond the end of `source`.
+    pub fn new(source: S, start: u64, len: u64) -> io::Result<MDictSubSource<S>> {
+        match start.checked_add(len) {
+            Some(end) if end <= source.size() => Ok(MDictSubSource { source, start, len }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Part is beyond the end of source",
+            )),
+        }
+    }
+
+    /// The source this part is taken from.
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: MDictSource> MDictSource for MDictSubSource<S> {
+    fn size(&self) -> u64 {
+        self.len
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        match offset.checked_add(buf.len() as u64) {
+            Some(end) if end <= self.len => self.source.read_exact_at(buf, self.start + offset),
+            _ => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Read beyond the end of source",
+            )),
+        }
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        let data = self.source.as_slice()?;
+        data.get(self.start as usize..(self.start + self.len) as usize)
+    }
+}
+
+/// A reader of a [`MDictSource`], to parse the file by [`MDictIndex::new`](crate::MDictIndex::new)
+/// or [`MDictHeader::new`](crate::MDictHeader::new).
+///
+/// ## Example
+///
+/// ```no_run
+/// use mdict::*;
+///
+/// fn main() -> std::io::Result<()> {
+///     let bundle = MDictFileSource::open("bundle.bin")?;
+///     // the dictionary is stored at 4096 of the bundle
+///     let source = MDictSubSource::new(bundle, 4096, 1 << 20)?;
+///     let mut mdict = MDictIndex::new(MDictSourceReader::new(&source), MDictMode::Mdx)?;
+///     let (blocks, keys) = mdict.make_index()?;
+///     for (_, idx) in keys.iter() {
+///         let record = lookup_source(&source, idx, &blocks)?;
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct MDictSourceReader<'a> {
+    source: &'a dyn MDictSource,
+    pos: u64,
+}
+
+impl<'a> MDictSourceReader<'a> {
+    /// A reader at the start of `source`.
+    pub fn new(source: &'a dyn MDictSource) -> MDictSourceReader<'a> {
+        MDictSourceReader { source, pos: 0 }
+    }
+}
+
+impl Read for MDictSourceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remain = self.source.size().saturating_sub(self.pos);
+        let len = (buf.len() as u64).min(remain) as usize;
+        // at or beyond the end, where sources may refuse to read even nothing
+        if len == 0 {
+            return Ok(0);
+        }
+        self.source.read_exact_at(&mut buf[..len], self.pos)?;
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for MDictSourceReader<'_> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(offset) => checked_offset(self.source.size(), offset),
+            io::SeekFrom::Current(offset) => checked_offset(self.pos, offset),
+        };
+        match pos {
+            Some(pos) => {
+                self.pos = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+fn checked_offset(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.unsigned_abs())
+    }
+}
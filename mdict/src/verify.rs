@@ -0,0 +1,292 @@
+// Verification of Octopus MDict Dictionary File (.mdx) and Resource File (.mdd)
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::{
+    check_blocks, check_record_block, record_indexes, record_pieces, MDictBlockKind, MDictError,
+    MDictFormatVersion, MDictIndex, MDictKeyBlockIndex, MDictRecordBlockIndex, MDictRecordIndex,
+    MDictRecordIndexes, MDictResult,
+};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::io::{self, prelude::*};
+
+/// A corrupted block found by [`MDictIndex::verify`].
+#[derive(Debug)]
+pub struct MDictBadBlock {
+    /// A keyword block or a record block
+    pub kind: MDictBlockKind,
+    /// Number of this block in the keyword blocks or the record blocks
+    pub index: usize,
+    /// Offset of this block from the start of the file
+    pub offset: u64,
+    /// Compressed size of this block
+    pub comp_size: u64,
+    /// Why this block is corrupted
+    pub error: MDictError,
+    /// The keywords whose records are in this record block, even partly.
+    ///
+    /// The keywords of a corrupted keyword block are lost, only its first and last keywords
+    /// from the index of keyword blocks are listed, which are unknown in v3. They follow the
+    /// keywords before the block, whose records are lost as their ends are unknown.
+    pub keywords: Vec<String>,
+}
+
+/// Result of [`MDictIndex::verify`].
+#[derive(Debug, Default)]
+pub struct MDictVerifyReport {
+    /// Number of keyword blocks
+    pub key_blocks: usize,
+    /// Number of record blocks
+    pub record_blocks: usize,
+    /// Number of keywords in the keyword blocks which are not corrupted
+    pub keywords: usize,
+    /// The corrupted keyword blocks and then the corrupted record blocks, in the order of the file
+    pub bad_blocks: Vec<MDictBadBlock>,
+    /// The keywords whose records are out of the record blocks,
+    /// with the offsets of the records from the start of the uncompressed record blocks
+    pub bad_records: Vec<(String, u64)>,
+}
+
+impl MDictVerifyReport {
+    /// Whether no corrupted block or record is found.
+    pub fn is_ok(&self) -> bool {
+        self.bad_blocks.is_empty() && self.bad_records.is_empty()
+    }
+}
+
+impl<R: Read + Seek> MDictIndex<R> {
+    /// Walk every keyword block and record block to find the corrupted ones.
+    ///
+    /// Lookups only check the blocks they read, so a file may serve broken records for a long
+    /// time before anyone notices. This decompresses every block to check its compression type,
+    /// checksum and sizes, decodes the keywords, and checks that the record of every keyword is
+    /// in the record blocks. Unlike [`MDictIndex::check_record_blocks`], it goes on after a
+    /// corrupted block and reports all of them with the keywords they affect.
+    ///
+    /// # Error
+    ///
+    /// This function returns [`MDictError`] if the header or index of blocks can't be read,
+    /// then the blocks can't be located.
+    pub fn verify(&mut self) -> MDictResult<MDictVerifyReport> {
+        let now = std::time::Instant::now();
+        let (report, _, _) = self.walk()?;
+        info!(
+            "Verify {} keyword blocks and {} record blocks in {:?}, {} corrupted",
+            report.key_blocks,
+            report.record_blocks,
+            now.elapsed(),
+            report.bad_blocks.len()
+        );
+        Ok(report)
+    }
+
+    // Build the index of the blocks which are not corrupted, see `MDictIndex::set_salvage`
+    pub(crate) fn salvage_index(&mut self) -> MDictResult<MDictRecordIndexes> {
+        let now = std::time::Instant::now();
+        let (report, mut blocks, indexes) = self.walk()?;
+        for bad in report.bad_blocks.iter() {
+            match bad.kind {
+                MDictBlockKind::RecordBlock => {
+                    blocks[bad.index].dropped = true;
+                    warn!(
+                        "Drop record block {} at {}: {}, records of {} keywords are lost: {:?}",
+                        bad.index,
+                        bad.offset,
+                        bad.error,
+                        bad.keywords.len(),
+                        bad.keywords
+                    );
+                }
+                _ => match (bad.keywords.first(), bad.keywords.last()) {
+                    (Some(first), Some(last)) => warn!(
+                        "Drop keywords block {} at {}: {}, keywords from {} to {} are lost",
+                        bad.index, bad.offset, bad.error, first, last
+                    ),
+                    _ => warn!(
+                        "Drop keywords block {} at {}: {}, its keywords are lost",
+                        bad.index, bad.offset, bad.error
+                    ),
+                },
+            }
+        }
+        for (key, offset) in report.bad_records.iter() {
+            warn!(
+                "Drop {} whose record at {} is out of record blocks",
+                key, offset
+            );
+        }
+        info!(
+            "Salvage {} keywords from {} keyword blocks and {} record blocks in {:?}, {} corrupted",
+            indexes.len(),
+            report.key_blocks,
+            report.record_blocks,
+            now.elapsed(),
+            report.bad_blocks.len()
+        );
+        Ok((blocks, indexes))
+    }
+
+    // Check every block, returns the report, the record blocks and the index of records
+    #[allow(clippy::type_complexity)]
+    fn walk(
+        &mut self,
+    ) -> MDictResult<(
+        MDictVerifyReport,
+        Vec<MDictRecordBlockIndex>,
+        Vec<(String, MDictRecordIndex)>,
+    )> {
+        let (key_blocks, record_blocks) = match self.header.version() {
+            MDictFormatVersion::V3 => {
+                // `make_key_index` decodes the keyword blocks of v3 and stops at a corrupted one
+                self.file.seek(io::SeekFrom::Start(self.key_block_offset))?;
+                let sections = self.read_sections()?;
+                let key_blocks = self.read_key_block_index_v3(&sections)?;
+                (key_blocks, self.read_record_blocks_v3(&sections)?)
+            }
+            _ => self.make_key_index()?,
+        };
+        let mut report = MDictVerifyReport {
+            key_blocks: key_blocks.len(),
+            record_blocks: record_blocks.len(),
+            ..Default::default()
+        };
+
+        let mut keys = Vec::new();
+        // the number of keywords before each corrupted keyword block
+        let mut gaps = Vec::new();
+        let header = &self.header;
+        let ranges: Vec<_> = key_blocks.iter().map(|b| (b.offset, b.comp_size)).collect();
+        check_blocks(
+            &mut self.file,
+            &ranges,
+            |i, compressed| header.decode_key_block(compressed.into(), &key_blocks[i], i),
+            |i, result| {
+                match result {
+                    Ok(words) => keys.extend(words),
+                    Err(error) => {
+                        gaps.push(keys.len());
+                        report
+                            .bad_blocks
+                            .push(bad_key_block(&key_blocks[i], i, error));
+                    }
+                }
+                Ok(())
+            },
+        )?;
+        report.keywords = keys.len();
+        // A record ends where the next record starts, so the record of the keyword before
+        // a corrupted keyword block would run over the records of the lost keywords. Its end is
+        // unknown, so it is lost with the keywords sharing its record. The corrupted keyword
+        // blocks are the first in the report.
+        let mut unknown_ends = HashMap::new();
+        for (i, &gap) in gaps.iter().enumerate() {
+            if gap > 0 {
+                unknown_ends.entry(keys[gap - 1].1).or_insert(i);
+            }
+        }
+
+        // the position of corrupted record blocks in the report
+        let mut bad = HashMap::new();
+        let ranges: Vec<_> = record_blocks
+            .iter()
+            .map(|b| (b.offset, b.comp_size))
+            .collect();
+        check_blocks(
+            &mut self.file,
+            &ranges,
+            |i, compressed| check_record_block(&compressed, &record_blocks[i], i),
+            |i, result| {
+                if let Err(error) = result {
+                    bad.insert(i, report.bad_blocks.len());
+                    report
+                        .bad_blocks
+                        .push(bad_record_block(&record_blocks[i], i, error));
+                }
+                Ok(())
+            },
+        )?;
+
+        let mut indexes = record_indexes(keys, &record_blocks, |key, offset| {
+            report.bad_records.push((key, offset));
+            Ok(())
+        })?;
+        if !unknown_ends.is_empty() {
+            let mut starts = Vec::with_capacity(record_blocks.len());
+            let mut start = 0u64;
+            for block in record_blocks.iter() {
+                starts.push(start);
+                start = start.saturating_add(block.uncomp_size);
+            }
+            let mut lost = vec![Vec::new(); gaps.len()];
+            indexes.retain(|(key, index)| {
+                let offset = starts[index.block as usize] + index.offset;
+                match unknown_ends.get(&offset) {
+                    Some(&i) => {
+                        lost[i].push(key.clone());
+                        false
+                    }
+                    None => true,
+                }
+            });
+            for (bad, lost) in report.bad_blocks.iter_mut().zip(lost) {
+                bad.keywords.splice(0..0, lost);
+            }
+        }
+        if !bad.is_empty() {
+            for (key, index) in indexes.iter() {
+                for (block, _) in record_pieces(index, &record_blocks)? {
+                    if let Some(&i) = bad.get(&block) {
+                        report.bad_blocks[i].keywords.push(key.clone());
+                    }
+                }
+            }
+        }
+        Ok((report, record_blocks, indexes))
+    }
+}
+
+fn bad_key_block(block: &MDictKeyBlockIndex, index: usize, error: MDictError) -> MDictBadBlock {
+    let mut keywords = Vec::new();
+    for word in [&block.first_word, &block.last_word].iter() {
+        if !word.is_empty() && !keywords.contains(*word) {
+            keywords.push(word.to_string());
+        }
+    }
+    MDictBadBlock {
+        kind: MDictBlockKind::KeyBlock,
+        index,
+        offset: block.offset,
+        comp_size: block.comp_size,
+        error,
+        keywords,
+    }
+}
+
+fn bad_record_block(
+    block: &MDictRecordBlockIndex,
+    index: usize,
+    error: MDictError,
+) -> MDictBadBlock {
+    MDictBadBlock {
+        kind: MDictBlockKind::RecordBlock,
+        index,
+        offset: block.offset,
+        comp_size: block.comp_size,
+        error,
+        keywords: Vec::new(),
+    }
+}
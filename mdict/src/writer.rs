@@ -0,0 +1,433 @@
+// Octopus MDict Dictionary File (.mdx) and Resource File (.mdd) Writer
+//
+// Copyright (C) 2020 韩朴宇 <w12101111@gmail.com>
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Writer of MDict v2 files, a rust rewrite of `writemdict.py` from
+//! [writemdict](https://github.com/zhansliu/writemdict).
+
+use crate::{encoding_unit_size, lzo, normalize_key, MDictMode};
+use bytes::BufMut;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use std::io::{self, prelude::*, Error, ErrorKind};
+
+// Same size as the blocks generated by MdxBuilder and writemdict
+const DEFAULT_BLOCK_SIZE: usize = 0x10000;
+
+/// Compression of the blocks written by [`MDictWriter`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MDictCompression {
+    /// Store the blocks without compression.
+    None,
+    /// LZO1X-1, faster to decompress than zlib but larger.
+    Lzo,
+    /// Zlib at level 6, the default.
+    #[default]
+    Zlib,
+}
+
+/// A builder to write a MDict file.
+///
+/// MDict expects keywords to be sorted, so the entries are sorted by their keywords normalized
+/// like [`MDictHeader::normalize_key`](crate::MDictHeader::normalize_key) when written, see
+/// [`MDictWriter::key_case_sensitive`] and [`MDictWriter::strip_key`]. Entries with the same
+/// normalized keyword keep the order they are added.
+///
+/// ## Example
+///
+/// ```no_run
+/// use std::fs::File;
+/// use mdict::*;
+///
+/// fn main() -> std::io::Result<()> {
+///     let mut writer = MDictWriter::new(MDictMode::Mdx);
+///     writer.title("Glossary").description("In-house glossary");
+///     writer.add_entry("mdict", "<b>MDict</b> dictionary format")?;
+///     writer.add_entry("rust", "<b>Rust</b> programming language")?;
+///     writer.write(File::create("glossary.mdx")?)?;
+///     Ok(())
+/// }
+/// ```
+pub struct MDictWriter {
+    mode: MDictMode,
+    encoding: &'static Encoding,
+    title: String,
+    description: String,
+    attrs: Vec<(String, String)>,
+    key_block_size: usize,
+    record_block_size: usize,
+    compression: MDictCompression,
+    key_case_sensitive: bool,
+    strip_key: bool,
+    // keywords and records, encoded when written
+    entries: Vec<(String, Record)>,
+}
+
+// The text of a mdx record, or the data of a mdd resource
+enum Record {
+    Text(String),
+    Data(Vec<u8>),
+}
+
+// An encoded keyword and its record
+type Entry<'a> = (&'a [u8], &'a [u8]);
+
+impl MDictWriter {
+    /// Create a empty writer for a mdx or mdd file.
+    ///
+    /// The encoding of a mdx file defaults to UTF-8.
+    pub fn new(mode: MDictMode) -> MDictWriter {
+        MDictWriter {
+            mode,
+            encoding: match mode {
+                MDictMode::Mdx => UTF_8,
+                MDictMode::Mdd => UTF_16LE,
+            },
+            title: String::new(),
+            description: String::new(),
+            attrs: Vec::new(),
+            key_block_size: DEFAULT_BLOCK_SIZE,
+            record_block_size: DEFAULT_BLOCK_SIZE,
+            compression: MDictCompression::default(),
+            key_case_sensitive: false,
+            strip_key: false,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Set the `Title` attribute of the header.
+    pub fn title(&mut self, title: &str) -> &mut MDictWriter {
+        self.title = title.to_owned();
+        self
+    }
+
+    /// Set the `Description` attribute of the header, which may contain HTML.
+    pub fn description(&mut self, description: &str) -> &mut MDictWriter {
+        self.description = description.to_owned();
+        self
+    }
+
+    /// Set the encoding of keywords and records of a mdx file.
+    ///
+    /// Entries are encoded when written, so the entries already added are written in this
+    /// encoding too. It has no effect on a mdd file, whose keywords are always encoded in UTF-16LE.
+    /// Entries can't be added in an encoding which `encoding_rs` can't encode into,
+    /// such as `replacement`.
+    pub fn encoding(&mut self, encoding: &'static Encoding) -> &mut MDictWriter {
+        if let MDictMode::Mdx = self.mode {
+            self.encoding = encoding;
+        }
+        self
+    }
+
+    /// Add an extra attribute such as `CreationDate` or `StyleSheet` to the header.
+    pub fn attr(&mut self, key: &str, value: &str) -> &mut MDictWriter {
+        self.attrs.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Set the uncompressed size that a keyword block and a record block try to reach.
+    pub fn block_size(
+        &mut self,
+        key_block_size: usize,
+        record_block_size: usize,
+    ) -> &mut MDictWriter {
+        self.key_block_size = key_block_size.max(1);
+        self.record_block_size = record_block_size.max(1);
+        self
+    }
+
+    /// Set the compression of keyword blocks, keyword block index and record blocks.
+    pub fn compression(&mut self, compression: MDictCompression) -> &mut MDictWriter {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the `KeyCaseSensitive` attribute of the header, keywords are matched
+    /// case-insensitively by default.
+    pub fn key_case_sensitive(&mut self, key_case_sensitive: bool) -> &mut MDictWriter {
+        self.key_case_sensitive = key_case_sensitive;
+        self
+    }
+
+    /// Set the `StripKey` attribute of the header of a mdx file, punctuation and spaces
+    /// in keywords are not ignored by default.
+    pub fn strip_key(&mut self, strip_key: bool) -> &mut MDictWriter {
+        self.strip_key = strip_key;
+        self
+    }
+
+    /// Add a keyword and its record to a mdx file.
+    ///
+    /// # Error
+    ///
+    /// [`io::Error`] with [`ErrorKind::InvalidInput`] will return if this is a mdd writer or
+    /// the keyword or the record can't be encoded.
+    pub fn add_entry(&mut self, keyword: &str, record: &str) -> io::Result<()> {
+        if let MDictMode::Mdd = self.mode {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Can't add a text entry to mdd file",
+            ));
+        }
+        // encoded here only to report the entry which can't be encoded
+        encode(self.encoding, keyword)?;
+        encode(self.encoding, record)?;
+        self.entries
+            .push((keyword.to_owned(), Record::Text(record.to_owned())));
+        Ok(())
+    }
+
+    /// Add a resource file to a mdd file.
+    ///
+    /// The path is relative to the root of the dictionary, both `/` and `\` are accepted as separator.
+    ///
+    /// # Error
+    ///
+    /// [`io::Error`] with [`ErrorKind::InvalidInput`] will return if this is a mdx writer.
+    pub fn add_resource(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        if let MDictMode::Mdx = self.mode {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Can't add a resource to mdx file",
+            ));
+        }
+        // keyword of mdd is a Windows style absolute path
+        let path = path.trim_start_matches(['/', '\\']);
+        let path = format!("\\{}", path.replace('/', "\\"));
+        self.entries.push((path, Record::Data(data.to_vec())));
+        Ok(())
+    }
+
+    /// Write the whole MDict file to `writer`.
+    ///
+    /// # Error
+    ///
+    /// This function returns [`io::Error`] if any io operations failed, or with
+    /// [`ErrorKind::InvalidInput`] if an entry can't be encoded in the encoding of the file.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let entries = self.sorted_entries()?;
+        let entries: Vec<Entry> = entries
+            .iter()
+            .map(|(key, record)| (key.as_slice(), record.as_slice()))
+            .collect();
+        writer.write_all(&self.header())?;
+        writer.write_all(&self.keys(&entries)?)?;
+        writer.write_all(&self.records(&entries))?;
+        writer.flush()
+    }
+
+    // Encoded entries sorted by their normalized keywords, the paths of resources are never stripped
+    fn sorted_entries(&self) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let strip_key = self.strip_key && self.mode == MDictMode::Mdx;
+        let mut entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let normalized = normalize_key(&entry.0, self.key_case_sensitive, strip_key);
+                (normalized, entry)
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+            .into_iter()
+            .map(|(_, (keyword, record))| {
+                let key = encode(self.encoding, keyword)?;
+                let record = match record {
+                    // records of mdx end with \0
+                    Record::Text(text) => {
+                        let mut record = encode(self.encoding, text)?;
+                        record.resize(record.len() + encoding_unit_size(self.encoding), 0);
+                        record
+                    }
+                    Record::Data(data) => data.clone(),
+                };
+                Ok((key, record))
+            })
+            .collect()
+    }
+
+    // length of the header, the XML tag in UTF-16LE and its checksum
+    fn header(&self) -> Vec<u8> {
+        let mut tag = match self.mode {
+            MDictMode::Mdx => format!(
+                r#"<Dictionary GeneratedByEngineVersion="2.0" RequiredEngineVersion="2.0" Encrypted="No" Encoding="{}" Format="Html""#,
+                self.encoding.name()
+            ),
+            MDictMode::Mdd => String::from(
+                r#"<Library_Data GeneratedByEngineVersion="2.0" RequiredEngineVersion="2.0" Encrypted="No" Encoding="" Format="""#,
+            ),
+        };
+        let flag = |set: bool| if set { "Yes" } else { "No" };
+        let mut attrs = vec![
+            ("Title", self.title.as_str()),
+            ("Description", self.description.as_str()),
+            ("KeyCaseSensitive", flag(self.key_case_sensitive)),
+        ];
+        if let MDictMode::Mdx = self.mode {
+            attrs.push(("StripKey", flag(self.strip_key)));
+        }
+        let attrs = attrs
+            .into_iter()
+            .chain(self.attrs.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        for (key, value) in attrs {
+            tag.push_str(&format!(
+                r#" {}="{}""#,
+                key,
+                html_escape::encode_double_quoted_attribute(value)
+            ));
+        }
+        tag.push_str("/>\r\n\0");
+        let tag: Vec<u8> = tag.encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        let mut buf = Vec::with_capacity(tag.len() + 8);
+        buf.put_u32(tag.len() as u32);
+        buf.put_slice(&tag);
+        buf.put_u32_le(adler::adler32_slice(&tag));
+        buf
+    }
+
+    // keywords block header, keywords block index and keywords blocks
+    fn keys(&self, entries: &[Entry]) -> io::Result<Vec<u8>> {
+        let unit_size = encoding_unit_size(self.encoding);
+        let mut blocks = Vec::new();
+        let mut index = Vec::new();
+        let mut offset = 0;
+        let size = |(k, _): &Entry| 8 + k.len() + unit_size;
+        for chunk in chunks(entries, self.key_block_size, size) {
+            let mut block = Vec::new();
+            for (key, record) in chunk {
+                block.put_u64(offset);
+                block.put_slice(key);
+                block.put_slice(&vec![0; unit_size]);
+                offset += record.len() as u64;
+            }
+            let compressed = compress(self.compression, &block);
+            index.put_u64(chunk.len() as u64);
+            for word in [chunk[0].0, chunk[chunk.len() - 1].0].iter() {
+                if word.len() / unit_size > u16::MAX as usize {
+                    return Err(Error::new(ErrorKind::InvalidInput, "Keyword is too long"));
+                }
+                index.put_u16((word.len() / unit_size) as u16);
+                index.put_slice(word);
+                index.put_slice(&vec![0; unit_size]);
+            }
+            index.put_u64(compressed.len() as u64);
+            index.put_u64(block.len() as u64);
+            blocks.push(compressed);
+        }
+        let compressed_index = compress(self.compression, &index);
+        let blocks_size: usize = blocks.iter().map(|b| b.len()).sum();
+        let mut buf = Vec::with_capacity(44 + compressed_index.len() + blocks_size);
+        buf.put_u64(blocks.len() as u64);
+        buf.put_u64(entries.len() as u64);
+        buf.put_u64(index.len() as u64);
+        buf.put_u64(compressed_index.len() as u64);
+        buf.put_u64(blocks_size as u64);
+        let checksum = adler::adler32_slice(&buf);
+        buf.put_u32(checksum);
+        buf.put_slice(&compressed_index);
+        for block in blocks {
+            buf.put_slice(&block);
+        }
+        Ok(buf)
+    }
+
+    // record blocks header, record blocks index and record blocks
+    fn records(&self, entries: &[Entry]) -> Vec<u8> {
+        let mut blocks = Vec::new();
+        let mut index = Vec::new();
+        for chunk in chunks(entries, self.record_block_size, |(_, r)| r.len()) {
+            let block: Vec<u8> = chunk.iter().flat_map(|(_, r)| r.iter().copied()).collect();
+            let compressed = compress(self.compression, &block);
+            index.put_u64(compressed.len() as u64);
+            index.put_u64(block.len() as u64);
+            blocks.push(compressed);
+        }
+        let blocks_size: usize = blocks.iter().map(|b| b.len()).sum();
+        let mut buf = Vec::with_capacity(32 + index.len() + blocks_size);
+        buf.put_u64(blocks.len() as u64);
+        buf.put_u64(entries.len() as u64);
+        buf.put_u64(index.len() as u64);
+        buf.put_u64(blocks_size as u64);
+        buf.put_slice(&index);
+        for block in blocks {
+            buf.put_slice(&block);
+        }
+        buf
+    }
+}
+
+// Split entries into chunks whose size just reaches `block_size`
+fn chunks<'a, 'b, F>(entries: &'b [Entry<'a>], block_size: usize, size: F) -> Vec<&'b [Entry<'a>]>
+where
+    F: Fn(&Entry) -> usize,
+{
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut len = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        len += size(entry);
+        if len >= block_size {
+            chunks.push(&entries[start..=i]);
+            start = i + 1;
+            len = 0;
+        }
+    }
+    if start < entries.len() {
+        chunks.push(&entries[start..]);
+    }
+    chunks
+}
+
+// Encode string into bytes, `encoding_rs` always encode UTF-16 into UTF-8
+fn encode(encoding: &'static Encoding, s: &str) -> io::Result<Vec<u8>> {
+    if encoding == UTF_16LE {
+        return Ok(s.encode_utf16().flat_map(|u| u.to_le_bytes()).collect());
+    }
+    if encoding == UTF_16BE {
+        return Ok(s.encode_utf16().flat_map(|u| u.to_be_bytes()).collect());
+    }
+    // such as `replacement`, which is also encoded into UTF-8
+    if encoding.output_encoding() != encoding {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Can't encode to {}", encoding.name()),
+        ));
+    }
+    let (cow, _encoding_used, had_errors) = encoding.encode(s);
+    if had_errors {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} cannot encode to {}", s, encoding.name()),
+        ))
+    } else {
+        Ok(cow.into_owned())
+    }
+}
+
+// Compress block with the compression type and checksum, the reverse of `uncompress`
+fn compress(compression: MDictCompression, block: &[u8]) -> Vec<u8> {
+    let (magic, compressed) = match compression {
+        MDictCompression::None => (0x0, block.to_vec()),
+        MDictCompression::Lzo => (0x1, lzo::compress(block)),
+        MDictCompression::Zlib => (0x2, compress_to_vec_zlib(block, 6)),
+    };
+    let mut buf = Vec::with_capacity(compressed.len() + 8);
+    buf.put_u32_le(magic);
+    buf.put_u32(adler::adler32_slice(block));
+    buf.put_slice(&compressed);
+    buf
+}
@@ -0,0 +1,106 @@
+// Asynchronous header parsing, index building and lookup
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::*;
+use mdict::*;
+use std::io::{self, Cursor, Read, Seek};
+use std::sync::{mpsc, Arc, Mutex};
+
+// A reader which waits for the gate to be opened before its first read after arming
+struct GatedReader {
+    inner: Cursor<Vec<u8>>,
+    gate: Arc<Mutex<Option<mpsc::Receiver<()>>>>,
+}
+
+impl Read for GatedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let gate = self.gate.lock().unwrap().take();
+        if let Some(gate) = gate {
+            gate.recv().unwrap();
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for GatedReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[tokio::test]
+async fn same_index_as_blocking() {
+    let file = write_mdx(40, MDictCompression::Zlib);
+    let header = MDictHeader::new_async(Cursor::new(&file), MDictMode::Mdx)
+        .await
+        .unwrap();
+    assert_eq!(
+        header.attrs(),
+        MDictHeader::new(Cursor::new(&file), MDictMode::Mdx)
+            .unwrap()
+            .attrs()
+    );
+
+    let mut index = MDictIndex::new(Cursor::new(file.clone()), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let index = MDictIndex::new(Cursor::new(file.clone()), MDictMode::Mdx).unwrap();
+    let (header, async_blocks, async_keys) = index.make_index_async().await.unwrap();
+    assert_eq!(async_keys, keys);
+    assert_eq!(async_blocks.len(), blocks.len());
+
+    for (i, (key, idx)) in async_keys.iter().enumerate() {
+        let record = lookup_async(Cursor::new(&file), idx, &async_blocks)
+            .await
+            .unwrap();
+        assert_eq!(record, lookup(Cursor::new(&file), idx, &blocks).unwrap());
+        let record = header.decode_record(record).unwrap();
+        assert_eq!(key, &format!("word{:02}", i));
+        assert_eq!(record, format!("<p>record {:02}</p>\0", i));
+    }
+}
+
+#[tokio::test]
+async fn read_key_blocks_async() {
+    let file = write_mdx(40, MDictCompression::Zlib);
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (key_blocks, _) = index.make_key_index().unwrap();
+    for (i, block) in key_blocks.iter().enumerate() {
+        let keys = read_key_block_async(Cursor::new(&file), index.header(), block, i)
+            .await
+            .unwrap();
+        let expected = read_key_block(Cursor::new(&file), index.header(), block, i).unwrap();
+        assert_eq!(keys, expected);
+        assert_eq!(keys[0].0, block.first_word);
+    }
+}
+
+#[tokio::test]
+async fn header_errors_async() {
+    let mut file = write_mdx(40, MDictCompression::Zlib);
+    file[10] ^= 0xff;
+    match MDictHeader::new_async(Cursor::new(&file), MDictMode::Mdx).await {
+        Err(MDictError::HeaderChecksum { .. }) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("corrupted header is parsed"),
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn index_off_the_runtime() {
+    let gate = Arc::new(Mutex::new(None));
+    let reader = GatedReader {
+        inner: Cursor::new(write_mdx(40, MDictCompression::Zlib)),
+        gate: gate.clone(),
+    };
+    let index = MDictIndex::new(reader, MDictMode::Mdx).unwrap();
+    let (open, wait) = mpsc::channel();
+    *gate.lock().unwrap() = Some(wait);
+    // the gate is opened by a task of this single-threaded runtime,
+    // which never runs if the keywords are read on the runtime
+    let opener = tokio::spawn(async move { open.send(()).unwrap() });
+    let (_, _, keys) = index.make_index_async().await.unwrap();
+    opener.await.unwrap();
+    assert_eq!(keys.len(), 40);
+}
@@ -0,0 +1,130 @@
+// The LRU cache of decompressed record blocks
+
+use mdict::*;
+use std::io::Cursor;
+
+struct Dictionary {
+    file: Vec<u8>,
+    blocks: Vec<MDictRecordBlockIndex>,
+    // a record in each record block
+    records: Vec<MDictRecordIndex>,
+}
+
+impl Dictionary {
+    // Records of the same size, each in its own record block
+    fn new() -> Dictionary {
+        let mut writer = MDictWriter::new(MDictMode::Mdx);
+        writer.block_size(16, 16);
+        for i in 0..6 {
+            writer
+                .add_entry(&format!("word{}", i), &format!("<p>record {}</p>", i))
+                .unwrap();
+        }
+        let mut file = Vec::new();
+        writer.write(&mut file).unwrap();
+        let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+        let (blocks, keys) = index.make_index().unwrap();
+        let records: Vec<_> = keys.into_iter().map(|(_, idx)| idx).collect();
+        assert_eq!(records.len(), blocks.len());
+        assert!(records.iter().enumerate().all(|(i, r)| r.block == i as u64));
+        Dictionary {
+            file,
+            blocks,
+            records,
+        }
+    }
+
+    fn lookup(&self, cache: &MDictBlockCache, file: u64, record: usize) -> String {
+        let record = lookup_cached(
+            Cursor::new(&self.file),
+            &self.records[record],
+            &self.blocks,
+            cache,
+            file,
+        )
+        .unwrap();
+        String::from_utf8(record.to_vec()).unwrap()
+    }
+
+    fn block_size(&self, block: usize) -> usize {
+        self.blocks[block].uncomp_size as usize
+    }
+}
+
+#[test]
+fn hits_and_misses() {
+    let dict = Dictionary::new();
+    let cache = MDictBlockCache::new(1 << 20);
+    let file = cache.new_file_id();
+    assert_eq!(dict.lookup(&cache, file, 1), "<p>record 1</p>\0");
+    assert_eq!(dict.lookup(&cache, file, 1), "<p>record 1</p>\0");
+    assert_eq!(dict.lookup(&cache, file, 2), "<p>record 2</p>\0");
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+    assert_eq!(stats.blocks, 2);
+    assert_eq!(stats.size, dict.block_size(1) + dict.block_size(2));
+
+    // the same offset of another file is another block
+    let other = cache.new_file_id();
+    assert_ne!(file, other);
+    dict.lookup(&cache, other, 1);
+    assert_eq!(cache.stats().misses, 3);
+    assert_eq!(cache.stats().blocks, 3);
+
+    // the counters are kept after clearing
+    cache.clear();
+    let stats = cache.stats();
+    assert_eq!(
+        (stats.hits, stats.misses, stats.blocks, stats.size),
+        (1, 3, 0, 0)
+    );
+}
+
+#[test]
+fn evict_least_recently_used() {
+    let dict = Dictionary::new();
+    // room for the first 3 blocks
+    let budget = (0..3).map(|i| dict.block_size(i)).sum();
+    let cache = MDictBlockCache::new(budget);
+    assert_eq!(cache.budget(), budget);
+    for i in 0..3 {
+        dict.lookup(&cache, 0, i);
+    }
+    assert_eq!(cache.stats().blocks, 3);
+    // block 1 becomes the least recently used
+    dict.lookup(&cache, 0, 0);
+    dict.lookup(&cache, 0, 2);
+    dict.lookup(&cache, 0, 3);
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (2, 4));
+    assert!(stats.size <= budget);
+
+    let misses = stats.misses;
+    dict.lookup(&cache, 0, 2);
+    dict.lookup(&cache, 0, 3);
+    assert_eq!(cache.stats().misses, misses);
+    dict.lookup(&cache, 0, 1);
+    assert_eq!(cache.stats().misses, misses + 1);
+}
+
+#[test]
+fn byte_budget() {
+    let dict = Dictionary::new();
+    // a block larger than the budget is never cached
+    let cache = MDictBlockCache::new(dict.block_size(0) - 1);
+    dict.lookup(&cache, 0, 0);
+    dict.lookup(&cache, 0, 0);
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.blocks), (0, 2, 0));
+
+    // the size of blocks never exceeds the budget
+    let budget = dict.block_size(0) + dict.block_size(1) / 2;
+    let cache = MDictBlockCache::new(budget);
+    for i in 0..dict.blocks.len() {
+        dict.lookup(&cache, 0, i);
+        let stats = cache.stats();
+        assert!(stats.size <= budget);
+        assert_eq!(stats.blocks, 1);
+        assert_eq!(stats.size, dict.block_size(i));
+    }
+}
@@ -0,0 +1,54 @@
+// Dictionaries written by MDictWriter for the tests
+#![allow(dead_code)]
+
+use mdict::{MDictCompression, MDictMode, MDictWriter};
+
+// `n` keywords "word00", "word01", ... and their records "<p>record 00</p>", ...,
+// numbered with the same width so they are sorted in this order
+pub fn entries(n: usize) -> Vec<(String, String)> {
+    let width = n.saturating_sub(1).to_string().len().max(2);
+    (0..n)
+        .map(|i| {
+            let key = format!("word{:0width$}", i, width = width);
+            let record = format!("<p>record {:0width$}</p>", i, width = width);
+            (key, record)
+        })
+        .collect()
+}
+
+// A mdx file of the entries, whose keyword blocks and record blocks try to reach `block_size`
+pub fn write_entries(
+    entries: &[(String, String)],
+    (key_block_size, record_block_size): (usize, usize),
+    compression: MDictCompression,
+) -> Vec<u8> {
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer
+        .block_size(key_block_size, record_block_size)
+        .compression(compression);
+    for (key, record) in entries {
+        writer.add_entry(key, record).unwrap();
+    }
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+    file
+}
+
+// A mdx file of `n` entries in many small blocks,
+// a byte of any block can be corrupted if they are uncompressed
+pub fn write_mdx(n: usize, compression: MDictCompression) -> Vec<u8> {
+    write_entries(&entries(n), (64, 64), compression)
+}
+
+// A header of v2 with the given tag, in UTF-16LE with its checksum
+pub fn header(tag: &str) -> Vec<u8> {
+    let tag: Vec<u8> = tag
+        .encode_utf16()
+        .chain(Some(0))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let mut file = (tag.len() as u32).to_be_bytes().to_vec();
+    file.extend_from_slice(&tag);
+    file.extend_from_slice(&adler::adler32_slice(&tag).to_le_bytes());
+    file
+}
@@ -0,0 +1,138 @@
+// Files written by MDictWriter with every compression type
+
+mod common;
+
+use common::*;
+use mdict::*;
+use std::io::Cursor;
+
+// Write a mdx file with small blocks, whose records repeat so LZO emits matches of all lengths
+fn write_definitions(compression: MDictCompression) -> (Vec<u8>, Vec<(String, String)>) {
+    let entries: Vec<(String, String)> = (0..500)
+        .map(|i| {
+            let key = format!("word{:04}", i);
+            let record = format!("<p>{}</p>", "definition ".repeat(i % 40 + 1));
+            (key, record)
+        })
+        .collect();
+    let file = write_entries(&entries, (1024, 4096), compression);
+    (file, entries)
+}
+
+fn check_entries(file: &[u8], entries: &[(String, String)]) {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    assert!(blocks.len() > 1);
+    let header = index.into_header();
+    let keys: Vec<_> = keys.into_iter().collect();
+    assert_eq!(keys.len(), entries.len());
+    for ((key, idx), (expect_key, expect_record)) in keys.iter().zip(entries) {
+        let record = lookup(Cursor::new(file), idx, &blocks).unwrap();
+        // records of mdx end with \0
+        let record = header.decode_string(record).unwrap();
+        assert_eq!(key, expect_key);
+        assert_eq!(record.trim_end_matches('\0'), expect_record);
+    }
+}
+
+#[test]
+fn lzo_blocks() {
+    let (file, entries) = write_definitions(MDictCompression::Lzo);
+    check_entries(&file, &entries);
+    let (zlib, _) = write_definitions(MDictCompression::Zlib);
+    let (stored, _) = write_definitions(MDictCompression::None);
+    assert!(file.len() < stored.len());
+    assert!(zlib.len() < stored.len());
+    check_entries(&stored, &entries);
+}
+
+#[test]
+fn corrupted_lzo_block() {
+    let (mut file, _) = write_definitions(MDictCompression::Lzo);
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let (_, idx) = keys.iter().next().unwrap();
+    // the end of stream marker of the first record block
+    let end = (blocks[0].offset + blocks[0].comp_size) as usize;
+    file[end - 3] = 0xff;
+    match lookup(Cursor::new(&file[..]), idx, &blocks) {
+        Err(MDictError::Decompress { kind, index, .. }) => {
+            assert_eq!(kind, MDictBlockKind::RecordBlock);
+            assert_eq!(index, 0);
+        }
+        other => panic!("Expect a decompress error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn lzo_block_larger_than_64_kib() {
+    let entries: Vec<(String, String)> = (0..300)
+        .map(|i| {
+            let key = format!("word{:04}", i);
+            let words: Vec<_> = (0..100).map(|j| format!("{}", i * j)).collect();
+            (key, words.join(" "))
+        })
+        .collect();
+    let file = write_entries(&entries, (1024, 96 << 10), MDictCompression::Lzo);
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (blocks, _) = index.make_index().unwrap();
+    assert!(blocks[0].uncomp_size > 64 << 10);
+    assert!(blocks[0].comp_size < blocks[0].uncomp_size);
+    check_entries(&file, &entries);
+}
+
+#[test]
+fn every_corrupted_byte_of_lzo_block() {
+    let (file, _) = write_definitions(MDictCompression::Lzo);
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let (_, idx) = &keys[0];
+    let record = lookup(Cursor::new(&file[..]), idx, &blocks).unwrap();
+    // the compressed data after the compression type and checksum
+    let start = blocks[0].offset as usize + 8;
+    let end = (blocks[0].offset + blocks[0].comp_size) as usize;
+    for i in start..end {
+        let mut corrupted = file.clone();
+        corrupted[i] ^= 0x55;
+        // a corrupted byte is found by LZO or the checksum, unless it makes the same output
+        match lookup(Cursor::new(&corrupted[..]), idx, &blocks) {
+            Err(MDictError::Decompress { .. }) | Err(MDictError::BlockChecksum { .. }) => {}
+            Ok(found) => assert_eq!(found, record),
+            Err(error) => panic!("unexpected error: {}", error),
+        }
+    }
+}
+
+#[test]
+fn layout_of_blocks() {
+    for compression in [MDictCompression::None, MDictCompression::Lzo, MDictCompression::Zlib] {
+        let (file, entries) = write_definitions(compression);
+        let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+        let layout = index.layout().unwrap();
+        let (key_blocks, record_blocks) = index.make_key_index().unwrap();
+        assert_eq!(layout.version, MDictFormatVersion::V2);
+        assert_eq!(layout.key_blocks.len(), key_blocks.len());
+        assert_eq!(layout.record_blocks.len(), record_blocks.len());
+
+        let entries_count: u64 = layout.key_blocks.iter().map(|b| b.block_entries).sum();
+        assert_eq!(entries_count, entries.len() as u64);
+        assert_eq!(layout.key_blocks[0].first_word, entries[0].0);
+        let last = layout.key_blocks.last().unwrap();
+        assert_eq!(last.last_word, entries.last().unwrap().0);
+
+        for (block, expect) in layout.record_blocks.iter().zip(record_blocks.iter()) {
+            assert_eq!(block.offset, expect.offset);
+            assert_eq!(block.comp_size, expect.comp_size);
+            assert_eq!(block.uncomp_size, expect.uncomp_size);
+        }
+        let blocks = layout
+            .key_blocks
+            .iter()
+            .map(|b| b.compression)
+            .chain(layout.record_blocks.iter().map(|b| b.compression));
+        for block in blocks {
+            assert_eq!(block.method(), Some(compression));
+            assert_eq!(block.encryption, 0);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// Keywords and records which are invalid in the encoding of the file
+
+mod common;
+
+use bytes::Bytes;
+use common::*;
+use mdict::*;
+use std::io::Cursor;
+
+// "中文" in GBK, which is invalid in UTF-8
+const GBK: &[u8] = b"\xd6\xd0\xce\xc4";
+// invalid in both UTF-8 and GBK
+const INVALID: &[u8] = b"\xff\xff";
+
+// A mdx file in UTF-8 with 3 entries in one keyword block and one record block,
+// the end of the keyword and the record in the middle are replaced by GBK
+fn write_gbk_mdx() -> Vec<u8> {
+    let mut file = write_mdx(3, MDictCompression::None);
+
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (key_blocks, record_blocks) = index.make_key_index().unwrap();
+    let key_block = (key_blocks[0].offset, key_blocks[0].comp_size);
+    let record_block = (record_blocks[0].offset, record_blocks[0].comp_size);
+    assert_eq!((key_blocks.len(), record_blocks.len()), (1, 1));
+    replace(&mut file, b"word01\0", key_block);
+    replace(&mut file, b"01</p>\0", record_block);
+    file
+}
+
+// Replace the 4 bytes before the `\0` at the end of `from` by GBK,
+// and the checksum of the uncompressed block
+fn replace(file: &mut [u8], from: &[u8], (offset, size): (u64, u64)) {
+    let block = &mut file[offset as usize..(offset + size) as usize];
+    let pos = block.windows(from.len()).position(|w| w == from).unwrap() + from.len() - 1;
+    block[pos - GBK.len()..pos].copy_from_slice(GBK);
+    let checksum = adler::adler32_slice(&block[8..]);
+    block[4..8].copy_from_slice(&checksum.to_be_bytes());
+}
+
+// Index the file by `policy`, returns the keywords and the record of the keyword in GBK
+fn index(file: &[u8], policy: MDictDecodePolicy) -> MDictResult<(MDictHeader, Vec<String>, Bytes)> {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx)?;
+    index.set_decode_policy(policy);
+    let (blocks, keys) = index.make_index()?;
+    let record = lookup(Cursor::new(file), &keys[1].1, &blocks)?;
+    let keys = keys.into_iter().map(|(key, _)| key).collect();
+    Ok((index.into_header(), keys, record))
+}
+
+#[test]
+fn strict() {
+    let file = write_gbk_mdx();
+    match index(&file, MDictDecodePolicy::Strict).err().unwrap() {
+        MDictError::Decode { encoding, offset } => {
+            assert_eq!(encoding, "UTF-8");
+            // the offset of the keyword in the uncompressed keyword blocks,
+            // after the offset of record and "word00\0" of the first entry
+            assert_eq!(offset, Some(8 + 7 + 8));
+        }
+        error => panic!("unexpected error: {}", error),
+    }
+
+    let file = write_gbk_mdx();
+    let (mut header, _, record) = index(&file, MDictDecodePolicy::Lossy).unwrap();
+    header.set_decode_policy(MDictDecodePolicy::Strict);
+    assert_eq!(header.decode_policy(), &MDictDecodePolicy::Strict);
+    assert!(matches!(
+        header.decode_record(record),
+        Err(MDictError::Decode { offset: None, .. })
+    ));
+    assert_eq!(
+        header.decode_string(Bytes::from_static(b"valid")).unwrap(),
+        "valid"
+    );
+}
+
+#[test]
+fn lossy() {
+    let file = write_gbk_mdx();
+    let (header, keys, record) = index(&file, MDictDecodePolicy::Lossy).unwrap();
+    assert_eq!(keys[0], "word00");
+    assert!(keys[1].contains('\u{fffd}'));
+    assert_eq!(keys[2], "word02");
+    let record = header.decode_record(record).unwrap();
+    assert!(record.contains('\u{fffd}'));
+    assert!(record.ends_with('\0'));
+    let stats = header.decode_stats();
+    assert_eq!((stats.keys_replaced, stats.records_replaced), (1, 1));
+    assert_eq!((stats.keys_fallback, stats.records_fallback), (0, 0));
+}
+
+#[test]
+fn fallback() {
+    let file = write_gbk_mdx();
+    let policy = MDictDecodePolicy::Fallback(vec![encoding_rs::GBK]);
+    let (mut header, keys, record) = index(&file, policy).unwrap();
+    assert_eq!(keys, ["word00", "wo中文", "word02"]);
+    assert_eq!(header.decode_record(record).unwrap(), "<p>record 01中文\0");
+    let stats = header.decode_stats();
+    assert_eq!((stats.keys_fallback, stats.records_fallback), (1, 1));
+    assert_eq!((stats.keys_replaced, stats.records_replaced), (0, 0));
+
+    // replaced like `Lossy` if no encoding can decode it
+    let string = header.decode_string(Bytes::from_static(INVALID)).unwrap();
+    assert_eq!(string, "\u{fffd}\u{fffd}");
+    assert_eq!(header.decode_stats().records_replaced, 1);
+    // the encodings are tried in order
+    header.set_decode_policy(MDictDecodePolicy::Fallback(vec![
+        encoding_rs::UTF_8,
+        encoding_rs::BIG5,
+        encoding_rs::GBK,
+    ]));
+    let string = header.decode_string(Bytes::from_static(GBK)).unwrap();
+    assert_eq!(string, encoding_rs::BIG5.decode(GBK).0);
+    assert_eq!(header.decode_stats().records_fallback, 2);
+}
+
+#[test]
+fn stats_of_valid_strings() {
+    let file = write_gbk_mdx();
+    let policy = MDictDecodePolicy::Fallback(vec![encoding_rs::GBK]);
+    let (header, _, _) = index(&file, policy).unwrap();
+    let stats = header.decode_stats();
+    header.decode_string(Bytes::from_static(b"valid")).unwrap();
+    assert_eq!(header.decode_stats(), stats);
+    // keywords are counted again when their block is read again
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    index.set_decode_policy(MDictDecodePolicy::Lossy);
+    let (key_blocks, _) = index.make_key_index().unwrap();
+    for _ in 0..2 {
+        read_key_block(Cursor::new(&file), index.header(), &key_blocks[0], 0).unwrap();
+    }
+    assert_eq!(index.header().decode_stats().keys_replaced, 2);
+}
@@ -0,0 +1,134 @@
+// Errors of corrupted and unsupported files
+
+mod common;
+
+use bytes::Bytes;
+use common::*;
+use mdict::*;
+use std::io::{self, Cursor};
+
+// Look up all records of a file
+fn lookup_all(file: &[u8]) -> MDictResult<()> {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx)?;
+    let (blocks, keys) = index.make_index()?;
+    for (_, idx) in keys {
+        lookup(Cursor::new(file), &idx, &blocks)?;
+    }
+    Ok(())
+}
+
+fn header_error(file: &[u8]) -> MDictError {
+    match MDictHeader::new(Cursor::new(file), MDictMode::Mdx) {
+        Ok(_) => panic!("header is parsed"),
+        Err(e) => e,
+    }
+}
+
+fn record_blocks(file: &[u8]) -> Vec<MDictRecordBlockIndex> {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx).unwrap();
+    index.make_key_index().unwrap().1
+}
+
+#[test]
+fn header_checksum() {
+    let mut file = write_mdx(20, MDictCompression::None);
+    file[10] ^= 0xff;
+    let error = header_error(&file);
+    assert!(matches!(error, MDictError::HeaderChecksum { .. }));
+
+    // the original error is kept in io::Error
+    let error = io::Error::from(error);
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let inner = error.get_ref().unwrap().downcast_ref::<MDictError>();
+    assert!(matches!(inner, Some(MDictError::HeaderChecksum { .. })));
+}
+
+#[test]
+fn truncated_file() {
+    let file = write_mdx(20, MDictCompression::None);
+    let error = header_error(&file[..20]);
+    assert!(matches!(error, MDictError::Truncated { actual: 16, .. }));
+    assert_eq!(io::Error::from(error).kind(), io::ErrorKind::UnexpectedEof);
+
+    let error = lookup_all(&file[..file.len() - 10]).unwrap_err();
+    assert!(matches!(error, MDictError::Truncated { .. }));
+}
+
+#[test]
+fn unsupported_version() {
+    let file = header(r#"<Dictionary Encoding="UTF-16" Title="No version"/>"#);
+    let error = header_error(&file);
+    assert!(matches!(error, MDictError::UnsupportedVersion(_)));
+    assert_eq!(io::Error::from(error).kind(), io::ErrorKind::Unsupported);
+
+    let file = header(r#"<Dictionary GeneratedByEngineVersion="2.0" Encoding="UTF-16"/>"#);
+    let header = MDictHeader::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    assert_eq!(header.version(), MDictFormatVersion::V2);
+}
+
+#[test]
+fn block_checksum_and_compression() {
+    let file = write_mdx(20, MDictCompression::None);
+    let blocks = record_blocks(&file);
+    assert!(blocks.len() > 3);
+
+    let mut corrupted = file.clone();
+    corrupted[blocks[2].offset as usize + 10] ^= 0xff;
+    match lookup_all(&corrupted).unwrap_err() {
+        MDictError::BlockChecksum { kind, index, .. } => {
+            assert_eq!(kind, MDictBlockKind::RecordBlock);
+            assert_eq!(index, 2);
+        }
+        error => panic!("unexpected error: {}", error),
+    }
+
+    let mut corrupted = file;
+    corrupted[blocks[3].offset as usize] = 0x7;
+    match lookup_all(&corrupted).unwrap_err() {
+        MDictError::UnknownCompression { kind, index, magic } => {
+            assert_eq!(kind, MDictBlockKind::RecordBlock);
+            assert_eq!(index, 3);
+            assert_eq!(magic, 0x7);
+        }
+        error => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn undecodable_string() {
+    let file = write_mdx(20, MDictCompression::None);
+    let header = MDictHeader::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    assert_eq!(header.encoding(), encoding_rs::UTF_8);
+    let error = header
+        .decode_string(Bytes::from_static(b"a\xffb"))
+        .unwrap_err();
+    match error {
+        MDictError::Decode { encoding, offset } => {
+            assert_eq!(encoding, "UTF-8");
+            assert_eq!(offset, None);
+        }
+        error => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn record_index_out_of_range() {
+    let file = write_mdx(20, MDictCompression::None);
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let key = keys[0].1;
+    // offsets which would be the first record if they were truncated to 32 bits
+    for idx in [
+        MDictRecordIndex {
+            block: key.block + (1 << 32),
+            ..key
+        },
+        MDictRecordIndex {
+            offset: key.offset + (1 << 32),
+            ..key
+        },
+    ] {
+        assert!(lookup(Cursor::new(&file), &idx, &blocks).is_err());
+    }
+    assert!(lookup(Cursor::new(&file), &key, &blocks).is_ok());
+}
@@ -0,0 +1,174 @@
+#!/usr/bin/env python3
+# Generate the MDict 3.0 files used by tests/v3.rs, independently of the mdict crate.
+#
+#     python3 make_v3.py
+#
+# A v3 file is the header in UTF-8 followed by tagged sections. The data sections contain
+# blocks of `uncompressed size (u32 BE), block size (u32 BE), info (u32 LE), checksum (u32 BE),
+# data`. The info holds the compression in bits 0-3, the encryption in bits 4-7 and the number
+# of encrypted bytes in bits 8-15. The checksum is the adler32 of the data before encryption,
+# and the key of the encryption is the RIPEMD-128 digest of the checksum bytes.
+
+import struct
+import zlib
+
+
+def _rol(x, n):
+    x &= 0xffffffff
+    return ((x << n) | (x >> (32 - n))) & 0xffffffff
+
+# RIPEMD-128, from the specification by Dobbertin, Bosselaers and Preneel
+_R = [
+    list(range(16)),
+    [7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8],
+    [3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12],
+    [1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2],
+]
+_RP = [
+    [5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12],
+    [6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2],
+    [15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13],
+    [8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14],
+]
+_S = [
+    [11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8],
+    [7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12],
+    [11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5],
+    [11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12],
+]
+_SP = [
+    [8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6],
+    [9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11],
+    [9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5],
+    [15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8],
+]
+_K = [0x00000000, 0x5a827999, 0x6ed9eba1, 0x8f1bbcdc]
+_KP = [0x50a28be6, 0x5c4dd124, 0x6d703ef3, 0x00000000]
+_F = [
+    lambda x, y, z: x ^ y ^ z,
+    lambda x, y, z: (x & y) | (~x & z),
+    lambda x, y, z: (x | ~y) ^ z,
+    lambda x, y, z: (x & z) | (y & ~z),
+]
+
+def ripemd128(data):
+    h = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476]
+    msg = bytes(data) + b"\x80"
+    msg += b"\x00" * ((56 - len(msg)) % 64)
+    msg += struct.pack("<Q", len(data) * 8)
+    for off in range(0, len(msg), 64):
+        x = struct.unpack("<16I", msg[off:off + 64])
+        a, b, c, d = h
+        ap, bp, cp, dp = h
+        for j in range(64):
+            r = j // 16
+            t = _rol(a + (_F[r](b, c, d) & 0xffffffff) + x[_R[r][j % 16]] + _K[r], _S[r][j % 16])
+            a, d, c, b = d, c, b, t
+            t = _rol(ap + (_F[3 - r](bp, cp, dp) & 0xffffffff) + x[_RP[r][j % 16]] + _KP[r], _SP[r][j % 16])
+            ap, dp, cp, bp = dp, cp, bp, t
+        t = (h[1] + c + dp) & 0xffffffff
+        h[1] = (h[2] + d + ap) & 0xffffffff
+        h[2] = (h[3] + a + bp) & 0xffffffff
+        h[3] = (h[0] + b + cp) & 0xffffffff
+        h[0] = t
+    return struct.pack("<4I", *h)
+
+# Salsa20 by Bernstein, `rounds` is 8 for Salsa20/8, zero nonce
+def _salsa_block(inp, rounds):
+    x = list(inp)
+    def qr(a, b, c, d):
+        x[b] ^= _rol(x[a] + x[d], 7)
+        x[c] ^= _rol(x[b] + x[a], 9)
+        x[d] ^= _rol(x[c] + x[b], 13)
+        x[a] ^= _rol(x[d] + x[c], 18)
+    for _ in range(rounds // 2):
+        qr(0, 4, 8, 12); qr(5, 9, 13, 1); qr(10, 14, 2, 6); qr(15, 3, 7, 11)
+        qr(0, 1, 2, 3); qr(5, 6, 7, 4); qr(10, 11, 8, 9); qr(15, 12, 13, 14)
+    return struct.pack("<16I", *[(a + b) & 0xffffffff for a, b in zip(x, inp)])
+
+def salsa20(data, key, rounds=8, nonce=b"\x00" * 8):
+    if len(key) == 16:
+        c = struct.unpack("<4I", b"expand 16-byte k")
+        k0 = k1 = struct.unpack("<4I", key)
+    else:
+        c = struct.unpack("<4I", b"expand 32-byte k")
+        k0 = struct.unpack("<4I", key[:16]); k1 = struct.unpack("<4I", key[16:])
+    n = struct.unpack("<2I", nonce)
+    out = bytearray()
+    for counter, off in enumerate(range(0, len(data), 64)):
+        inp = [c[0], *k0, c[1], n[0], n[1], counter & 0xffffffff, counter >> 32, c[2], *k1, c[3]]
+        stream = _salsa_block(inp, rounds)
+        out += bytes(a ^ b for a, b in zip(data[off:off + 64], stream))
+    return bytes(out)
+
+# The simple encryption of MDict, the inverse of `_fast_decrypt` of readmdict
+def fast_encrypt(data, key):
+    out = bytearray()
+    previous = 0x36
+    for i, p in enumerate(data):
+        t = p ^ previous ^ (i & 0xff) ^ key[i % len(key)]
+        c = ((t << 4) | (t >> 4)) & 0xff
+        out.append(c)
+        previous = c
+    return bytes(out)
+
+
+def block(data, compression, encryption, encrypted_size):
+    payload = zlib.compress(data) if compression == 2 else data
+    checksum = struct.pack(">I", zlib.adler32(payload))
+    key = ripemd128(checksum)
+    size = min(encrypted_size, len(payload))
+    if encryption == 1:
+        payload = fast_encrypt(payload[:size], key) + payload[size:]
+    elif encryption == 2:
+        payload = salsa20(payload[:size], key) + payload[size:]
+    info = compression | encryption << 4 | encrypted_size << 8
+    return struct.pack("<I", info) + checksum + payload
+
+
+def section(kind, blocks):
+    content = struct.pack(">IQ", len(blocks), sum(len(b) for _, b in blocks))
+    for size, data in blocks:
+        content += struct.pack(">II", size, len(data)) + data
+    return struct.pack(">IQ", kind, len(content)) + content
+
+
+def make_v3(entries, encryption=0, attrs=""):
+    tag = (
+        '<Dictionary GeneratedByEngineVersion="3.0" RequiredEngineVersion="3.0" '
+        'Encoding="UTF-8" Title="Version 3"%s/>\0' % attrs
+    ).encode()
+    out = struct.pack(">I", len(tag)) + tag + struct.pack("<I", zlib.adler32(tag))
+    key_blocks, record_blocks = [], []
+    offset = 0
+    for i in range(0, len(entries), 4):
+        keys, records = b"", b""
+        for key, record in entries[i : i + 4]:
+            record = record.encode() + b"\0"
+            keys += struct.pack(">Q", offset) + key.encode() + b"\0"
+            records += record
+            offset += len(record)
+        # keywords are compressed by zlib, records are stored or compressed in turn
+        key_blocks.append((len(keys), block(keys, 2, encryption, 16)))
+        compression = 2 if i // 4 % 2 else 0
+        record_blocks.append((len(records), block(records, compression, encryption, 255)))
+    out += section(0x0300_0000, key_blocks)
+    # the index sections are optional for readers
+    out += struct.pack(">IQ", 0x0400_0000, 0)
+    out += section(0x0100_0000, record_blocks)
+    out += struct.pack(">IQ", 0x0200_0000, 0)
+    return out
+
+
+if __name__ == "__main__":
+    entries = [("k%02d" % i, "<p>record %02d</p>" % i) for i in range(20)]
+    entries.append(("über", "<p>ü</p>"))
+    files = {
+        "v3.mdx": make_v3(entries),
+        "v3_fast.mdx": make_v3(entries, 1),
+        "v3_salsa20.mdx": make_v3(entries, 2),
+        "v3_uuid.mdx": make_v3(entries[:2], 2, ' UUID="00000000-0000-0000-0000-000000000000"'),
+    }
+    for name, content in files.items():
+        with open(name, "wb") as f:
+            f.write(content)
@@ -0,0 +1,116 @@
+// Typed metadata parsed from the attributes of header
+
+mod common;
+
+use common::*;
+use mdict::*;
+use std::io::Cursor;
+
+fn metadata(attrs: &[(&str, &str)]) -> MDictMetadata {
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    for (key, value) in attrs {
+        writer.attr(key, value);
+    }
+    writer.add_entry("word", "record").unwrap();
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+    let header = MDictHeader::new(Cursor::new(file), MDictMode::Mdx).unwrap();
+    header.metadata().clone()
+}
+
+#[test]
+fn parse_attributes() {
+    let metadata = metadata(&[
+        ("Title", "Dictionary"),
+        ("Description", "<b>Bold</b> & \"quoted\""),
+        ("CreationDate", "2020-6-1"),
+        ("Format", "Html"),
+        ("Compact", "Yes"),
+        ("Left2Right", "true"),
+        ("DataSourceFormat", "106"),
+        ("RegisterBy", "EMail"),
+        ("StyleSheet", "1\n<b>\n</b>\n2\n<i>\n</i>"),
+    ]);
+    assert_eq!(metadata.title.as_deref(), Some("Dictionary"));
+    assert_eq!(
+        metadata.description.as_deref(),
+        Some("<b>Bold</b> & \"quoted\"")
+    );
+    let date = metadata.creation_date.unwrap();
+    assert_eq!((date.year, date.month, date.day), (2020, 6, 1));
+    assert_eq!(metadata.engine_version.as_deref(), Some("2.0"));
+    assert_eq!(metadata.format, Some(MDictRecordFormat::Html));
+    assert!(metadata.compact);
+    assert!(metadata.left_to_right);
+    assert_eq!(metadata.data_source_format.as_deref(), Some("106"));
+    assert_eq!(metadata.register_by, Some(MDictRegisterBy::Email));
+    assert_eq!(metadata.stylesheet.get(2).unwrap().begin, "<i>");
+}
+
+#[test]
+fn missing_and_placeholder_attributes() {
+    let metadata = metadata(&[
+        ("Title", "Title (No HTML code allowed)"),
+        ("Description", "  "),
+        ("CreationDate", "unknown"),
+        ("Format", "Markdown"),
+        ("Left2Right", "No"),
+        ("RegisterBy", "Phone"),
+    ]);
+    assert_eq!(metadata.title, None);
+    assert_eq!(metadata.description, None);
+    assert_eq!(metadata.creation_date, None);
+    assert_eq!(
+        metadata.format,
+        Some(MDictRecordFormat::Other("Markdown".to_owned()))
+    );
+    assert!(!metadata.compact);
+    assert!(!metadata.left_to_right);
+    assert_eq!(metadata.data_source_format, None);
+    assert_eq!(metadata.register_by, None);
+    assert!(metadata.stylesheet.is_empty());
+}
+
+#[test]
+fn creation_date_and_flags() {
+    for (date, expected) in [
+        ("2020.06.01", Some("2020-06-01")),
+        ("2020/6/1 12:00", Some("2020-06-01")),
+        ("2020-13-1", None),
+        ("2020-6", None),
+    ] {
+        let metadata = metadata(&[("CreationDate", date)]);
+        let parsed = metadata.creation_date.map(|d| d.to_string());
+        assert_eq!(parsed.as_deref(), expected, "{}", date);
+    }
+    // the misspelled attribute of old dictionaries
+    assert!(metadata(&[("Compat", "1")]).compact);
+    let metadata = metadata(&[("RegisterBy", "DeviceID")]);
+    assert_eq!(metadata.register_by, Some(MDictRegisterBy::DeviceId));
+}
+
+#[test]
+fn header_attributes_on_threads() {
+    // the regex of attributes is compiled by the first header parsed on any thread
+    let file = header(
+        "<Dictionary GeneratedByEngineVersion=\"2.0\" Encoding=\"\" Title=\"a &amp; b\" \
+         Multi_Line=\"one\r\ntwo\" Left2Right=\"Yes\"/>\r\n",
+    );
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let file = file.clone();
+            std::thread::spawn(move || {
+                let header = MDictHeader::new(Cursor::new(file), MDictMode::Mdx).unwrap();
+                header.attrs().clone()
+            })
+        })
+        .collect();
+    for thread in threads {
+        let attrs = thread.join().unwrap();
+        assert_eq!(attrs.len(), 5);
+        assert_eq!(attrs["Encoding"], "");
+        assert_eq!(attrs["Title"], "a & b");
+        assert_eq!(attrs["Multi_Line"], "one\r\ntwo");
+        assert_eq!(attrs["Left2Right"], "Yes");
+    }
+}
@@ -0,0 +1,85 @@
+// Files mapped into memory and records sliced from the mappings
+#![cfg(feature = "mmap")]
+
+mod common;
+
+use common::*;
+use mdict::*;
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::PathBuf;
+
+// Write the file into the temporary directory
+fn write_temp_mdx(name: &str) -> (PathBuf, Vec<u8>) {
+    let file = write_mdx(40, MDictCompression::Zlib);
+    let path = std::env::temp_dir().join(format!("mdict-{}-{}.mdx", name, std::process::id()));
+    fs::write(&path, &file).unwrap();
+    (path, file)
+}
+
+#[test]
+fn same_records_as_file() {
+    let (path, file) = write_temp_mdx("mmap-records");
+    let map = unsafe { MDictMmap::open(&path).unwrap() };
+    assert_eq!(&map[..], &file[..]);
+    assert_eq!(map.size(), file.len() as u64);
+    assert_eq!(map.as_slice(), Some(&file[..]));
+
+    let mut index = MDictIndex::new(map.reader(), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let (file_blocks, file_keys) = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx)
+        .unwrap()
+        .make_index()
+        .unwrap();
+    assert_eq!(keys, file_keys);
+    assert_eq!(blocks.len(), file_blocks.len());
+    assert!(blocks.len() > 1);
+
+    let cache = MDictBlockCache::new(1 << 20);
+    for (i, (key, idx)) in keys.iter().enumerate() {
+        let record = lookup_slice(&map, idx, &blocks).unwrap();
+        assert_eq!(record, lookup(Cursor::new(&file), idx, &blocks).unwrap());
+        assert_eq!(record, lookup_source(&map, idx, &blocks).unwrap());
+        assert_eq!(
+            record,
+            lookup_slice_cached(&map, idx, &blocks, &cache, 0).unwrap()
+        );
+        assert_eq!(key, &format!("word{:02}", i));
+        let record = index.header().decode_record(record).unwrap();
+        assert_eq!(record, format!("<p>record {:02}</p>\0", i));
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn map_opened_file() {
+    let (path, file) = write_temp_mdx("mmap-opened");
+    let map = unsafe { MDictMmap::map(&File::open(&path).unwrap()).unwrap() };
+    // the mapping is kept after the file is closed
+    let header = MDictHeader::new(map.reader(), MDictMode::Mdx).unwrap();
+    assert_eq!(header.version(), MDictFormatVersion::V2);
+    assert_eq!(map.as_ref(), &file[..]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn truncated_slice() {
+    let (path, file) = write_temp_mdx("mmap-truncated");
+    fs::remove_file(&path).unwrap();
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let (_, last) = keys.last().unwrap();
+    let end = blocks[last.block as usize].offset as usize + 4;
+    match lookup_slice(&file[..end], last, &blocks) {
+        Err(MDictError::Truncated { actual: 4, .. }) => {}
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("record is sliced out of the data"),
+    }
+    // and a block starting after the end of data
+    let end = blocks[last.block as usize].offset as usize - 1;
+    match lookup_slice(&file[..end], last, &blocks) {
+        Err(MDictError::Truncated { actual: 0, .. }) => {}
+        Err(error) => panic!("unexpected error: {}", error),
+        Ok(_) => panic!("record is sliced out of the data"),
+    }
+}
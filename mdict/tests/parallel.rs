@@ -0,0 +1,109 @@
+// Keywords blocks and record blocks give the same results one by one as in batches,
+// which are mapped across the thread pool with the "parallel" feature
+
+mod common;
+
+use common::*;
+use mdict::*;
+use std::io::Cursor;
+
+// Corrupt the first byte of `needle` in the file
+fn corrupt(file: &mut [u8], needle: &[u8]) {
+    let pos = file
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .unwrap();
+    file[pos] ^= 0xff;
+}
+
+fn block_error(error: MDictError) -> (MDictBlockKind, usize) {
+    match error {
+        MDictError::BlockChecksum { kind, index, .. } => (kind, index),
+        error => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn decode_key_blocks_in_order() {
+    let file = write_mdx(200, MDictCompression::None);
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (key_blocks, _) = index.make_key_index().unwrap();
+    assert!(key_blocks.len() > 10);
+    let mut expected = Vec::new();
+    for (i, block) in key_blocks.iter().enumerate() {
+        expected.extend(read_key_block(Cursor::new(&file), index.header(), block, i).unwrap());
+    }
+
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    // offsets from the start of the uncompressed record blocks
+    let starts: Vec<_> = blocks
+        .iter()
+        .scan(0, |start, b| {
+            *start += b.uncomp_size;
+            Some(*start - b.uncomp_size)
+        })
+        .collect();
+    let keys: Vec<_> = keys
+        .into_iter()
+        .map(|(key, idx)| (key, starts[idx.block as usize] + idx.offset))
+        .collect();
+    assert_eq!(keys.len(), 200);
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn first_corrupted_key_block() {
+    let mut file = write_mdx(200, MDictCompression::None);
+    corrupt(&mut file, b"word150\0");
+    corrupt(&mut file, b"word050\0");
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (key_blocks, _) = index.make_key_index().unwrap();
+    let first = (0..key_blocks.len())
+        .find(|&i| read_key_block(Cursor::new(&file), index.header(), &key_blocks[i], i).is_err())
+        .unwrap();
+    assert!(first > 0);
+
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let error = index.make_index().err().unwrap();
+    assert_eq!(block_error(error), (MDictBlockKind::KeyBlock, first));
+}
+
+#[test]
+fn check_record_blocks_in_order() {
+    let mut file = write_mdx(200, MDictCompression::None);
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    assert!(blocks.len() > 10);
+    index.check_record_blocks(&blocks).unwrap();
+
+    corrupt(&mut file, b"record 150");
+    corrupt(&mut file, b"record 050");
+    // the corrupted blocks, looked up one by one
+    let mut bad = Vec::new();
+    for (_, idx) in &keys {
+        let block = idx.block as usize;
+        if !bad.contains(&block) && lookup(Cursor::new(&file), idx, &blocks).is_err() {
+            bad.push(block);
+        }
+    }
+    assert_eq!(bad.len(), 2);
+
+    // the error of the first corrupted block is returned
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let error = index.check_record_blocks(&blocks).unwrap_err();
+    assert_eq!(block_error(error), (MDictBlockKind::RecordBlock, bad[0]));
+
+    // all corrupted blocks are reported in order
+    let report = index.verify().unwrap();
+    let reported: Vec<_> = report
+        .bad_blocks
+        .iter()
+        .map(|b| (b.kind, b.index))
+        .collect();
+    let bad: Vec<_> = bad
+        .into_iter()
+        .map(|i| (MDictBlockKind::RecordBlock, i))
+        .collect();
+    assert_eq!(reported, bad);
+}
@@ -0,0 +1,174 @@
+// Records which span several record blocks
+//
+// MDictWriter never splits a record, so the files are built here with record blocks
+// cut at a fixed size regardless of the boundaries of records, like some large dictionaries.
+
+use mdict::*;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+// An uncompressed block: compression type, checksum and the data
+fn block(data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend(&0u32.to_le_bytes());
+    buf.extend(&adler::adler32_slice(data).to_be_bytes());
+    buf.extend(data);
+    buf
+}
+
+// Build a v2 mdx file in UTF-8 with one keyword block.
+// `keys` are keywords and offsets of their records in `records`.
+fn build_mdx(keys: &[(&str, u64)], records: &[u8], block_size: usize) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let tag = r#"<Dictionary GeneratedByEngineVersion="2.0" RequiredEngineVersion="2.0" Encrypted="No" Encoding="UTF-8" Format="Html" Title="Span"/>"#;
+    let tag: Vec<u8> = format!("{}\r\n\0", tag)
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes().to_vec())
+        .collect();
+    buf.extend(&(tag.len() as u32).to_be_bytes());
+    buf.extend(&tag);
+    buf.extend(&adler::adler32_slice(&tag).to_le_bytes());
+
+    let mut key_block = Vec::new();
+    for (key, offset) in keys {
+        key_block.extend(&offset.to_be_bytes());
+        key_block.extend(key.as_bytes());
+        key_block.push(0);
+    }
+    let key_block = block(&key_block);
+    let mut key_index = Vec::new();
+    key_index.extend(&(keys.len() as u64).to_be_bytes());
+    for word in [keys[0].0, keys[keys.len() - 1].0].iter() {
+        key_index.extend(&(word.len() as u16).to_be_bytes());
+        key_index.extend(word.as_bytes());
+        key_index.push(0);
+    }
+    key_index.extend(&(key_block.len() as u64).to_be_bytes());
+    key_index.extend(&(key_block.len() as u64 - 8).to_be_bytes());
+    let compressed_index = block(&key_index);
+    let mut key_header = Vec::new();
+    for n in [
+        1,
+        keys.len(),
+        key_index.len(),
+        compressed_index.len(),
+        key_block.len(),
+    ]
+    .iter()
+    {
+        key_header.extend(&(*n as u64).to_be_bytes());
+    }
+    buf.extend(&key_header);
+    buf.extend(&adler::adler32_slice(&key_header).to_be_bytes());
+    buf.extend(&compressed_index);
+    buf.extend(&key_block);
+
+    let blocks: Vec<(usize, Vec<u8>)> = records
+        .chunks(block_size)
+        .map(|c| (c.len(), block(c)))
+        .collect();
+    let blocks_size: usize = blocks.iter().map(|(_, b)| b.len()).sum();
+    for n in [blocks.len(), keys.len(), blocks.len() * 16, blocks_size].iter() {
+        buf.extend(&(*n as u64).to_be_bytes());
+    }
+    for (uncomp_size, b) in blocks.iter() {
+        buf.extend(&(b.len() as u64).to_be_bytes());
+        buf.extend(&(*uncomp_size as u64).to_be_bytes());
+    }
+    for (_, b) in blocks {
+        buf.extend(b);
+    }
+    buf
+}
+
+// Look up every keyword and decode its record
+fn lookup_all(
+    file: &[u8],
+) -> (
+    Vec<MDictRecordBlockIndex>,
+    HashMap<String, (MDictRecordIndex, String)>,
+) {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let header = index.into_header();
+    let records = keys
+        .into_iter()
+        .map(|(key, idx)| {
+            let record = lookup(Cursor::new(file), &idx, &blocks).unwrap();
+            (key, (idx, header.decode_string(record).unwrap()))
+        })
+        .collect();
+    (blocks, records)
+}
+
+#[test]
+fn record_spans_blocks() {
+    let long: String = (0..100).map(|i| (b'a' + i % 26) as char).collect();
+    let records = format!("short{}tail", long);
+    let keys = [("a", 0), ("b", 5), ("c", 105)];
+    let file = build_mdx(&keys, records.as_bytes(), 16);
+    let (blocks, records) = lookup_all(&file);
+    assert_eq!(blocks.len(), 7);
+    assert_eq!(records["a"].1, "short");
+    assert_eq!(records["b"].1, long);
+    assert_eq!(records["c"].1, "tail");
+    // "b" starts in the first block and ends in the 7th block
+    assert_eq!(records["b"].0.block, 0);
+    assert_eq!(records["b"].0.len, 100);
+    // the record after a long record starts in a later block
+    assert_eq!(records["c"].0.block, 6);
+    assert_eq!(records["c"].0.offset, 9);
+}
+
+#[test]
+fn keywords_share_record() {
+    let keys = [("color", 0), ("colour", 0), ("flavor", 10)];
+    let file = build_mdx(&keys, b"<b>hue</b><b>taste</b>", 4);
+    let (_, records) = lookup_all(&file);
+    assert_eq!(records["color"].1, "<b>hue</b>");
+    assert_eq!(records["colour"].1, "<b>hue</b>");
+    assert_eq!(records["flavor"].1, "<b>taste</b>");
+}
+
+#[test]
+fn records_iterates_spanning_records() {
+    let keys = [("a", 0), ("b", 3), ("c", 3), ("d", 15)];
+    let file = build_mdx(&keys, b"onetwothreefourfive", 4);
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let records: Vec<_> = index
+        .records()
+        .unwrap()
+        .decoded()
+        .map(|r| r.unwrap())
+        .collect();
+    let expected = [
+        ("a", "one"),
+        ("b", "twothreefour"),
+        ("c", "twothreefour"),
+        ("d", "five"),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(k, r)| (k.to_string(), r.to_string()))
+        .collect();
+    assert_eq!(records, expected);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn record_spans_blocks_async() {
+    let keys = [("a", 0), ("b", 3)];
+    let file = build_mdx(&keys, b"onetwothreefour", 2);
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let records: Vec<_> = keys
+        .iter()
+        .map(|(_, idx)| {
+            runtime
+                .block_on(lookup_async(Cursor::new(&file), idx, &blocks))
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(records, vec!["one", "twothreefour"]);
+}
@@ -0,0 +1,107 @@
+// Iteration of all records, reading and decompressing each record block once
+
+mod common;
+
+use common::*;
+use mdict::*;
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+
+// A source which counts the reads at each offset
+struct CountingSource {
+    data: Vec<u8>,
+    reads: Mutex<HashMap<u64, usize>>,
+}
+
+impl CountingSource {
+    fn new(data: Vec<u8>) -> CountingSource {
+        CountingSource {
+            data,
+            reads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn reads(&self, offset: u64) -> usize {
+        self.reads
+            .lock()
+            .unwrap()
+            .get(&offset)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl MDictSource for CountingSource {
+    fn size(&self) -> u64 {
+        self.data.size()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        *self.reads.lock().unwrap().entry(offset).or_insert(0) += 1;
+        self.data.read_exact_at(buf, offset)
+    }
+}
+
+#[test]
+fn read_each_block_once() {
+    let file = write_mdx(40, MDictCompression::Zlib);
+    let (blocks, keys) = MDictIndex::new(MDictSourceReader::new(&file), MDictMode::Mdx)
+        .unwrap()
+        .make_index()
+        .unwrap();
+    assert!(blocks.len() > 5);
+
+    let source = CountingSource::new(file.clone());
+    let mut index = MDictIndex::new(MDictSourceReader::new(&source), MDictMode::Mdx).unwrap();
+    let records: Vec<_> = index.records().unwrap().map(|r| r.unwrap()).collect();
+    assert_eq!(records.len(), keys.len());
+    for ((key, record), (expected_key, idx)) in records.iter().zip(&keys) {
+        assert_eq!(key, expected_key);
+        assert_eq!(record, &lookup_source(&file, idx, &blocks).unwrap());
+    }
+    for block in &blocks {
+        assert_eq!(source.reads(block.offset), 1);
+    }
+}
+
+#[test]
+fn error_of_block_for_each_record() {
+    let mut file = write_mdx(40, MDictCompression::None);
+    let (blocks, keys) = MDictIndex::new(MDictSourceReader::new(&file), MDictMode::Mdx)
+        .unwrap()
+        .make_index()
+        .unwrap();
+    // corrupt the third record block, which has more than one record
+    let bad = 2;
+    let in_bad: Vec<_> = keys
+        .iter()
+        .filter(|(_, idx)| idx.block as usize == bad)
+        .map(|(key, _)| key.clone())
+        .collect();
+    assert!(in_bad.len() > 1);
+    file[blocks[bad].offset as usize + 8] ^= 0xff;
+
+    let source = CountingSource::new(file);
+    let mut index = MDictIndex::new(MDictSourceReader::new(&source), MDictMode::Mdx).unwrap();
+    let records: Vec<_> = index.records().unwrap().collect();
+    assert_eq!(records.len(), keys.len());
+    let mut failed = Vec::new();
+    for (record, (key, _)) in records.into_iter().zip(&keys) {
+        match record {
+            Ok((k, _)) => assert_eq!(&k, key),
+            Err(MDictError::BlockChecksum {
+                kind: MDictBlockKind::RecordBlock,
+                index,
+                ..
+            }) => {
+                assert_eq!(index, bad);
+                failed.push(key.clone());
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+        }
+    }
+    // every keyword of the block fails, and the block is read only once
+    assert_eq!(failed, in_bad);
+    assert_eq!(source.reads(blocks[bad].offset), 1);
+}
@@ -0,0 +1,57 @@
+// Readers of sources read by positions
+
+use mdict::*;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+#[test]
+fn read_at_and_beyond_end() {
+    let data = b"0123456789".to_vec();
+    let mut reader = MDictSourceReader::new(&data);
+    let mut buf = [0; 4];
+    assert_eq!(reader.read(&mut buf).unwrap(), 4);
+    assert_eq!(&buf, b"0123");
+    let mut rest = Vec::new();
+    assert_eq!(reader.read_to_end(&mut rest).unwrap(), 6);
+    assert_eq!(rest, b"456789");
+
+    // reading at or beyond the end returns nothing, like a file
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(reader.seek(SeekFrom::End(5)).unwrap(), 15);
+    assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    assert_eq!(reader.read(&mut []).unwrap(), 0);
+    assert!(reader.read_exact(&mut buf).is_err());
+
+    assert_eq!(reader.seek(SeekFrom::Current(-7)).unwrap(), 8);
+    assert_eq!(reader.read(&mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"89");
+    assert!(reader.seek(SeekFrom::Current(-20)).is_err());
+}
+
+#[test]
+fn read_sub_source_beyond_end() {
+    let source = MDictSubSource::new(b"0123456789".to_vec(), 2, 4).unwrap();
+    let mut reader = MDictSourceReader::new(&source);
+    reader.seek(SeekFrom::Start(6)).unwrap();
+    assert_eq!(reader.read(&mut [0; 4]).unwrap(), 0);
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"2345");
+    assert!(MDictSubSource::new(b"0123".to_vec(), 2, 4).is_err());
+}
+
+#[test]
+fn parse_from_source() {
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer.add_entry("word", "record").unwrap();
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+
+    let mut index = MDictIndex::new(MDictSourceReader::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let record = lookup_source(&file, &keys[0].1, &blocks).unwrap();
+    assert_eq!(
+        record,
+        lookup(Cursor::new(&file), &keys[0].1, &blocks).unwrap()
+    );
+}
@@ -0,0 +1,51 @@
+// Styles of compact dictionaries
+
+use mdict::*;
+use std::io::Cursor;
+
+const STYLESHEET: &str = "1\n<b>\n</b>\n2\n<i>\n</i>\nx\n<u>\n</u>\n3\n<br>";
+
+#[test]
+fn parse_stylesheet() {
+    let stylesheet = MDictStyleSheet::parse(STYLESHEET);
+    let style = stylesheet.get(1).unwrap();
+    assert_eq!((style.begin.as_str(), style.end.as_str()), ("<b>", "</b>"));
+    assert_eq!(stylesheet.get(2).unwrap().begin, "<i>");
+    // a style with an invalid number is skipped, a style without end HTML is kept
+    assert!(stylesheet.get(4).is_none());
+    let style = stylesheet.get(3).unwrap();
+    assert_eq!((style.begin.as_str(), style.end.as_str()), ("<br>", ""));
+    assert!(MDictStyleSheet::parse("").is_empty());
+}
+
+#[test]
+fn expand_markers() {
+    let stylesheet = MDictStyleSheet::parse(STYLESHEET);
+    assert_eq!(
+        stylesheet.expand("`1`word`2`text"),
+        "<b>word</b><i>text</i>"
+    );
+    // the text before the first marker is kept, a marker of unknown style is removed
+    assert_eq!(stylesheet.expand("a`9`b`1`c"), "ab<b>c</b>");
+    // the end of style is put before the line break
+    assert_eq!(
+        stylesheet.expand("`1`word\n`2`text"),
+        "<b>word</b>\r\n<i>text</i>"
+    );
+    assert_eq!(stylesheet.expand("no `markers`"), "no `markers`");
+}
+
+#[test]
+fn expand_records_of_compact_dictionary() {
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer.attr("Compact", "Yes").attr("StyleSheet", STYLESHEET);
+    writer.add_entry("word", "`1`word`2`noun").unwrap();
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let record = lookup(Cursor::new(&file), &keys[0].1, &blocks).unwrap();
+    let record = index.header().decode_record(record).unwrap();
+    assert_eq!(record, "<b>word</b><i>noun\0</i>");
+}
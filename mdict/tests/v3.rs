@@ -0,0 +1,110 @@
+// MDict 3.0 files generated by tests/fixtures/make_v3.py
+
+use mdict::*;
+use std::io::Cursor;
+
+const PLAIN: &[u8] = include_bytes!("fixtures/v3.mdx");
+const FAST: &[u8] = include_bytes!("fixtures/v3_fast.mdx");
+const SALSA20: &[u8] = include_bytes!("fixtures/v3_salsa20.mdx");
+const UUID: &[u8] = include_bytes!("fixtures/v3_uuid.mdx");
+
+fn expected() -> Vec<(String, String)> {
+    let mut entries: Vec<_> = (0..20)
+        .map(|i| (format!("k{:02}", i), format!("<p>record {:02}</p>\0", i)))
+        .collect();
+    entries.push(("über".to_owned(), "<p>ü</p>\0".to_owned()));
+    entries
+}
+
+fn read_all(file: &[u8]) -> MDictResult<Vec<(String, String)>> {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx)?;
+    let (blocks, keys) = index.make_index()?;
+    keys.into_iter()
+        .map(|(key, idx)| {
+            let record = lookup(Cursor::new(file), &idx, &blocks)?;
+            Ok((key, index.header().decode_record(record)?))
+        })
+        .collect()
+}
+
+#[test]
+fn parse_header() {
+    let header = MDictHeader::new(Cursor::new(PLAIN), MDictMode::Mdx).unwrap();
+    assert_eq!(header.version(), MDictFormatVersion::V3);
+    assert_eq!(header.encoding(), encoding_rs::UTF_8);
+    assert_eq!(header.metadata().title.as_deref(), Some("Version 3"));
+}
+
+#[test]
+fn lookup_plain_blocks() {
+    assert_eq!(read_all(PLAIN).unwrap(), expected());
+}
+
+#[test]
+fn lookup_encrypted_blocks() {
+    assert_eq!(read_all(FAST).unwrap(), expected());
+    assert_eq!(read_all(SALSA20).unwrap(), expected());
+}
+
+#[test]
+fn key_blocks_and_record_blocks() {
+    let mut index = MDictIndex::new(Cursor::new(SALSA20), MDictMode::Mdx).unwrap();
+    let (key_blocks, record_blocks) = index.make_key_index().unwrap();
+    assert_eq!(key_blocks.len(), 6);
+    assert_eq!(record_blocks.len(), 6);
+    assert_eq!(key_blocks[0].first_word, "k00");
+    assert_eq!(key_blocks[0].last_word, "k03");
+    assert_eq!(key_blocks[0].block_entries, 4);
+    assert_eq!(key_blocks[5].first_word, "über");
+    assert_eq!(key_blocks[5].block_entries, 1);
+
+    let layout = index.layout().unwrap();
+    assert_eq!(layout.version, MDictFormatVersion::V3);
+    // records are stored and compressed by zlib in turn, all blocks are encrypted by Salsa20/8
+    let methods: Vec<_> = layout
+        .record_blocks
+        .iter()
+        .map(|block| (block.compression.method(), block.compression.encryption))
+        .collect();
+    assert_eq!(methods[0], (Some(MDictCompression::None), 2));
+    assert_eq!(methods[1], (Some(MDictCompression::Zlib), 2));
+    assert!(layout
+        .key_blocks
+        .iter()
+        .all(|block| block.compression.method() == Some(MDictCompression::Zlib)));
+}
+
+#[test]
+fn corrupted_encrypted_block() {
+    let mut file = SALSA20.to_vec();
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (_, record_blocks) = index.make_key_index().unwrap();
+    // the checksum is of the decrypted data, so a wrong key or a changed byte is detected
+    file[record_blocks[1].offset as usize + 8] ^= 0xff;
+    match read_all(&file) {
+        Err(MDictError::BlockChecksum { kind, .. }) => {
+            assert_eq!(kind, MDictBlockKind::RecordBlock)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn blocks_encrypted_with_uuid() {
+    let mut index = MDictIndex::new(Cursor::new(UUID), MDictMode::Mdx).unwrap();
+    match index.make_index() {
+        Err(MDictError::Unsupported(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn unknown_section() {
+    let mut file = PLAIN.to_vec();
+    file.extend_from_slice(&[0x05, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    match index.make_index() {
+        Err(MDictError::Malformed(message)) => assert!(message.contains("0x5000000")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
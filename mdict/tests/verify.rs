@@ -0,0 +1,175 @@
+// Verification of files with corrupted blocks
+
+mod common;
+
+use common::*;
+use mdict::*;
+use std::io::Cursor;
+
+fn verify(file: &[u8]) -> MDictVerifyReport {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx).unwrap();
+    index.verify().unwrap()
+}
+
+// Build the index by skipping the corrupted blocks
+fn salvage(
+    file: &[u8],
+) -> (
+    MDictHeader,
+    Vec<MDictRecordBlockIndex>,
+    Vec<(String, MDictRecordIndex)>,
+) {
+    let mut index = MDictIndex::new(Cursor::new(file), MDictMode::Mdx).unwrap();
+    index.set_salvage(true);
+    let (blocks, keys) = index.make_index().unwrap();
+    (index.into_header(), blocks, keys)
+}
+
+// The record written for `key`
+fn record(key: &str) -> String {
+    format!("<p>record {}</p>\0", &key[4..])
+}
+
+#[test]
+fn verify_intact_file() {
+    let report = verify(&write_mdx(40, MDictCompression::None));
+    assert!(report.is_ok());
+    assert_eq!(report.keywords, 40);
+    assert!(report.key_blocks > 1);
+    assert!(report.record_blocks > 1);
+}
+
+#[test]
+fn verify_corrupted_blocks() {
+    let mut file = write_mdx(40, MDictCompression::None);
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (key_blocks, record_blocks) = index.make_key_index().unwrap();
+    // the keywords before the last keyword block keep their records
+    let last = key_blocks.len() - 1;
+    let keywords = read_key_block(
+        Cursor::new(&file[..]),
+        index.header(),
+        &key_blocks[last],
+        last,
+    )
+    .unwrap()
+    .len();
+    // a byte after the compression type and checksum of the blocks
+    file[key_blocks[last].offset as usize + 12] ^= 0xff;
+    file[record_blocks[2].offset as usize + 10] ^= 0xff;
+    // an unknown compression type
+    file[record_blocks[4].offset as usize] = 0x7;
+
+    let report = verify(&file);
+    assert!(!report.is_ok());
+    assert_eq!(report.keywords, 40 - keywords);
+    assert_eq!(report.bad_blocks.len(), 3);
+
+    let key_block = &report.bad_blocks[0];
+    assert_eq!(key_block.kind, MDictBlockKind::KeyBlock);
+    assert_eq!(key_block.index, last);
+    assert_eq!(key_block.offset, key_blocks[last].offset);
+    assert!(matches!(key_block.error, MDictError::BlockChecksum { .. }));
+    // the keyword before the block is lost too
+    assert_eq!(
+        key_block.keywords,
+        vec![
+            key_blocks[last - 1].last_word.clone(),
+            key_blocks[last].first_word.clone(),
+            key_blocks[last].last_word.clone()
+        ]
+    );
+
+    let record_block = &report.bad_blocks[1];
+    assert_eq!(record_block.kind, MDictBlockKind::RecordBlock);
+    assert_eq!(record_block.index, 2);
+    assert_eq!(record_block.offset, record_blocks[2].offset);
+    assert_eq!(record_block.comp_size, record_blocks[2].comp_size);
+    assert!(matches!(
+        record_block.error,
+        MDictError::BlockChecksum { index: 2, .. }
+    ));
+    // 4 records of 17 bytes reach the block size of 64 bytes
+    assert_eq!(
+        record_block.keywords,
+        ["word08", "word09", "word10", "word11"]
+    );
+    assert!(matches!(
+        report.bad_blocks[2].error,
+        MDictError::UnknownCompression { magic: 0x7, .. }
+    ));
+
+    // while building the index stops at the corrupted keyword block
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    assert!(index.make_index().is_err());
+}
+
+#[test]
+fn salvage_corrupted_blocks() {
+    let mut file = write_mdx(40, MDictCompression::None);
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (key_blocks, record_blocks) = index.make_key_index().unwrap();
+    let last = key_blocks.len() - 1;
+    file[key_blocks[last].offset as usize + 12] ^= 0xff;
+    file[record_blocks[2].offset as usize + 10] ^= 0xff;
+
+    let (header, blocks, keys) = salvage(&file);
+    let report = verify(&file);
+    // the keyword before the corrupted keyword block is dropped
+    assert_eq!(keys.len(), report.keywords - 1);
+    assert!(!keys
+        .iter()
+        .any(|(key, _)| *key == key_blocks[last - 1].last_word));
+    let dropped: Vec<_> = blocks.iter().map(|b| b.dropped).collect();
+    assert_eq!(dropped.iter().filter(|d| **d).count(), 1);
+    assert!(dropped[2]);
+
+    for (key, idx) in keys.iter() {
+        let result = lookup(Cursor::new(&file[..]), idx, &blocks);
+        if report.bad_blocks[1].keywords.contains(key) {
+            assert!(matches!(
+                result,
+                Err(MDictError::DroppedBlock { index: 2, .. })
+            ));
+        } else {
+            let found = header.decode_record(result.unwrap()).unwrap();
+            assert_eq!(found, record(key));
+        }
+    }
+}
+
+#[test]
+fn salvage_keyword_block_in_the_middle() {
+    let mut file = write_mdx(40, MDictCompression::None);
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (key_blocks, record_blocks) = index.make_key_index().unwrap();
+    // 5 keywords of 15 bytes in a keyword block, and 4 records of 17 bytes in a record block
+    assert_eq!(key_blocks[4].first_word, "word20");
+    assert_eq!(key_blocks[4].last_word, "word24");
+    assert_eq!(record_blocks.len(), 10);
+    file[key_blocks[4].offset as usize + 12] ^= 0xff;
+    // a block of the records of the lost keywords
+    file[record_blocks[5].offset as usize + 10] ^= 0xff;
+
+    let (header, blocks, keys) = salvage(&file);
+    let found: Vec<_> = keys.iter().map(|(key, _)| key.as_str()).collect();
+    let expected: Vec<_> = (0..40)
+        .filter(|i| !(19..25).contains(i))
+        .map(|i| format!("word{:02}", i))
+        .collect();
+    assert_eq!(found, expected);
+    for (key, idx) in keys.iter() {
+        let found = lookup(Cursor::new(&file[..]), idx, &blocks).unwrap();
+        assert_eq!(header.decode_record(found).unwrap(), record(key));
+    }
+
+    let report = verify(&file);
+    assert_eq!(report.keywords, 35);
+    assert_eq!(
+        report.bad_blocks[0].keywords,
+        ["word19", "word20", "word24"]
+    );
+    // no keyword is left in the corrupted record block
+    assert_eq!(report.bad_blocks[1].index, 5);
+    assert!(report.bad_blocks[1].keywords.is_empty());
+}
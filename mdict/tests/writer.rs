@@ -0,0 +1,158 @@
+// Files written by MDictWriter read back by MDictIndex
+
+use mdict::*;
+use std::io::Cursor;
+
+fn read_back(file: &[u8], mode: MDictMode) -> (MDictHeader, Vec<(String, Vec<u8>)>) {
+    let mut index = MDictIndex::new(Cursor::new(file), mode).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    let entries = keys
+        .into_iter()
+        .map(|(key, idx)| {
+            let record = lookup(Cursor::new(file), &idx, &blocks).unwrap();
+            (key, record.to_vec())
+        })
+        .collect();
+    (index.into_header(), entries)
+}
+
+fn write_mdx(writer: &mut MDictWriter, keys: &[&str]) -> Vec<u8> {
+    for key in keys {
+        writer.add_entry(key, &format!("<p>{}</p>", key)).unwrap();
+    }
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+    file
+}
+
+#[test]
+fn mdx_round_trip() {
+    let keys = ["Apple", "Banana", "Zebra", "apple", "cherry", "zoo"];
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer
+        .title("Fruits")
+        .description("<b>Fruits</b> & more")
+        .attr("CreationDate", "2020-6-1")
+        .block_size(16, 16);
+    let file = write_mdx(&mut writer, &keys);
+    let (header, entries) = read_back(&file, MDictMode::Mdx);
+
+    let metadata = header.metadata();
+    assert_eq!(metadata.title.as_deref(), Some("Fruits"));
+    assert_eq!(
+        metadata.description.as_deref(),
+        Some("<b>Fruits</b> & more")
+    );
+    assert_eq!(metadata.creation_date.unwrap().to_string(), "2020-06-01");
+    assert_eq!(header.attrs.get("KeyCaseSensitive").unwrap(), "No");
+    assert_eq!(header.attrs.get("StripKey").unwrap(), "No");
+
+    // sorted case-insensitively, keywords of the same normalized form keep their order
+    let sorted: Vec<_> = entries.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(
+        sorted,
+        ["Apple", "apple", "Banana", "cherry", "Zebra", "zoo"]
+    );
+    for (key, record) in entries.iter() {
+        let record = header.decode_string(record.clone().into()).unwrap();
+        assert_eq!(record, format!("<p>{}</p>\0", key));
+    }
+}
+
+#[test]
+fn sorted_by_header_attributes() {
+    let keys = ["b-c", "a d", "B", "ab"];
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer.key_case_sensitive(true).strip_key(true);
+    let file = write_mdx(&mut writer, &keys);
+    let (header, entries) = read_back(&file, MDictMode::Mdx);
+    assert!(header.metadata().key_case_sensitive);
+    assert!(header.metadata().strip_key);
+    let sorted: Vec<_> = entries.iter().map(|(k, _)| k.as_str()).collect();
+    // "B" < "ab" < "ad" < "bc" in byte order
+    assert_eq!(sorted, ["B", "ab", "a d", "b-c"]);
+    let normalized: Vec<_> = sorted.iter().map(|k| header.normalize_key(k)).collect();
+    assert!(normalized.windows(2).all(|w| w[0] <= w[1]));
+}
+
+#[test]
+fn mdd_round_trip() {
+    let mut writer = MDictWriter::new(MDictMode::Mdd);
+    writer.add_resource("/img/b.png", &[1, 2, 3]).unwrap();
+    writer.add_resource("style.css", b"p {}").unwrap();
+    writer.add_resource("IMG\\a.png", &[4]).unwrap();
+    assert!(writer.add_entry("word", "record").is_err());
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+
+    let (_, entries) = read_back(&file, MDictMode::Mdd);
+    assert_eq!(
+        entries,
+        [
+            ("\\IMG\\a.png".to_owned(), vec![4]),
+            ("\\img\\b.png".to_owned(), vec![1, 2, 3]),
+            ("\\style.css".to_owned(), b"p {}".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn empty_round_trip() {
+    for &compression in [
+        MDictCompression::None,
+        MDictCompression::Lzo,
+        MDictCompression::Zlib,
+    ]
+    .iter()
+    {
+        for &mode in [MDictMode::Mdx, MDictMode::Mdd].iter() {
+            let mut writer = MDictWriter::new(mode);
+            writer.compression(compression);
+            let mut file = Vec::new();
+            writer.write(&mut file).unwrap();
+            let (_, entries) = read_back(&file, mode);
+            assert!(entries.is_empty());
+        }
+    }
+}
+
+#[test]
+fn utf_16_round_trip() {
+    for &encoding in [encoding_rs::UTF_16LE, encoding_rs::UTF_16BE].iter() {
+        let mut writer = MDictWriter::new(MDictMode::Mdx);
+        writer.encoding(encoding);
+        let file = write_mdx(&mut writer, &["中文", "apple"]);
+        let (header, entries) = read_back(&file, MDictMode::Mdx);
+        assert_eq!(header.encoding(), encoding);
+        let keys: Vec<_> = entries.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["apple", "中文"]);
+        let record = header.decode_string(entries[1].1.clone().into()).unwrap();
+        assert_eq!(record, "<p>中文</p>\0");
+    }
+}
+
+#[test]
+fn encoding_after_entries() {
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer.add_entry("中文", "<p>中文</p>").unwrap();
+    // the entry already added is written in GBK too
+    writer.encoding(encoding_rs::GBK);
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+    let (header, entries) = read_back(&file, MDictMode::Mdx);
+    assert_eq!(header.encoding(), encoding_rs::GBK);
+    assert_eq!(entries[0].0, "中文");
+    let record = header.decode_string(entries[0].1.clone().into()).unwrap();
+    assert_eq!(record, "<p>中文</p>\0");
+
+    // but fails if it can't be encoded
+    writer.encoding(encoding_rs::WINDOWS_1252);
+    let error = writer.write(&mut Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    let error = writer.add_entry("中", "").unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+    // encoding_rs encodes replacement into UTF-8
+    writer.encoding(encoding_rs::REPLACEMENT);
+    assert!(writer.add_entry("word", "record").is_err());
+}
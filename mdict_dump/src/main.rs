@@ -13,7 +13,9 @@
 //!
 //! This program will panic if the mdx file is invalid or can't be opened by `mdict`
 
-use mdict_index::*;
+use mdict::{MDictIndex, MDictMode};
+use mdict_index::{MDictLookup, MDictMemIndex};
+use std::fs::File;
 use std::io::{stderr, Write};
 use std::path::Path;
 use std::env;
@@ -26,6 +28,8 @@ fn usage(program: &str) {
     Available commands:\n\
     \tkey:       print all keys\n\
     \tsearch:    search and dump the content of a key\n\
+    \tverify:    check all blocks of mdx and mdd files\n\
+    \tlayout:    print the keyword blocks and record blocks of a mdx or mdd file\n\
     ", program);
     stderr().write(usage.as_bytes()).unwrap();
 }
@@ -39,21 +43,96 @@ fn do_keys(args: Vec<String>) {
     }
 }
 
-async fn do_search(args: Vec<String>) {
+fn do_search(args: Vec<String>) {
     let file = &args.get(0).expect("MDX file is required");
     let key = &args.get(1).expect("key for search is required");
 
     let mdx_file = Path::new(file).canonicalize().unwrap();
     let index = MDictMemIndex::new(mdx_file).unwrap();
-    if let Some(result) = index.lookup_word(key).await.ok() {
+    if let Some(result) = index.lookup_word(key).ok() {
         println!("Content:\n{result:?}");
     } else {
         println!("not found");
     }
 }
 
-#[tokio::main]
-async fn main() {
+fn do_verify(args: Vec<String>) {
+    let mut ok = true;
+    for file in args.iter() {
+        let mode = if file.ends_with(".mdd") {
+            MDictMode::Mdd
+        } else {
+            MDictMode::Mdx
+        };
+        let report = File::open(file)
+            .map_err(Into::into)
+            .and_then(|f| MDictIndex::new(f, mode))
+            .and_then(|mut index| index.verify());
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                println!("{}: {}", file, e);
+                ok = false;
+                continue;
+            }
+        };
+        println!(
+            "{}: {} keyword blocks, {} record blocks, {} keywords",
+            file, report.key_blocks, report.record_blocks, report.keywords
+        );
+        for block in report.bad_blocks.iter() {
+            println!(
+                "  {} {} at {}: {}, keywords: {:?}",
+                block.kind, block.index, block.offset, block.error, block.keywords
+            );
+        }
+        for (key, offset) in report.bad_records.iter() {
+            println!("  record of {} at {} is out of record blocks", key, offset);
+        }
+        ok &= report.is_ok();
+    }
+    if !ok {
+        exit(1);
+    }
+}
+
+fn do_layout(args: Vec<String>) {
+    let file = &args.get(0).expect("MDX or MDD file is required");
+    let mode = if file.ends_with(".mdd") {
+        MDictMode::Mdd
+    } else {
+        MDictMode::Mdx
+    };
+    let mut index = MDictIndex::new(File::open(file).unwrap(), mode).unwrap();
+    let layout = index.layout().unwrap();
+    println!(
+        "{:?}: {} keyword blocks, {} record blocks",
+        layout.version,
+        layout.key_blocks.len(),
+        layout.record_blocks.len()
+    );
+    for (i, block) in layout.key_blocks.iter().enumerate() {
+        println!(
+            "keyword block {} at {}: {} -> {} bytes, {}, {} keywords from {:?} to {:?}",
+            i,
+            block.offset,
+            block.comp_size,
+            block.uncomp_size,
+            block.compression,
+            block.block_entries,
+            block.first_word,
+            block.last_word
+        );
+    }
+    for (i, block) in layout.record_blocks.iter().enumerate() {
+        println!(
+            "record block {} at {}: {} -> {} bytes, {}",
+            i, block.offset, block.comp_size, block.uncomp_size, block.compression
+        );
+    }
+}
+
+fn main() {
     if env::var_os("RUST_LOG").is_none() {
         env::set_var("RUST_LOG", "info");
     }
@@ -62,7 +141,9 @@ async fn main() {
     let command = env::args().nth(1).expect("command is required");
     match command.as_str() {
         "key" => do_keys(env::args().skip(2).collect()),
-        "search" => do_search(env::args().skip(2).collect()).await,
+        "search" => do_search(env::args().skip(2).collect()),
+        "verify" => do_verify(env::args().skip(2).collect()),
+        "layout" => do_layout(env::args().skip(2).collect()),
         _ => {
             println!("unknown command {command}");
             usage(program.as_str());
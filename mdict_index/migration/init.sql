@@ -5,29 +5,34 @@ CREATE TABLE meta (
 CREATE TABLE mdx_block (
     block_index integer primary key not null,
     block_offset bigint not null,
-    block_size bigint not null
+    block_size bigint not null,
+    block_uncomp_size bigint not null,
+    block_dropped boolean not null default 0
 );
 CREATE TABLE mdx_index (
     id integer primary key not null,
     keyword text not null,
-    block_index integer not null,
-    record_offset integer not null,
-    record_size integer not null,
+    headword text not null,
+    block_index bigint not null,
+    record_offset bigint not null,
+    record_size bigint not null,
     foreign key (block_index) references mdx_block(block_index)
 );
 CREATE TABLE mdd_block (
     file_index integer,
-    block_index integer,
+    block_index bigint,
     block_offset bigint not null,
     block_size bigint not null,
+    block_uncomp_size bigint not null,
+    block_dropped boolean not null default 0,
     primary key (file_index, block_index)
 );
 CREATE TABLE mdd_index (
     id integer primary key not null,
     keyword text not null,
     file_index integer not null,
-    block_index integer not null,
-    record_offset integer not null,
-    record_size integer not null,
+    block_index bigint not null,
+    record_offset bigint not null,
+    record_size bigint not null,
     foreign key (file_index, block_index) references mdd_block(file_index, block_index)
 );
@@ -0,0 +1,148 @@
+use crate::*;
+use std::collections::HashSet;
+use std::io::Read;
+use std::sync::Mutex;
+use zip::result::ZipError;
+use zip::{CompressionMethod, ZipArchive};
+
+// The mdx file and the mdd files of a dictionary
+type MDictSources = (Box<dyn MDictSource>, Vec<Box<dyn MDictSource>>);
+
+/// A dictionary in a zip archive, only available with the "zip" crate feature.
+///
+/// The archive contains a mdx file, its mdd files named like files in a directory:
+/// `a.mdx`, `a.mdd`, `a.1.mdd` ..., and other files used by the records, such as CSS and fonts.
+/// The first mdx file by name is taken if there are many.
+///
+/// The entries may be stored or deflated. Stored entries are read in place from the archive,
+/// deflated mdx and mdd files are decompressed into memory when they are opened.
+/// [`MDictMemIndex::new`] and `MDictSqliteIndex::new` open a path to a `.zip` file by this,
+/// `MDictSqliteIndex::from_archive` indexes an opened archive.
+pub struct MDictArchive {
+    path: PathBuf,
+    archive: Mutex<ZipArchive<File>>,
+    // the archive read by positioned reads, for stored entries
+    source: Arc<MDictFileSource>,
+    mdx: String,
+    mdds: Vec<String>,
+    // the directory of the mdx file in the archive, ends with `/` unless it is empty
+    dir: String,
+}
+
+impl MDictArchive {
+    /// Open the archive at `path` and find the dictionary in it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MDictArchive> {
+        let path = path.as_ref().canonicalize()?;
+        let archive = ZipArchive::new(File::open(&path)?).map_err(zip_error)?;
+        let source = Arc::new(MDictFileSource::open(&path)?);
+        let names: HashSet<&str> = archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .collect();
+        let mdx = names
+            .iter()
+            .filter(|name| has_extension(Path::new(name), "mdx"))
+            .min()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Expect a mdx file in the archive",
+                )
+            })?
+            .to_string();
+        info!("mdx: {} in {}", mdx, path.to_string_lossy());
+        let mut mdds = Vec::new();
+        for ext in mdd_extensions() {
+            let mdd = Path::new(&mdx).with_extension(ext);
+            match mdd.to_str() {
+                Some(mdd) if names.contains(mdd) => {
+                    info!("mdd: {} in {}", mdd, path.to_string_lossy());
+                    mdds.push(mdd.to_string());
+                }
+                _ => break,
+            }
+        }
+        let dir = match mdx.rfind('/') {
+            Some(i) => mdx[..=i].to_string(),
+            None => String::new(),
+        };
+        Ok(MDictArchive {
+            path,
+            archive: Mutex::new(archive),
+            source,
+            mdx,
+            mdds,
+            dir,
+        })
+    }
+
+    /// The canonical path of the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the mdx file in the archive.
+    pub fn mdx_name(&self) -> &str {
+        &self.mdx
+    }
+
+    /// The names of the mdd files in the archive, in the order of `.mdd`, `.1.mdd`, `.2.mdd` ...
+    pub fn mdd_names(&self) -> &[String] {
+        &self.mdds
+    }
+
+    /// The mdx file and the mdd files, which can be indexed by [`MDictMemIndex::from_sources`].
+    pub fn sources(&self) -> io::Result<MDictSources> {
+        let mdx = self.source(&self.mdx)?;
+        let mdds = self
+            .mdds
+            .iter()
+            .map(|mdd| self.source(mdd))
+            .collect::<io::Result<_>>()?;
+        Ok((mdx, mdds))
+    }
+
+    /// Read a file next to the mdx file in the archive, such as a CSS or JS file of the dictionary.
+    ///
+    /// `name` is relative to the directory of the mdx file. Returns an error of kind
+    /// [`NotFound`](io::ErrorKind::NotFound) if there isn't such a file.
+    pub fn read_file(&self, name: &str) -> io::Result<Vec<u8>> {
+        let name = format!("{}{}", self.dir, name);
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_name(&name).map_err(zip_error)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn source(&self, name: &str) -> io::Result<Box<dyn MDictSource>> {
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_name(name).map_err(zip_error)?;
+        match file.compression() {
+            CompressionMethod::Stored => {
+                let source =
+                    MDictSubSource::new(self.source.clone(), file.data_start(), file.size())?;
+                Ok(Box::new(source))
+            }
+            CompressionMethod::Deflated => {
+                let now = std::time::Instant::now();
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                info!("Decompress {} in {:?}", name, now.elapsed());
+                Ok(Box::new(data))
+            }
+            method => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported compression {:?} of {}", method, name),
+            )),
+        }
+    }
+}
+
+fn zip_error(e: ZipError) -> io::Error {
+    match e {
+        ZipError::Io(e) => e,
+        ZipError::FileNotFound => io::Error::new(io::ErrorKind::NotFound, e),
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
@@ -0,0 +1,516 @@
+use crate::*;
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Number of decoded keyword blocks kept in memory for each file
+const KEY_BLOCK_CACHE_SIZE: usize = 16;
+
+// Keywords in a keyword block and offsets of their records
+type MDictKeyBlock = Arc<Vec<(String, u64)>>;
+
+// Index of a mdx or mdd file which only has the index of blocks in memory
+struct MDictDiskFile {
+    file: MDictFile,
+    // the path of the file for messages, kept after the file is mapped
+    name: String,
+    header: MDictHeader,
+    key_blocks: Vec<MDictKeyBlockIndex>,
+    record_blocks: Vec<MDictRecordBlockIndex>,
+    // offset of each record block from the start of the uncompressed record blocks
+    record_offsets: Vec<u64>,
+    records_size: u64,
+    // most recently used keyword blocks come first
+    cache: Mutex<VecDeque<(usize, MDictKeyBlock)>>,
+    // whether the keyword blocks are sorted so that they can be searched, see `check_order`
+    // and `check_block_order`
+    sorted: AtomicBool,
+}
+
+impl MDictDiskFile {
+    fn new(path: PathBuf, mode: MDictMode) -> io::Result<MDictDiskFile> {
+        let now = std::time::Instant::now();
+        let mut index = MDictIndex::new(OpenOptions::new().read(true).open(&path)?, mode)?;
+        let (key_blocks, record_blocks) = index.make_key_index()?;
+        let header = index.into_header();
+        let mut records_size = 0u64;
+        let record_offsets = record_blocks
+            .iter()
+            .map(|b| {
+                let offset = records_size;
+                records_size = records_size.saturating_add(b.uncomp_size);
+                offset
+            })
+            .collect();
+        info!(
+            "Read {} keyword blocks of {} in {:?}",
+            key_blocks.len(),
+            path.to_string_lossy(),
+            now.elapsed()
+        );
+        Ok(MDictDiskFile {
+            name: path.to_string_lossy().into_owned(),
+            file: MDictFile::Path(path),
+            header,
+            key_blocks,
+            record_blocks,
+            record_offsets,
+            records_size,
+            cache: Mutex::new(VecDeque::with_capacity(KEY_BLOCK_CACHE_SIZE)),
+            sorted: AtomicBool::new(false),
+        })
+    }
+
+    // Check that the first and last keywords of blocks are in order after `normalize`.
+    // Files written by other tools may sort the keywords in another form, such as the
+    // original keywords in byte order, then every keyword block is searched.
+    fn check_order<F: Fn(&str) -> String>(&self, normalize: F) {
+        let bounds: Vec<String> = self
+            .key_blocks
+            .iter()
+            .flat_map(|b| vec![normalize(&b.first_word), normalize(&b.last_word)])
+            .collect();
+        let sorted = bounds.windows(2).all(|w| w[0] <= w[1]);
+        if !sorted {
+            warn!(
+                "Keyword blocks of {} are not sorted by the normalized keyword, \
+                 all {} keyword blocks are searched for a lookup",
+                self.name,
+                self.key_blocks.len()
+            );
+        }
+        self.sorted.store(sorted, Ordering::Relaxed);
+    }
+
+    // Check that the keywords of a decoded block are in order after `normalize`, or a keyword
+    // may be out of the first and last keyword of its block. It is only found when the block
+    // is decoded, then every keyword block is searched for the following lookups.
+    fn check_block_order<F: Fn(&str) -> String>(
+        &self,
+        block: usize,
+        words: &[(String, u64)],
+        normalize: &F,
+    ) {
+        if !self.sorted.load(Ordering::Relaxed) {
+            return;
+        }
+        let words: Vec<String> = words.iter().map(|(w, _)| normalize(w)).collect();
+        if words.windows(2).all(|w| w[0] <= w[1]) {
+            return;
+        }
+        if self.sorted.swap(false, Ordering::Relaxed) {
+            warn!(
+                "Keywords of keyword block {} of {} are not sorted by the normalized keyword, \
+                 all {} keyword blocks are searched for a lookup",
+                block,
+                self.name,
+                self.key_blocks.len()
+            );
+        }
+    }
+
+    // The keyword blocks which may contain `key`, keywords are compared after `normalize`.
+    // MDict sorts the keywords in this form, so that they can be searched by the first and
+    // last keyword of blocks.
+    fn search_blocks<F: Fn(&str) -> String>(&self, key: &str, normalize: &F) -> Range<usize> {
+        if !self.sorted.load(Ordering::Relaxed) {
+            return 0..self.key_blocks.len();
+        }
+        let start = self
+            .key_blocks
+            .partition_point(|b| normalize(&b.last_word).as_str() < key);
+        let end = start
+            + self.key_blocks[start..]
+                .iter()
+                .take_while(|b| normalize(&b.first_word).as_str() <= key)
+                .count();
+        start..end
+    }
+
+    fn cached_key_block(&self, block: usize) -> Option<MDictKeyBlock> {
+        let mut cache = self.cache.lock().unwrap();
+        let pos = cache.iter().position(|(i, _)| *i == block)?;
+        let entry = cache.remove(pos)?;
+        let words = entry.1.clone();
+        cache.push_front(entry);
+        Some(words)
+    }
+
+    fn cache_key_block<F: Fn(&str) -> String>(
+        &self,
+        block: usize,
+        words: Vec<(String, u64)>,
+        normalize: &F,
+    ) -> MDictKeyBlock {
+        self.check_block_order(block, &words, normalize);
+        let words = Arc::new(words);
+        let mut cache = self.cache.lock().unwrap();
+        cache.push_front((block, words.clone()));
+        cache.truncate(KEY_BLOCK_CACHE_SIZE);
+        words
+    }
+
+    fn key_block<F: Fn(&str) -> String>(
+        &self,
+        block: usize,
+        normalize: &F,
+    ) -> io::Result<MDictKeyBlock> {
+        if let Some(words) = self.cached_key_block(block) {
+            return Ok(words);
+        }
+        let words = read_key_block(
+            self.file.reader()?,
+            &self.header,
+            &self.key_blocks[block],
+            block,
+        )?;
+        Ok(self.cache_key_block(block, words, normalize))
+    }
+
+    // The end of the record at `offset`, where the next record starts in the following
+    // keyword blocks. Keywords sharing one record have the same offset.
+    fn record_end<F: Fn(&str) -> String>(
+        &self,
+        block: usize,
+        offset: u64,
+        normalize: &F,
+    ) -> io::Result<u64> {
+        for next in block + 1..self.key_blocks.len() {
+            if let Some(end) = next_offset(&self.key_block(next, normalize)?, offset) {
+                return Ok(end);
+            }
+        }
+        Ok(self.records_size)
+    }
+
+    // Find the records of `key` in the keyword blocks, all blocks are searched again
+    // if a block out of order is found in this search
+    fn search<F: Fn(&str) -> String>(
+        &self,
+        key: &str,
+        normalize: F,
+    ) -> io::Result<Vec<MDictRecordIndex>> {
+        let sorted = self.sorted.load(Ordering::Relaxed);
+        let result = self.search_in(self.search_blocks(key, &normalize), key, &normalize)?;
+        if sorted && !self.sorted.load(Ordering::Relaxed) {
+            return self.search_in(0..self.key_blocks.len(), key, &normalize);
+        }
+        Ok(result)
+    }
+
+    fn search_in<F: Fn(&str) -> String>(
+        &self,
+        blocks: Range<usize>,
+        key: &str,
+        normalize: &F,
+    ) -> io::Result<Vec<MDictRecordIndex>> {
+        let mut result = vec![];
+        for block in blocks {
+            let words = self.key_block(block, normalize)?;
+            for (i, (word, offset)) in words.iter().enumerate() {
+                if normalize(word) != key {
+                    continue;
+                }
+                let end = match next_offset(&words[i + 1..], *offset) {
+                    Some(end) => end,
+                    None => self.record_end(block, *offset, normalize)?,
+                };
+                result.push(self.record_index(*offset, end)?);
+            }
+        }
+        Ok(result)
+    }
+
+    // Map the offsets of a record in uncompressed record blocks to the index of record
+    fn record_index(&self, start: u64, end: u64) -> io::Result<MDictRecordIndex> {
+        let block = self.record_offsets.partition_point(|o| *o <= start);
+        if block == 0 || start >= self.records_size || end < start {
+            return Err(MDictError::Malformed(format!(
+                "Record at {} is out of record blocks",
+                start
+            ))
+            .into());
+        }
+        let block = block - 1;
+        Ok(MDictRecordIndex {
+            block: block as u64,
+            offset: start - self.record_offsets[block],
+            len: end - start,
+        })
+    }
+
+    fn lookup(
+        &self,
+        index: &MDictRecordIndex,
+        cache: Option<(&MDictBlockCache, u64)>,
+    ) -> io::Result<Bytes> {
+        self.file.lookup(index, &self.record_blocks, cache)
+    }
+}
+
+// The offset of the first record after `offset`
+fn next_offset(words: &[(String, u64)], offset: u64) -> Option<u64> {
+    words.iter().map(|(_, o)| *o).find(|o| *o > offset)
+}
+
+#[cfg(feature = "async")]
+impl MDictDiskFile {
+    async fn key_block_async<F: Fn(&str) -> String>(
+        &self,
+        block: usize,
+        normalize: &F,
+    ) -> io::Result<MDictKeyBlock> {
+        let path = match &self.file {
+            MDictFile::Path(path) => path,
+            MDictFile::Source(_) => return self.key_block(block, normalize),
+        };
+        if let Some(words) = self.cached_key_block(block) {
+            return Ok(words);
+        }
+        let file = tokio::fs::OpenOptions::new().read(true).open(path).await?;
+        let words =
+            read_key_block_async(file, &self.header, &self.key_blocks[block], block).await?;
+        Ok(self.cache_key_block(block, words, normalize))
+    }
+
+    async fn record_end_async<F: Fn(&str) -> String>(
+        &self,
+        block: usize,
+        offset: u64,
+        normalize: &F,
+    ) -> io::Result<u64> {
+        for next in block + 1..self.key_blocks.len() {
+            if let Some(end) = next_offset(&self.key_block_async(next, normalize).await?, offset) {
+                return Ok(end);
+            }
+        }
+        Ok(self.records_size)
+    }
+
+    async fn search_async<F: Fn(&str) -> String>(
+        &self,
+        key: &str,
+        normalize: F,
+    ) -> io::Result<Vec<MDictRecordIndex>> {
+        let sorted = self.sorted.load(Ordering::Relaxed);
+        let blocks = self.search_blocks(key, &normalize);
+        let result = self.search_in_async(blocks, key, &normalize).await?;
+        if sorted && !self.sorted.load(Ordering::Relaxed) {
+            let blocks = 0..self.key_blocks.len();
+            return self.search_in_async(blocks, key, &normalize).await;
+        }
+        Ok(result)
+    }
+
+    async fn search_in_async<F: Fn(&str) -> String>(
+        &self,
+        blocks: Range<usize>,
+        key: &str,
+        normalize: &F,
+    ) -> io::Result<Vec<MDictRecordIndex>> {
+        let mut result = vec![];
+        for block in blocks {
+            let words = self.key_block_async(block, normalize).await?;
+            for (i, (word, offset)) in words.iter().enumerate() {
+                if normalize(word) != key {
+                    continue;
+                }
+                let end = match next_offset(&words[i + 1..], *offset) {
+                    Some(end) => end,
+                    None => self.record_end_async(block, *offset, normalize).await?,
+                };
+                result.push(self.record_index(*offset, end)?);
+            }
+        }
+        Ok(result)
+    }
+
+    async fn lookup_async(
+        &self,
+        index: &MDictRecordIndex,
+        cache: Option<(&MDictBlockCache, u64)>,
+    ) -> io::Result<Bytes> {
+        self.file.lookup_async(index, &self.record_blocks, cache).await
+    }
+}
+
+/// Index of a MDict Dictionary which keeps only the index of keyword blocks and record blocks
+/// in memory.
+///
+/// A lookup searches the keyword blocks by their first and last keywords, and decodes the
+/// keyword blocks which may contain the keyword. The recently used keyword blocks are cached.
+/// This uses much less memory than [`MDictMemIndex`] for large dictionaries, at the cost of
+/// decoding a keyword block for a lookup which misses the cache.
+///
+/// The keywords are expected to be sorted in the form normalized by [`MDictHeader::normalize_key`],
+/// as MdxBuilder does. This is checked by the first and last keywords of blocks when the files
+/// are opened, a file sorted in another way is searched through all of its keyword blocks.
+/// The keywords in a block are checked when the block is decoded, a block out of order makes
+/// the lookups search all keyword blocks from then on, but a keyword in that block may be
+/// missed by the lookups before.
+pub struct MDictDiskIndex {
+    mdx: MDictDiskFile,
+    mdds: Vec<MDictDiskFile>,
+    cache: Option<MDictFileCache>,
+}
+
+impl MDictDiskIndex {
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<MDictDiskIndex> {
+        let (mdx_file, mdd_files) = find_files(path)?;
+        let mdx = MDictDiskFile::new(mdx_file, MDictMode::Mdx)?;
+        mdx.check_order(|k| mdx.header.normalize_key(k));
+        let mdds = mdd_files
+            .into_iter()
+            .map(|file| MDictDiskFile::new(file, MDictMode::Mdd))
+            .collect::<io::Result<Vec<_>>>()?;
+        // resources are searched by the normalization of the mdx file, see `lookup_resource`
+        for mdd in mdds.iter() {
+            mdd.check_order(|k| normalize_resource(&mdx.header, k));
+        }
+        Ok(MDictDiskIndex {
+            mdx,
+            mdds,
+            cache: None,
+        })
+    }
+
+    #[cfg(feature = "async")]
+    /// Asynchronous version of [`MDictDiskIndex::new`], the files are parsed in the blocking
+    /// thread pool of tokio.
+    pub async fn new_async<P: AsRef<Path>>(path: P) -> io::Result<MDictDiskIndex> {
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || MDictDiskIndex::new(path))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// The header of the mdx file.
+    pub fn header(&self) -> &MDictHeader {
+        &self.mdx.header
+    }
+
+    /// Keep the decompressed record blocks of this dictionary in `cache`, which may be shared
+    /// with other dictionaries.
+    pub fn set_block_cache(&mut self, cache: Arc<MDictBlockCache>) {
+        self.cache = Some(MDictFileCache::new(cache, 1 + self.mdds.len()));
+    }
+
+    #[cfg(feature = "mmap")]
+    /// Map the mdx and mdd files into memory, like [`MDictMemIndex::map_files`].
+    /// The keyword blocks are also read from the mappings.
+    ///
+    /// # Safety
+    ///
+    /// The files must not be modified or truncated while this index is alive,
+    /// see [`MDictMmap::open`].
+    pub unsafe fn map_files(&mut self) -> io::Result<()> {
+        self.mdx.file.map()?;
+        for mdd in self.mdds.iter_mut() {
+            mdd.file.map()?;
+        }
+        Ok(())
+    }
+
+    // The cache of the `file`th file, see `MDictFileCache`
+    fn file_cache(&self, file: usize) -> Option<(&MDictBlockCache, u64)> {
+        self.cache.as_ref().map(|c| c.file(file))
+    }
+
+    // Keys of mdd files are paths like `\\a\\b.png`, sorted without replacing the separator
+    fn resource_key(&self, key: &str) -> String {
+        let key = format!("\\{}", key.replace('/', "\\"));
+        normalize_resource(self.header(), &key)
+    }
+}
+
+impl MDictLookup for MDictDiskIndex {
+    fn word_exists(&self, key: &str) -> io::Result<bool> {
+        let header = self.header();
+        let key = header.normalize_key(key);
+        Ok(!self.mdx.search(&key, |k| header.normalize_key(k))?.is_empty())
+    }
+    fn lookup_word(&self, key: &str) -> io::Result<Vec<String>> {
+        let header = self.header();
+        let key = header.normalize_key(key);
+        let indexes = self.mdx.search(&key, |k| header.normalize_key(k))?;
+        if indexes.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Not found in index",
+            ));
+        }
+        let mut result = vec![];
+        for idx in indexes {
+            let bytes = self.mdx.lookup(&idx, self.file_cache(0))?;
+            let decoded = header.decode_record(bytes)?;
+            result.push(decoded);
+        }
+        Ok(result)
+    }
+
+    fn lookup_resource(&self, key: &str) -> io::Result<Bytes> {
+        let key = self.resource_key(key);
+        for (i, mdd) in self.mdds.iter().enumerate() {
+            let indexes = mdd.search(&key, |k| normalize_resource(self.header(), k))?;
+            if let Some(idx) = indexes.first() {
+                return mdd.lookup(idx, self.file_cache(1 + i));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Not found in index",
+        ))
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait]
+impl MDictAsyncLookup for MDictDiskIndex {
+    async fn word_exists(&self, key: &str) -> io::Result<bool> {
+        let header = self.header();
+        let key = header.normalize_key(key);
+        let indexes = self
+            .mdx
+            .search_async(&key, |k| header.normalize_key(k))
+            .await?;
+        Ok(!indexes.is_empty())
+    }
+    async fn lookup_word(&self, key: &str) -> io::Result<Vec<String>> {
+        let header = self.header();
+        let key = header.normalize_key(key);
+        let indexes = self
+            .mdx
+            .search_async(&key, |k| header.normalize_key(k))
+            .await?;
+        if indexes.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Not found in index",
+            ));
+        }
+        let mut result = vec![];
+        for idx in indexes {
+            let bytes = self.mdx.lookup_async(&idx, self.file_cache(0)).await?;
+            let decoded = header.decode_record(bytes)?;
+            result.push(decoded);
+        }
+        Ok(result)
+    }
+
+    async fn lookup_resource(&self, key: &str) -> io::Result<Bytes> {
+        let key = self.resource_key(key);
+        for (i, mdd) in self.mdds.iter().enumerate() {
+            let indexes = mdd
+                .search_async(&key, |k| normalize_resource(self.header(), k))
+                .await?;
+            if let Some(idx) = indexes.first() {
+                return mdd.lookup_async(idx, self.file_cache(1 + i)).await;
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Not found in index",
+        ))
+    }
+}
@@ -1,12 +1,17 @@
 use bytes::Bytes;
+use either::Either;
 use log::*;
 use mdict::*;
 use patricia_tree::PatriciaMap;
-use std::{fs::OpenOptions, io, path::{Path, PathBuf}};
+use std::{fs::{File, OpenOptions}, io, path::{Path, PathBuf}, sync::Arc};
 
+// The original keyword and the index of its record
+type MDictEntry = (String, MDictRecordIndex);
+
+// All entries of a normalized keyword
 enum MDictRecordIndices {
-    Index(MDictRecordIndex),
-    IndexVec(Vec<MDictRecordIndex>),
+    Index(MDictEntry),
+    IndexVec(Vec<MDictEntry>),
 }
 
 struct MDictRecordIndicesIntoIter {
@@ -20,21 +25,21 @@ struct MDictRecordIndicesIter<'a> {
 }
 
 impl Iterator for MDictRecordIndicesIntoIter {
-    type Item = MDictRecordIndex;
+    type Item = MDictEntry;
     fn next(&mut self) -> Option<Self::Item> {
         let cursor = self.cursor;
         self.cursor += 1;
         match &self.inner {
             MDictRecordIndices::IndexVec(v) => {
                 if cursor < v.len() {
-                    Some(v[cursor])
+                    Some(v[cursor].clone())
                 } else {
                     None
                 }
             },
             MDictRecordIndices::Index(v) => {
                 if cursor == 0 {
-                    Some(*v)
+                    Some(v.clone())
                 } else {
                     None
                 }
@@ -44,7 +49,7 @@ impl Iterator for MDictRecordIndicesIntoIter {
 }
 
 impl<'a> Iterator for MDictRecordIndicesIter<'a> {
-    type Item = &'a MDictRecordIndex;
+    type Item = &'a MDictEntry;
     fn next(&mut self) -> Option<Self::Item> {
         let cursor = self.cursor;
         self.cursor += 1;
@@ -68,7 +73,7 @@ impl<'a> Iterator for MDictRecordIndicesIter<'a> {
 }
 
 impl IntoIterator for MDictRecordIndices {
-    type Item = MDictRecordIndex;
+    type Item = MDictEntry;
     type IntoIter = MDictRecordIndicesIntoIter;
     fn into_iter(self) -> Self::IntoIter {
         MDictRecordIndicesIntoIter {
@@ -79,7 +84,7 @@ impl IntoIterator for MDictRecordIndices {
 }
 
 impl<'a> IntoIterator for &'a MDictRecordIndices {
-    type Item = &'a MDictRecordIndex;
+    type Item = &'a MDictEntry;
     type IntoIter = MDictRecordIndicesIter<'a>;
     fn into_iter(self) -> Self::IntoIter {
         MDictRecordIndicesIter {
@@ -89,88 +94,150 @@ impl<'a> IntoIterator for &'a MDictRecordIndices {
     }
 }
 
+mod disk;
+mod redirect;
+
+pub use disk::*;
+pub use redirect::{redirect_target, MDictResolved, DEFAULT_MAX_REDIRECTS, REDIRECT_PREFIX};
+use redirect::Resolver;
+
 #[cfg(feature = "sqlite")]
 mod sqlite;
 
 #[cfg(feature = "sqlite")]
 pub use sqlite::*;
 
-#[cfg(not(feature = "async"))]
+#[cfg(feature = "zip")]
+mod archive;
+
+#[cfg(feature = "zip")]
+pub use archive::*;
+
+/// Blocking lookup of keywords and resources in a MDict Dictionary.
 pub trait MDictLookup {
     fn word_exists(&self, key: &str) -> io::Result<bool>;
+    /// Returns the records of all entries of the keyword, as a keyword may have multiple entries.
     fn lookup_word(&self, key: &str) -> io::Result<Vec<String>>;
     fn lookup_resource(&self, key: &str) -> io::Result<Bytes>;
+
+    /// Lookup the records of the keyword like [`MDictLookup::lookup_word`], and follow the
+    /// redirects `@@@LINK=target` to the records they point to.
+    ///
+    /// Each record is returned with the chain of keywords traversed to reach it. A redirect
+    /// whose target is not found is returned as is, so it may still be shown as a link.
+    ///
+    /// # Error
+    ///
+    /// Besides the errors of `lookup_word`, this fails with [`io::ErrorKind::InvalidData`]
+    /// if a chain has more than `max_redirects` redirects or goes back to a keyword in it.
+    fn resolve_word(&self, key: &str, max_redirects: usize) -> io::Result<Vec<MDictResolved>> {
+        let mut resolver = Resolver::new(key, self.lookup_word(key)?, max_redirects);
+        while let Some(redirect) = resolver.next_redirect()? {
+            let records = self.lookup_word(&redirect.target);
+            resolver.follow(redirect, records)?;
+        }
+        Ok(resolver.finish())
+    }
 }
 
 #[cfg(feature = "async")]
+/// Asynchronous version of [`MDictLookup`], only available with the "async" crate feature.
+///
+/// Both traits are implemented by the indexes and have the same method names,
+/// so import only the one you use.
 #[async_trait]
 pub trait MDictAsyncLookup {
     async fn word_exists(&self, key: &str) -> io::Result<bool>;
     async fn lookup_word(&self, key: &str) -> io::Result<Vec<String>>;
     async fn lookup_resource(&self, key: &str) -> io::Result<Bytes>;
+
+    /// Asynchronous version of [`MDictLookup::resolve_word`].
+    async fn resolve_word(
+        &self,
+        key: &str,
+        max_redirects: usize,
+    ) -> io::Result<Vec<MDictResolved>> {
+        let mut resolver = Resolver::new(key, self.lookup_word(key).await?, max_redirects);
+        while let Some(redirect) = resolver.next_redirect()? {
+            let records = self.lookup_word(&redirect.target).await;
+            resolver.follow(redirect, records)?;
+        }
+        Ok(resolver.finish())
+    }
 }
 
 pub struct MDictMemIndex {
     mdx_index: PatriciaMap<MDictRecordIndices>,
     mdx_block: Vec<MDictRecordBlockIndex>,
-    mdx_file: PathBuf,
+    mdx_file: MDictFile,
     mdd_index: PatriciaMap<(u8, MDictRecordIndex)>,
     mdd_blocks: Vec<Vec<MDictRecordBlockIndex>>,
-    mdd_files: Vec<PathBuf>,
+    mdd_files: Vec<MDictFile>,
     header: MDictHeader,
+    cache: Option<MDictFileCache>,
 }
 
 impl MDictMemIndex {
+    /// Index the mdx file at `path` and the mdd files next to it.
+    ///
+    /// With the "zip" crate feature, `path` may also be a zip archive of the dictionary,
+    /// see [`MDictArchive`].
     pub fn new<P: AsRef<Path>>(path: P) -> io::Result<MDictMemIndex> {
-        let mdx_file = path.as_ref().canonicalize()?;
-        if !mdx_file.is_file()
-            || mdx_file
-                .extension()
-                .map(|s| s.to_str())
-                .flatten()
-                .map(|s| s.to_ascii_lowercase())
-                != Some(String::from("mdx"))
-        {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Expect a mdx file",
-            ));
-        }
-        info!("mdx: {}", mdx_file.to_string_lossy());
-        let mut mdd_files = Vec::new();
-        let mdd0 = mdx_file.with_extension("mdd");
-        if mdd0.is_file() {
-            mdd_files.push(mdd0);
-            for i in 1.. {
-                let ext = format!("{}.mdd", i);
-                let mddi = mdx_file.with_extension(ext);
-                if mddi.is_file() {
-                    info!("mdd: {}", mddi.to_string_lossy());
-                    mdd_files.push(mddi);
-                } else {
-                    break;
-                }
-            }
-        }
-        let mut mdx = MDictIndex::new(
-            OpenOptions::new().read(true).open(&mdx_file)?,
-            MDictMode::Mdx,
-        )?;
+        let (_, mdx_file, mdd_files) = open_files(path)?;
+        MDictMemIndex::from_files(mdx_file, mdd_files, false)
+    }
+
+    /// Index a damaged dictionary like [`MDictMemIndex::new`], but skip its corrupted blocks
+    /// rather than failing, see [`MDictIndex::set_salvage`].
+    ///
+    /// The lost keywords are logged, and looking up a record in a dropped block fails with
+    /// an error whose inner error is [`MDictError::DroppedBlock`].
+    pub fn salvage<P: AsRef<Path>>(path: P) -> io::Result<MDictMemIndex> {
+        let (_, mdx_file, mdd_files) = open_files(path)?;
+        MDictMemIndex::from_files(mdx_file, mdd_files, true)
+    }
+
+    /// Index a dictionary read from sources rather than files found by the path, such as the
+    /// buffers of files embedded in the program, or parts of a larger file.
+    ///
+    /// `mdds` are the resource files in the order of `.mdd`, `.1.mdd`, `.2.mdd` ...
+    /// The sources are kept to lookup the records.
+    pub fn from_sources(
+        mdx: Box<dyn MDictSource>,
+        mdds: Vec<Box<dyn MDictSource>>,
+    ) -> io::Result<MDictMemIndex> {
+        MDictMemIndex::from_files(
+            MDictFile::Source(mdx),
+            mdds.into_iter().map(MDictFile::Source).collect(),
+            false,
+        )
+    }
+
+    fn from_files(
+        mdx_file: MDictFile,
+        mdd_files: Vec<MDictFile>,
+        salvage: bool,
+    ) -> io::Result<MDictMemIndex> {
+        let mut mdx = MDictIndex::new(mdx_file.reader()?, MDictMode::Mdx)?;
+        mdx.set_salvage(salvage);
         let (mdx_block, mdx_keys) = mdx.make_index()?;
+        let header = mdx.into_header();
         let now = std::time::Instant::now();
         let mut mdx_index: PatriciaMap<MDictRecordIndices> = Default::default();
         for (k, v) in mdx_keys.into_iter() {
-            match mdx_index.get_mut(&k) {
+            let key = header.normalize_key(&k);
+            match mdx_index.get_mut(&key) {
                 Some(record_offset) => match record_offset {
                     MDictRecordIndices::Index(v0) => {
-                        *record_offset = MDictRecordIndices::IndexVec(vec![v, *v0]);
+                        let v0 = v0.clone();
+                        *record_offset = MDictRecordIndices::IndexVec(vec![v0, (k, v)]);
                     },
                     MDictRecordIndices::IndexVec(v0) => {
-                        v0.push(v);
+                        v0.push((k, v));
                     }
                 },
                 None => {
-                    mdx_index.insert(k, MDictRecordIndices::Index(v));
+                    mdx_index.insert(key, MDictRecordIndices::Index((k, v)));
                 }
             }
         }
@@ -178,19 +245,21 @@ impl MDictMemIndex {
         let mut mdd_index = PatriciaMap::new();
         let mut mdd_blocks = Vec::new();
         for (i, file) in mdd_files.iter().enumerate() {
-            let mut mdd = MDictIndex::new(
-                OpenOptions::new().read(true).clone().open(file)?,
-                MDictMode::Mdd,
-            )?;
+            let mut mdd = MDictIndex::new(file.reader()?, MDictMode::Mdd)?;
+            mdd.set_salvage(salvage);
             let (mdd_block, mdd_keys) = mdd.make_index()?;
             let now = std::time::Instant::now();
-            mdd_index.extend(mdd_keys.into_iter().map(|(k, idx)| {
+            for (k, idx) in mdd_keys {
                 // process keys when building map rather than lookup
-                let (prefix, key) = k.split_at(1);
-                assert_eq!(prefix, "\\");
-                let key = key.replace('\\', "/");
-                (key, (i as u8, idx))
-            }));
+                let key = k.strip_prefix('\\').ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Resource key doesn't start with \\: {:?}", k),
+                    )
+                })?;
+                let key = normalize_resource(&header, &key.replace('\\', "/"));
+                mdd_index.insert(key, (i as u8, idx));
+            }
             mdd_blocks.push(mdd_block);
             info!("Build Patricia Map for mdd {} in {:?}", i, now.elapsed());
         }
@@ -201,26 +270,231 @@ impl MDictMemIndex {
             mdd_index,
             mdd_blocks,
             mdd_files,
-            header: mdx.into_header(),
+            header,
+            cache: None,
         })
     }
+    #[cfg(feature = "async")]
+    /// Asynchronous version of [`MDictMemIndex::new`], the files are parsed and indexed in the
+    /// blocking thread pool of tokio, so that loading a large dictionary doesn't stall the runtime.
+    pub async fn new_async<P: AsRef<Path>>(path: P) -> io::Result<MDictMemIndex> {
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || MDictMemIndex::new(path))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    #[cfg(feature = "async")]
+    /// Asynchronous version of [`MDictMemIndex::salvage`].
+    pub async fn salvage_async<P: AsRef<Path>>(path: P) -> io::Result<MDictMemIndex> {
+        let path = path.as_ref().to_owned();
+        tokio::task::spawn_blocking(move || MDictMemIndex::salvage(path))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Iterate the original keywords, sorted by their normalized form.
     pub fn keyword_iter(&self) -> impl Iterator<Item = String> + '_ {
-        self.mdx_index.keys().map(|k| String::from_utf8(k).unwrap())
+        self.mdx_index
+            .values()
+            .flat_map(|v| v.into_iter().map(|(k, _)| k.clone()))
+    }
+
+    /// Keep the decompressed record blocks of this dictionary in `cache`, which may be shared
+    /// with other dictionaries.
+    pub fn set_block_cache(&mut self, cache: Arc<MDictBlockCache>) {
+        self.cache = Some(MDictFileCache::new(cache, 1 + self.mdd_files.len()));
+    }
+
+    #[cfg(feature = "mmap")]
+    /// Map the mdx and mdd files into memory, so that the record blocks are sliced from the
+    /// mappings rather than read from the files for every lookup. It is only available with the
+    /// "mmap" crate feature.
+    ///
+    /// # Safety
+    ///
+    /// The files must not be modified or truncated while this index is alive,
+    /// see [`MDictMmap::open`].
+    pub unsafe fn map_files(&mut self) -> io::Result<()> {
+        self.mdx_file.map()?;
+        for file in self.mdd_files.iter_mut() {
+            file.map()?;
+        }
+        Ok(())
+    }
+}
+
+// A block cache and the numbers of files of a dictionary in it,
+// the mdx file is the 0th file and the mdd files follow it.
+struct MDictFileCache {
+    cache: Arc<MDictBlockCache>,
+    files: Vec<u64>,
+}
+
+impl MDictFileCache {
+    fn new(cache: Arc<MDictBlockCache>, files: usize) -> MDictFileCache {
+        let files = (0..files).map(|_| cache.new_file_id()).collect();
+        MDictFileCache { cache, files }
+    }
+
+    fn file(&self, file: usize) -> (&MDictBlockCache, u64) {
+        (&self.cache, self.files[file])
+    }
+}
+
+// A mdx or mdd file of a dictionary
+enum MDictFile {
+    // a file which is opened for every lookup
+    Path(PathBuf),
+    // a source given by the user, or a file mapped into memory by `map`
+    Source(Box<dyn MDictSource>),
+}
+
+impl MDictFile {
+    // A reader at the start of file to parse it
+    fn reader(&self) -> io::Result<Either<File, MDictSourceReader<'_>>> {
+        match self {
+            MDictFile::Path(path) => Ok(Either::Left(OpenOptions::new().read(true).open(path)?)),
+            MDictFile::Source(source) => Ok(Either::Right(MDictSourceReader::new(&**source))),
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    unsafe fn map(&mut self) -> io::Result<()> {
+        if let MDictFile::Path(path) = self {
+            *self = MDictFile::Source(Box::new(MDictMmap::open(path)?));
+        }
+        Ok(())
+    }
+
+    // Lookup a record in this file, through the cache if there is one
+    fn lookup(
+        &self,
+        key: &MDictRecordIndex,
+        blocks: &[MDictRecordBlockIndex],
+        cache: Option<(&MDictBlockCache, u64)>,
+    ) -> io::Result<Bytes> {
+        let data = match (self, cache) {
+            (MDictFile::Path(_), Some((cache, id))) => {
+                lookup_cached(self.reader()?, key, blocks, cache, id)?
+            }
+            (MDictFile::Path(_), None) => lookup(self.reader()?, key, blocks)?,
+            (MDictFile::Source(source), Some((cache, id))) => {
+                lookup_source_cached(&**source, key, blocks, cache, id)?
+            }
+            (MDictFile::Source(source), None) => lookup_source(&**source, key, blocks)?,
+        };
+        Ok(data)
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn header_async(&self, mode: MDictMode) -> io::Result<MDictHeader> {
+        let header = match self {
+            MDictFile::Path(path) => {
+                MDictHeader::new_async(tokio::fs::File::open(path).await?, mode).await?
+            }
+            MDictFile::Source(source) => MDictHeader::new(MDictSourceReader::new(&**source), mode)?,
+        };
+        Ok(header)
+    }
+
+    #[cfg(feature = "async")]
+    async fn lookup_async(
+        &self,
+        key: &MDictRecordIndex,
+        blocks: &[MDictRecordBlockIndex],
+        cache: Option<(&MDictBlockCache, u64)>,
+    ) -> io::Result<Bytes> {
+        // Sources are usually in memory, or read by a single positioned read of a block,
+        // so they are read in place like decompressing the block
+        let path = match self {
+            MDictFile::Path(path) => path,
+            MDictFile::Source(_) => return self.lookup(key, blocks, cache),
+        };
+        let file = tokio::fs::OpenOptions::new().read(true).open(path).await?;
+        let data = match cache {
+            Some((cache, id)) => lookup_cached_async(file, key, blocks, cache, id).await?,
+            None => lookup_async(file, key, blocks).await?,
+        };
+        Ok(data)
+    }
+}
+
+// Check the mdx file and find its mdd files: `a.mdd`, `a.1.mdd`, `a.2.mdd` ...
+fn find_files<P: AsRef<Path>>(path: P) -> io::Result<(PathBuf, Vec<PathBuf>)> {
+    let mdx_file = path.as_ref().canonicalize()?;
+    if !mdx_file.is_file() || !has_extension(&mdx_file, "mdx") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Expect a mdx file",
+        ));
+    }
+    info!("mdx: {}", mdx_file.to_string_lossy());
+    let mut mdd_files = Vec::new();
+    for ext in mdd_extensions() {
+        let mdd = mdx_file.with_extension(ext);
+        if !mdd.is_file() {
+            break;
+        }
+        info!("mdd: {}", mdd.to_string_lossy());
+        mdd_files.push(mdd);
+    }
+    Ok((mdx_file, mdd_files))
+}
+
+// Find the mdx file and its mdd files at `path`, which is a mdx file, or a zip archive with
+// the "zip" crate feature. Returns the canonical path too.
+fn open_files<P: AsRef<Path>>(path: P) -> io::Result<(PathBuf, MDictFile, Vec<MDictFile>)> {
+    #[cfg(feature = "zip")]
+    {
+        if has_extension(path.as_ref(), "zip") {
+            let archive = MDictArchive::open(path)?;
+            let (mdx, mdds) = archive.sources()?;
+            let mdd_files = mdds.into_iter().map(MDictFile::Source).collect();
+            return Ok((archive.path().to_owned(), MDictFile::Source(mdx), mdd_files));
+        }
+    }
+    let (mdx_file, mdd_files) = find_files(path)?;
+    let mdd_files = mdd_files.into_iter().map(MDictFile::Path).collect();
+    Ok((mdx_file.clone(), MDictFile::Path(mdx_file), mdd_files))
+}
+
+// Extensions of the mdd files of `a.mdx`: `a.mdd`, `a.1.mdd`, `a.2.mdd` ...
+fn mdd_extensions() -> impl Iterator<Item = String> {
+    std::iter::once(String::from("mdd")).chain((1..).map(|i| format!("{}.mdd", i)))
+}
+
+// Whether the extension of `path` is `ext`, ignoring case
+fn has_extension(path: &Path, ext: &str) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|s| s.eq_ignore_ascii_case(ext))
+}
+
+// Paths of resources are matched case-insensitively like keywords, but never stripped
+fn normalize_resource(header: &MDictHeader, path: &str) -> String {
+    if header.metadata().key_case_sensitive {
+        path.to_owned()
+    } else {
+        path.to_lowercase()
     }
 }
 
-#[cfg(not(feature = "async"))]
 impl MDictLookup for MDictMemIndex {
     fn word_exists(&self, key: &str) -> io::Result<bool> {
-        Ok(self.mdx_index.get(&key).is_some())
+        Ok(self.mdx_index.get(self.header.normalize_key(key)).is_some())
     }
     fn lookup_word(&self, key: &str) -> io::Result<Vec<String>> {
-        match self.mdx_index.get(&key) {
+        match self.mdx_index.get(self.header.normalize_key(key)) {
             Some(idx) => {
-                let file = OpenOptions::new().read(true).open(&self.mdx_file)?;
-                let bytes = lookup(file, idx, &self.mdx_block[idx.block as usize])?;
-                let decoded = self.header.decode_string(bytes)?;
-                Ok(decoded)
+                let mut result = vec![];
+                for (_, i) in idx {
+                    let cache = self.cache.as_ref().map(|c| c.file(0));
+                    let bytes = self.mdx_file.lookup(i, &self.mdx_block, cache)?;
+                    let decoded = self.header.decode_record(bytes)?;
+                    result.push(decoded);
+                }
+                Ok(result)
             }
             None => Err(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -230,17 +504,11 @@ impl MDictLookup for MDictMemIndex {
     }
 
     fn lookup_resource(&self, key: &str) -> io::Result<Bytes> {
-        match self.mdd_index.get(key) {
+        match self.mdd_index.get(normalize_resource(&self.header, key)) {
             Some((num, idx)) => {
-                let file = OpenOptions::new()
-                    .read(true)
-                    .open(&self.mdd_files[*num as usize])?;
-                let data = lookup(
-                    file,
-                    idx,
-                    &self.mdd_blocks[*num as usize][idx.block as usize],
-                )?;
-                Ok(data)
+                let num = *num as usize;
+                let cache = self.cache.as_ref().map(|c| c.file(1 + num));
+                self.mdd_files[num].lookup(idx, &self.mdd_blocks[num], cache)
             }
             None => Err(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -257,19 +525,17 @@ use async_trait::async_trait;
 #[async_trait]
 impl MDictAsyncLookup for MDictMemIndex {
     async fn word_exists(&self, key: &str) -> io::Result<bool> {
-        Ok(self.mdx_index.get(&key).is_some())
+        Ok(self.mdx_index.get(self.header.normalize_key(key)).is_some())
     }
     async fn lookup_word(&self, key: &str) -> io::Result<Vec<String>> {
-        match self.mdx_index.get(&key) {
+        match self.mdx_index.get(self.header.normalize_key(key)) {
             Some(idx) => {
                 let mut result = vec![];
-                for i in idx {
-                    let file: tokio::fs::File = tokio::fs::OpenOptions::new()
-                        .read(true)
-                        .open(&self.mdx_file)
-                        .await?;
-                    let bytes = lookup(file, i, &self.mdx_block[i.block as usize]).await?;
-                    let decoded = self.header.decode_string(bytes)?;
+                for (_, i) in idx {
+                    let cache = self.cache.as_ref().map(|c| c.file(0));
+                    let bytes =
+                        self.mdx_file.lookup_async(i, &self.mdx_block, cache).await?;
+                    let decoded = self.header.decode_record(bytes)?;
                     result.push(decoded);
                 }
                 Ok(result)
@@ -282,19 +548,11 @@ impl MDictAsyncLookup for MDictMemIndex {
     }
 
     async fn lookup_resource(&self, key: &str) -> io::Result<Bytes> {
-        match self.mdd_index.get(key) {
+        match self.mdd_index.get(normalize_resource(&self.header, key)) {
             Some((num, idx)) => {
-                let file = tokio::fs::OpenOptions::new()
-                    .read(true)
-                    .open(&self.mdd_files[*num as usize])
-                    .await?;
-                let data = lookup(
-                    file,
-                    idx,
-                    &self.mdd_blocks[*num as usize][idx.block as usize],
-                )
-                .await?;
-                Ok(data)
+                let num = *num as usize;
+                let cache = self.cache.as_ref().map(|c| c.file(1 + num));
+                self.mdd_files[num].lookup_async(idx, &self.mdd_blocks[num], cache).await
             }
             None => Err(io::Error::new(
                 io::ErrorKind::NotFound,
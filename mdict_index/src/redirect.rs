@@ -0,0 +1,135 @@
+use log::warn;
+use std::io;
+
+/// The prefix of a record which redirects to the record of another keyword,
+/// such as `@@@LINK=lemma` in the record of an inflected form.
+pub const REDIRECT_PREFIX: &str = "@@@LINK=";
+
+/// A `max_redirects` for [`MDictLookup::resolve_word`], an inflected form rarely takes more
+/// than a redirect or two to reach its lemma.
+///
+/// [`MDictLookup::resolve_word`]: crate::MDictLookup::resolve_word
+pub const DEFAULT_MAX_REDIRECTS: usize = 8;
+
+/// A record found by following the redirects from a keyword, see [`MDictLookup::resolve_word`].
+///
+/// [`MDictLookup::resolve_word`]: crate::MDictLookup::resolve_word
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MDictResolved {
+    /// The keywords traversed, from the keyword looked up to the keyword of `record`
+    pub chain: Vec<String>,
+    /// The record at the end of the chain
+    pub record: String,
+}
+
+/// The target of a redirect record, or `None` if the record is not a bare `@@@LINK=target`.
+///
+/// Records of mdx files usually end with a line break and `\0`, which are ignored.
+pub fn redirect_target(record: &str) -> Option<&str> {
+    let record = record.trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+    let target = record.trim_start().strip_prefix(REDIRECT_PREFIX)?.trim();
+    if target.is_empty() || target.contains(['\r', '\n']) {
+        None
+    } else {
+        Some(target)
+    }
+}
+
+// Follow the redirects of the records of a keyword in depth-first order, the records are
+// looked up by the caller so the same steps serve both blocking and asynchronous lookups
+pub(crate) struct Resolver {
+    // records waiting to be resolved with the keywords traversed to reach them, the last one first
+    stack: Vec<(Vec<String>, String)>,
+    result: Vec<MDictResolved>,
+    max_redirects: usize,
+}
+
+// A redirect to follow, the record is kept if its target is not found
+pub(crate) struct Redirect {
+    chain: Vec<String>,
+    record: String,
+    pub(crate) target: String,
+}
+
+impl Resolver {
+    pub(crate) fn new(key: &str, records: Vec<String>, max_redirects: usize) -> Resolver {
+        let mut resolver = Resolver {
+            stack: Vec::new(),
+            result: Vec::new(),
+            max_redirects,
+        };
+        resolver.push(vec![key.to_owned()], records);
+        resolver
+    }
+
+    // The next redirect to follow, or `None` if all records are resolved
+    pub(crate) fn next_redirect(&mut self) -> io::Result<Option<Redirect>> {
+        while let Some((chain, record)) = self.stack.pop() {
+            let target = match redirect_target(&record) {
+                Some(target) => target.to_owned(),
+                None => {
+                    self.result.push(MDictResolved { chain, record });
+                    continue;
+                }
+            };
+            let path = || format!("{} -> {}", chain.join(" -> "), target);
+            if chain.contains(&target) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Redirect cycle: {}", path()),
+                ));
+            }
+            if chain.len() > self.max_redirects {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Too many redirects: {}", path()),
+                ));
+            }
+            return Ok(Some(Redirect {
+                chain,
+                record,
+                target,
+            }));
+        }
+        Ok(None)
+    }
+
+    // Continue with the records of the target of `redirect`
+    pub(crate) fn follow(
+        &mut self,
+        redirect: Redirect,
+        records: io::Result<Vec<String>>,
+    ) -> io::Result<()> {
+        let Redirect {
+            mut chain,
+            record,
+            target,
+        } = redirect;
+        match records {
+            Ok(records) => {
+                chain.push(target);
+                self.push(chain, records);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!(
+                    "Redirect target of {} is not found: {}",
+                    chain.join(" -> "),
+                    target
+                );
+                self.result.push(MDictResolved { chain, record });
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Vec<MDictResolved> {
+        self.result
+    }
+
+    fn push(&mut self, chain: Vec<String>, records: Vec<String>) {
+        for record in records.into_iter().rev() {
+            self.stack.push((chain.clone(), record));
+        }
+    }
+}
@@ -5,77 +5,85 @@ use sqlx::sqlite::{
     SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePool, SqlitePoolOptions,
 };
 use sqlx::{ConnectOptions, Connection, Executor};
+use std::convert::TryFrom;
+use std::future::Future;
 use std::iter;
-use std::path::{Path, PathBuf};
-use std::{
-    fs::{self, OpenOptions},
-    io,
-};
+use std::path::Path;
+use std::{fs, io};
+use tokio::runtime::{Handle, RuntimeFlavor};
 use tokio_stream::StreamExt;
 
 const DB_INIT: &str = include_str!("../migration/init.sql");
+// Bump this when the schema in `init.sql` or the way to build index is changed,
+// so that old index will be rebuilt.
+const DB_SCHEMA_VERSION: &str = "5";
 
-pub struct MDictSqliteBuilder {
+pub struct MDictSqliteBuilder<'a> {
     conn: SqliteConnection,
-    index: MDictMemIndex,
+    index: &'a MDictMemIndex,
 }
 
+#[derive(sqlx::FromRow, Debug)]
 struct MdxBlock {
-    block_index: i32,
+    block_index: i64,
     block_offset: i64,
     block_size: i64,
+    block_uncomp_size: i64,
+    block_dropped: bool,
 }
 
 struct MdxIndex {
     keyword: String,
-    block_index: i32,
-    record_offset: i32,
-    record_size: i32,
+    headword: String,
+    block_index: i64,
+    record_offset: i64,
+    record_size: i64,
 }
 
 #[derive(sqlx::FromRow, Debug)]
 struct MdxQuery {
     #[allow(unused)]
-    id: i32,
+    id: i64,
     #[allow(unused)]
     keyword: String,
-    block_index: i32,
-    record_offset: i32,
-    record_size: i32,
-    block_offset: i64,
-    block_size: i64,
+    #[allow(unused)]
+    headword: String,
+    block_index: i64,
+    record_offset: i64,
+    record_size: i64,
 }
 
+#[derive(sqlx::FromRow, Debug)]
 struct MddBlock {
     file_index: i32,
-    block_index: i32,
+    block_index: i64,
     block_offset: i64,
     block_size: i64,
+    block_uncomp_size: i64,
+    block_dropped: bool,
 }
 
 struct MddIndex {
     keyword: String,
     file_index: i32,
-    block_index: i32,
-    record_offset: i32,
-    record_size: i32,
+    block_index: i64,
+    record_offset: i64,
+    record_size: i64,
 }
 
 #[derive(sqlx::FromRow, Debug)]
 struct MddQuery {
     #[allow(unused)]
-    id: i32,
+    id: i64,
     #[allow(unused)]
     keyword: String,
     file_index: i32,
-    block_index: i32,
-    record_offset: i32,
-    record_size: i32,
-    block_offset: i64,
-    block_size: i64,
+    block_index: i64,
+    record_offset: i64,
+    record_size: i64,
 }
 
-impl MDictSqliteBuilder {
+impl MDictSqliteBuilder<'_> {
     async fn build(mut self) -> sqlx::Result<()> {
         self.conn.execute(DB_INIT).await?;
         self.write_mdx().await?;
@@ -95,27 +103,40 @@ impl MDictSqliteBuilder {
                     .bind(env!("CARGO_PKG_VERSION")),
             )
             .await?;
+        transaction
+            .execute(
+                sqlx::query("insert into meta (key, value) values ( ?1, ?2)")
+                    .bind("schema")
+                    .bind(DB_SCHEMA_VERSION),
+            )
+            .await?;
         transaction.commit().await?;
         info!("Build index cache in {:?}", now.elapsed());
-        Ok(())
+        // a dropped connection is closed in the background, the database may be still locked
+        // when it is opened again to change the journal mode
+        self.conn.close().await
     }
     async fn write_mdx(&mut self) -> sqlx::Result<()> {
         let now = std::time::Instant::now();
         let mut transaction = self.conn.begin().await?;
         for (i, v) in self.index.mdx_block.iter().enumerate() {
             let mdx_block = MdxBlock {
-                block_index: i as i32,
-                block_offset: v.offset as i64,
-                block_size: v.comp_size as i64,
+                block_index: i as i64,
+                block_offset: to_i64(v.offset, "Record block offset")?,
+                block_size: to_i64(v.comp_size, "Record block size")?,
+                block_uncomp_size: to_i64(v.uncomp_size, "Record block size")?,
+                block_dropped: v.dropped,
             };
             sqlx::query!(
                 r"
-                    insert into mdx_block (block_index, block_offset, block_size)
-                    values ( ?1, ?2, ?3 )
+                    insert into mdx_block (block_index, block_offset, block_size, block_uncomp_size, block_dropped)
+                    values ( ?1, ?2, ?3, ?4, ?5 )
                 ",
                 mdx_block.block_index,
                 mdx_block.block_offset,
-                mdx_block.block_size
+                mdx_block.block_size,
+                mdx_block.block_uncomp_size,
+                mdx_block.block_dropped
             )
             .execute(&mut *transaction)
             .await?;
@@ -124,21 +145,23 @@ impl MDictSqliteBuilder {
         info!("Build mdx block index in {:?}", now.elapsed());
         let mut transaction = self.conn.begin().await?;
         let now = std::time::Instant::now();
-        for (k, v) in self.index.mdx_index.iter().flat_map(|(k, v)| {
+        for (k, (h, v)) in self.index.mdx_index.iter().flat_map(|(k, v)| {
             iter::repeat(k).zip(v.into_iter())
         }) {
             let mdx_index = MdxIndex {
                 keyword: String::from_utf8(k).unwrap(),
-                block_index: v.block as i32,
-                record_offset: v.offset as i32,
-                record_size: v.len as i32,
+                headword: h.clone(),
+                block_index: to_i64(v.block, "Record block")?,
+                record_offset: to_i64(v.offset, "Record offset")?,
+                record_size: to_i64(v.len, "Record size")?,
             };
             sqlx::query!(
                 r"
-                    insert into mdx_index (keyword, block_index, record_offset, record_size)
-                    values ( ?1, ?2, ?3 , ?4)
+                    insert into mdx_index (keyword, headword, block_index, record_offset, record_size)
+                    values ( ?1, ?2, ?3, ?4, ?5 )
                 ",
                 mdx_index.keyword,
+                mdx_index.headword,
                 mdx_index.block_index,
                 mdx_index.record_offset,
                 mdx_index.record_size
@@ -157,19 +180,23 @@ impl MDictSqliteBuilder {
             for (j, block) in file.iter().enumerate() {
                 let mdd_block = MddBlock {
                     file_index: i as i32,
-                    block_index: j as i32,
-                    block_offset: block.offset as i64,
-                    block_size: block.comp_size as i64,
+                    block_index: j as i64,
+                    block_offset: to_i64(block.offset, "Record block offset")?,
+                    block_size: to_i64(block.comp_size, "Record block size")?,
+                    block_uncomp_size: to_i64(block.uncomp_size, "Record block size")?,
+                    block_dropped: block.dropped,
                 };
                 sqlx::query!(
                     r"
-                        insert into mdd_block (file_index, block_index, block_offset, block_size)
-                        values ( ?1, ?2, ?3, ?4 )
+                        insert into mdd_block (file_index, block_index, block_offset, block_size, block_uncomp_size, block_dropped)
+                        values ( ?1, ?2, ?3, ?4, ?5, ?6 )
                     ",
                     mdd_block.file_index,
                     mdd_block.block_index,
                     mdd_block.block_offset,
-                    mdd_block.block_size
+                    mdd_block.block_size,
+                    mdd_block.block_uncomp_size,
+                    mdd_block.block_dropped
                 )
                 .execute(&mut *transaction)
                 .await?;
@@ -183,9 +210,9 @@ impl MDictSqliteBuilder {
             let mdd_index = MddIndex {
                 keyword: String::from_utf8(key.to_vec()).unwrap(),
                 file_index: *file as i32,
-                block_index: index.block as i32,
-                record_offset: index.offset as i32,
-                record_size: index.len as i32,
+                block_index: to_i64(index.block, "Record block")?,
+                record_offset: to_i64(index.offset, "Record offset")?,
+                record_size: to_i64(index.len, "Record size")?,
             };
             sqlx::query!(
                 r"
@@ -207,6 +234,52 @@ impl MDictSqliteBuilder {
     }
 }
 
+// Integers of SQLite are signed 64-bit
+fn to_i64(value: u64, what: &'static str) -> sqlx::Result<i64> {
+    i64::try_from(value).map_err(|_| sqlx::Error::Io(MDictError::Overflow { what, value }.into()))
+}
+
+fn to_u64(value: i64, what: &'static str) -> io::Result<u64> {
+    u64::try_from(value).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} {} in index is negative", what, value),
+        )
+    })
+}
+
+impl MdxBlock {
+    fn block_index(&self, version: MDictFormatVersion) -> io::Result<MDictRecordBlockIndex> {
+        Ok(MDictRecordBlockIndex {
+            offset: to_u64(self.block_offset, "Record block offset")?,
+            comp_size: to_u64(self.block_size, "Record block size")?,
+            uncomp_size: to_u64(self.block_uncomp_size, "Record block size")?,
+            version,
+            dropped: self.block_dropped,
+        })
+    }
+}
+
+impl MddBlock {
+    fn block_index(&self, version: MDictFormatVersion) -> io::Result<MDictRecordBlockIndex> {
+        Ok(MDictRecordBlockIndex {
+            offset: to_u64(self.block_offset, "Record block offset")?,
+            comp_size: to_u64(self.block_size, "Record block size")?,
+            uncomp_size: to_u64(self.block_uncomp_size, "Record block size")?,
+            version,
+            dropped: self.block_dropped,
+        })
+    }
+}
+
+fn record_index(block: i64, offset: i64, len: i64) -> io::Result<MDictRecordIndex> {
+    Ok(MDictRecordIndex {
+        block: to_u64(block, "Record block")?,
+        offset: to_u64(offset, "Record offset")?,
+        len: to_u64(len, "Record size")?,
+    })
+}
+
 async fn open_db(file: impl AsRef<Path>) -> Option<SqlitePool> {
     let db_file = file.as_ref().with_extension("db");
     if !db_file.exists() {
@@ -233,6 +306,14 @@ async fn open_db(file: impl AsRef<Path>) -> Option<SqlitePool> {
             if v.as_str() != env!("CARGO_PKG_VERSION") {
                 return None;
             }
+            let schema: sqlx::Result<Option<String>> =
+                sqlx::query_scalar("select value from meta where key = \"schema\"")
+                    .fetch_optional(&db)
+                    .await;
+            if !matches!(schema, Ok(Some(ref s)) if s == DB_SCHEMA_VERSION) {
+                info!("Index schema is outdated, need rebuilt");
+                return None;
+            }
             info!("Find index DB for mdict_index {}", v.as_str());
             Some(db)
         }
@@ -243,7 +324,7 @@ async fn open_db(file: impl AsRef<Path>) -> Option<SqlitePool> {
     }
 }
 
-async fn build_db(mdx_file: impl AsRef<Path>) -> sqlx::Result<SqlitePool> {
+async fn build_db(mdx_file: impl AsRef<Path>, index: &MDictMemIndex) -> sqlx::Result<SqlitePool> {
     let db_file = mdx_file.as_ref().with_extension("db");
     info!("Build index to {}", &db_file.to_string_lossy());
     if db_file.exists() {
@@ -256,7 +337,6 @@ async fn build_db(mdx_file: impl AsRef<Path>) -> sqlx::Result<SqlitePool> {
         .journal_mode(SqliteJournalMode::Wal)
         .create_if_missing(true);
     let conn = options.connect().await?;
-    let index = MDictMemIndex::new(&mdx_file).map_err(sqlx::Error::Io)?;
     let builder = MDictSqliteBuilder { conn, index };
     builder.build().await?;
     // open in writeable + journal mode = delete to remove db-wal file
@@ -274,138 +354,180 @@ async fn build_db(mdx_file: impl AsRef<Path>) -> sqlx::Result<SqlitePool> {
     Ok(pool)
 }
 
+/// Index of a MDict Dictionary stored in a sqlite database next to the mdx file.
+///
+/// The blocking [`MDictLookup`] runs the queries on the tokio runtime where the index was created.
+/// In an asynchronous task of a multi-threaded runtime, the worker thread is handed over by
+/// `tokio::task::block_in_place` while waiting. A current-thread runtime can't run the queries
+/// while its only thread is blocked, so the blocking lookup fails with [`io::ErrorKind::Unsupported`]
+/// there, use [`MDictAsyncLookup`] instead.
 pub struct MDictSqliteIndex {
     pool: SqlitePool,
-    mdx_file: PathBuf,
-    mdd_files: Vec<PathBuf>,
+    runtime: Handle,
+    mdx_file: MDictFile,
+    mdx_blocks: Vec<MDictRecordBlockIndex>,
+    mdd_files: Vec<MDictFile>,
+    mdd_blocks: Vec<Vec<MDictRecordBlockIndex>>,
     pub header: MDictHeader,
+    cache: Option<MDictFileCache>,
 }
 
 impl MDictSqliteIndex {
     pub async fn new<P: AsRef<Path>>(path: P) -> sqlx::Result<MDictSqliteIndex> {
-        let mdx_file = path.as_ref().canonicalize()?;
-        if !mdx_file.is_file()
-            || mdx_file
-                .extension()
-                .map(|s| s.to_str())
-                .flatten()
-                .map(|s| s.to_ascii_lowercase())
-                != Some(String::from("mdx"))
-        {
-            return Err(sqlx::Error::Io(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Expect a mdx file",
-            )));
-        }
-        info!("mdx: {}", mdx_file.to_string_lossy());
-        let pool = match open_db(&mdx_file).await {
-            Some(db) => db,
-            None => build_db(&mdx_file).await?,
-        };
-        let header = MDictHeader::new(
-            OpenOptions::new().read(true).open(&mdx_file)?,
-            MDictMode::Mdx,
-        )?;
-        let mut mdd_files = Vec::new();
-        let mdd0 = mdx_file.with_extension("mdd");
-        if mdd0.is_file() {
-            mdd_files.push(mdd0);
-            for i in 1.. {
-                let ext = format!("{}.mdd", i);
-                let mddi = mdx_file.with_extension(ext);
-                if mddi.is_file() {
-                    mdd_files.push(mddi);
-                } else {
-                    break;
-                }
+        MDictSqliteIndex::open(path, false).await
+    }
+
+    /// Index a damaged dictionary like [`MDictSqliteIndex::new`], but skip its corrupted blocks
+    /// rather than failing, see [`MDictMemIndex::salvage`].
+    ///
+    /// Only the building of the database is affected. An existing database is used as is,
+    /// and a database built by this is also used by [`MDictSqliteIndex::new`].
+    pub async fn salvage<P: AsRef<Path>>(path: P) -> sqlx::Result<MDictSqliteIndex> {
+        MDictSqliteIndex::open(path, true).await
+    }
+
+    #[cfg(feature = "zip")]
+    /// Index a dictionary in an opened zip archive like [`MDictSqliteIndex::new`], the database
+    /// is next to the archive. The archive can be kept to read the other files in it.
+    pub async fn from_archive(archive: Arc<MDictArchive>) -> sqlx::Result<MDictSqliteIndex> {
+        let path = archive.path().to_owned();
+        // deflated files are decompressed when they are opened
+        let (mdx, mdds) = tokio::task::spawn_blocking(move || archive.sources())
+            .await
+            .map_err(io::Error::other)??;
+        let mdd_files = mdds.into_iter().map(MDictFile::Source).collect();
+        MDictSqliteIndex::from_files(&path, MDictFile::Source(mdx), mdd_files, false).await
+    }
+
+    async fn open<P: AsRef<Path>>(path: P, salvage: bool) -> sqlx::Result<MDictSqliteIndex> {
+        // deflated files in a zip archive are decompressed when they are opened
+        let path = path.as_ref().to_owned();
+        let (path, mdx_file, mdd_files) = tokio::task::spawn_blocking(move || open_files(path))
+            .await
+            .map_err(io::Error::other)??;
+        MDictSqliteIndex::from_files(&path, mdx_file, mdd_files, salvage).await
+    }
+
+    // Open the database at `path` with the extension `.db`, or build it from the files
+    async fn from_files(
+        path: &Path,
+        mdx_file: MDictFile,
+        mdd_files: Vec<MDictFile>,
+        salvage: bool,
+    ) -> sqlx::Result<MDictSqliteIndex> {
+        let (pool, mdx_file, mdd_files) = match open_db(path).await {
+            Some(db) => (db, mdx_file, mdd_files),
+            None => {
+                // index the opened files, then keep them for lookups
+                let index = tokio::task::spawn_blocking(move || {
+                    MDictMemIndex::from_files(mdx_file, mdd_files, salvage)
+                })
+                .await
+                .map_err(io::Error::other)??;
+                let db = build_db(path, &index).await?;
+                (db, index.mdx_file, index.mdd_files)
             }
-        }
-        for i in mdd_files.iter() {
-            info!("mdd: {}", i.to_string_lossy());
+        };
+        let header = mdx_file.header_async(MDictMode::Mdx).await?;
+        // A record may span several blocks, so keep all blocks in memory for lookup
+        let mdx_blocks: Vec<MdxBlock> =
+            sqlx::query_as("select * from mdx_block order by block_index")
+                .fetch_all(&pool)
+                .await?;
+        let mdx_blocks = mdx_blocks
+            .iter()
+            .map(|b| b.block_index(header.version()))
+            .collect::<io::Result<_>>()?;
+        let mut mdd_blocks = vec![Vec::new(); mdd_files.len()];
+        let blocks: Vec<MddBlock> =
+            sqlx::query_as("select * from mdd_block order by file_index, block_index")
+                .fetch_all(&pool)
+                .await?;
+        for (i, file) in mdd_files.iter().enumerate() {
+            let mdd_header = file.header_async(MDictMode::Mdd).await?;
+            mdd_blocks[i] = blocks
+                .iter()
+                .filter(|b| b.file_index as usize == i)
+                .map(|b| b.block_index(mdd_header.version()))
+                .collect::<io::Result<_>>()?;
         }
         Ok(MDictSqliteIndex {
             pool,
+            runtime: Handle::current(),
             mdx_file,
+            mdx_blocks,
             mdd_files,
+            mdd_blocks,
             header,
+            cache: None,
         })
     }
+
+    /// Keep the decompressed record blocks of this dictionary in `cache`, which may be shared
+    /// with other dictionaries.
+    pub fn set_block_cache(&mut self, cache: Arc<MDictBlockCache>) {
+        self.cache = Some(MDictFileCache::new(cache, 1 + self.mdd_files.len()));
+    }
+
+    #[cfg(feature = "mmap")]
+    /// Map the mdx and mdd files into memory, like [`MDictMemIndex::map_files`].
+    ///
+    /// # Safety
+    ///
+    /// The files must not be modified or truncated while this index is alive,
+    /// see [`MDictMmap::open`].
+    pub unsafe fn map_files(&mut self) -> io::Result<()> {
+        self.mdx_file.map()?;
+        for file in self.mdd_files.iter_mut() {
+            file.map()?;
+        }
+        Ok(())
+    }
     pub async fn close(&self) {
         info!("Shutdown ...");
         self.pool.close().await;
     }
-}
 
-#[async_trait]
-impl MDictAsyncLookup for MDictSqliteIndex {
-    async fn word_exists(&self, key: &str) -> io::Result<bool> {
+    async fn query_word_exists(&self, key: &str) -> io::Result<bool> {
+        let key = self.header.normalize_key(key);
         let query = sqlx::query!("select keyword from mdx_index where keyword = ?1", key)
             .fetch_optional(&self.pool)
             .await
             .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
         Ok(query.is_some())
     }
-    async fn lookup_word(&self, key: &str) -> io::Result<Vec<String>> {
-        let query: Vec<MdxQuery> =
-            sqlx::query_as("select * from mdx_index natural join mdx_block where keyword = ?1")
-                .bind(key)
-                .fetch_all(&self.pool)
-                .await
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        match query.len() {
-            0 => Err(io::Error::new(
+
+    // indexes of all entries of the keyword
+    async fn query_word(&self, key: &str) -> io::Result<Vec<MDictRecordIndex>> {
+        let query: Vec<MdxQuery> = sqlx::query_as("select * from mdx_index where keyword = ?1 order by id")
+            .bind(self.header.normalize_key(key))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        if query.is_empty() {
+            return Err(io::Error::new(
                 io::ErrorKind::NotFound,
                 "Not found in index",
-            )),
-            _ => {
-                let mut result = vec![];
-                for idx in query {
-                    let file: tokio::fs::File = tokio::fs::OpenOptions::new()
-                        .read(true)
-                        .open(&self.mdx_file)
-                        .await?;
-                    let key = MDictRecordIndex {
-                        block: idx.block_index as u32,
-                        offset: idx.record_offset as u32,
-                        len: idx.record_size as u32,
-                    };
-                    let block = MDictRecordBlockIndex {
-                        offset: idx.block_offset as u64,
-                        comp_size: idx.block_size as u64,
-                    };
-                    let bytes = lookup(file, &key, &block).await?;
-                    let decoded = self.header.decode_string(bytes)?;
-                    result.push(decoded);
-                }
-                Ok(result)
-            }
+            ));
         }
+        query
+            .into_iter()
+            .map(|idx| record_index(idx.block_index, idx.record_offset, idx.record_size))
+            .collect()
     }
 
-    async fn lookup_resource(&self, key: &str) -> io::Result<Bytes> {
-        let query: Option<MddQuery> =
-            sqlx::query_as("select * from mdd_index natural join mdd_block where keyword = ?1")
-                .bind(key)
-                .fetch_optional(&self.pool)
-                .await
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    // the number of mdd file of the resource and its index
+    async fn query_resource(&self, key: &str) -> io::Result<(usize, MDictRecordIndex)> {
+        let query: Option<MddQuery> = sqlx::query_as("select * from mdd_index where keyword = ?1")
+            .bind(normalize_resource(&self.header, key))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
         match query {
             Some(result) => {
-                let file: tokio::fs::File = tokio::fs::OpenOptions::new()
-                    .read(true)
-                    .open(&self.mdd_files[result.file_index as usize])
-                    .await?;
-                let key = MDictRecordIndex {
-                    block: result.block_index as u32,
-                    offset: result.record_offset as u32,
-                    len: result.record_size as u32,
-                };
-                let block = MDictRecordBlockIndex {
-                    offset: result.block_offset as u64,
-                    comp_size: result.block_size as u64,
-                };
-                let data = lookup(file, &key, &block).await?;
-                Ok(data)
+                let key =
+                    record_index(result.block_index, result.record_offset, result.record_size)?;
+                Ok((result.file_index as usize, key))
             }
             None => Err(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -413,4 +535,63 @@ impl MDictAsyncLookup for MDictSqliteIndex {
             )),
         }
     }
+
+    // Wait for a query of the blocking lookup
+    fn block_on<T>(&self, query: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+        match Handle::try_current().map(|h| h.runtime_flavor()) {
+            Err(_) => self.runtime.block_on(query),
+            Ok(RuntimeFlavor::CurrentThread) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Blocking lookup of sqlite index in a current-thread runtime, use MDictAsyncLookup",
+            )),
+            Ok(_) => tokio::task::block_in_place(|| self.runtime.block_on(query)),
+        }
+    }
+}
+
+impl MDictLookup for MDictSqliteIndex {
+    fn word_exists(&self, key: &str) -> io::Result<bool> {
+        self.block_on(self.query_word_exists(key))
+    }
+    fn lookup_word(&self, key: &str) -> io::Result<Vec<String>> {
+        let query = self.block_on(self.query_word(key))?;
+        let mut result = vec![];
+        for key in query {
+            let cache = self.cache.as_ref().map(|c| c.file(0));
+            let bytes = self.mdx_file.lookup(&key, &self.mdx_blocks, cache)?;
+            let decoded = self.header.decode_record(bytes)?;
+            result.push(decoded);
+        }
+        Ok(result)
+    }
+
+    fn lookup_resource(&self, key: &str) -> io::Result<Bytes> {
+        let (num, key) = self.block_on(self.query_resource(key))?;
+        let cache = self.cache.as_ref().map(|c| c.file(1 + num));
+        self.mdd_files[num].lookup(&key, &self.mdd_blocks[num], cache)
+    }
+}
+
+#[async_trait]
+impl MDictAsyncLookup for MDictSqliteIndex {
+    async fn word_exists(&self, key: &str) -> io::Result<bool> {
+        self.query_word_exists(key).await
+    }
+    async fn lookup_word(&self, key: &str) -> io::Result<Vec<String>> {
+        let query = self.query_word(key).await?;
+        let mut result = vec![];
+        for key in query {
+            let cache = self.cache.as_ref().map(|c| c.file(0));
+            let bytes = self.mdx_file.lookup_async(&key, &self.mdx_blocks, cache).await?;
+            let decoded = self.header.decode_record(bytes)?;
+            result.push(decoded);
+        }
+        Ok(result)
+    }
+
+    async fn lookup_resource(&self, key: &str) -> io::Result<Bytes> {
+        let (num, key) = self.query_resource(key).await?;
+        let cache = self.cache.as_ref().map(|c| c.file(1 + num));
+        self.mdd_files[num].lookup_async(&key, &self.mdd_blocks[num], cache).await
+    }
 }
@@ -0,0 +1,107 @@
+// Dictionaries written into temporary directories for the tests
+#![allow(dead_code)]
+
+use mdict::{MDictIndex, MDictMode, MDictWriter};
+use std::convert::TryInto;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+// An empty directory for a test, removed if it is left by an earlier run
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mdict_index-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn write_file(path: &Path, writer: &mut MDictWriter) {
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+    fs::write(path, file).unwrap();
+}
+
+pub fn mdx_writer(entries: &[(&str, &str)]) -> MDictWriter {
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    for (key, record) in entries {
+        writer.add_entry(key, record).unwrap();
+    }
+    writer
+}
+
+pub fn mdd_writer(resources: &[(&str, &[u8])]) -> MDictWriter {
+    let mut writer = MDictWriter::new(MDictMode::Mdd);
+    for (path, data) in resources {
+        writer.add_resource(path, data).unwrap();
+    }
+    writer
+}
+
+// Write `dict.mdx` and `dict.mdd` into the directory, returns the path of the mdx file
+pub fn write_dictionary(
+    dir: &Path,
+    entries: &[(&str, &str)],
+    resources: &[(&str, &[u8])],
+) -> PathBuf {
+    let mdx = dir.join("dict.mdx");
+    write_file(&mdx, &mut mdx_writer(entries));
+    if !resources.is_empty() {
+        write_file(&dir.join("dict.mdd"), &mut mdd_writer(resources));
+    }
+    mdx
+}
+
+// The records without the `\0` at the end, sorted
+pub fn records(records: Vec<String>) -> Vec<String> {
+    let mut records: Vec<_> = records
+        .into_iter()
+        .map(|r| r.trim_end_matches('\0').to_owned())
+        .collect();
+    records.sort();
+    records
+}
+
+// Claim `padding` more bytes at the end of the first record block of a mdx file written with
+// uncompressed blocks, so that the records after it start beyond the padding. The lookups of
+// these records don't decompress the first block, so a padding of gigabytes costs nothing.
+pub fn pad_first_record_block(file: &mut [u8], padding: u64) {
+    let mut index = MDictIndex::new(Cursor::new(&file[..]), MDictMode::Mdx).unwrap();
+    let (key_blocks, record_blocks) = index.make_key_index().unwrap();
+    let first = record_blocks[0].uncomp_size;
+    // the record block index is a pair of compressed and uncompressed size for each block
+    let size = record_blocks[0].offset as usize - 16 * record_blocks.len() + 8;
+    assert_eq!(file[size..size + 8], first.to_be_bytes());
+    file[size..size + 8].copy_from_slice(&(first + padding).to_be_bytes());
+    // keyword blocks are the info, the checksum of the content and entries of
+    // the offset of record and the keyword ending with `\0`
+    for block in key_blocks {
+        let start = block.offset as usize;
+        let content = &mut file[start + 8..start + block.comp_size as usize];
+        let mut pos = 0;
+        while pos < content.len() {
+            let offset = u64::from_be_bytes(content[pos..pos + 8].try_into().unwrap());
+            if offset >= first {
+                content[pos..pos + 8].copy_from_slice(&(offset + padding).to_be_bytes());
+            }
+            pos += 8 + content[pos + 8..].iter().position(|b| *b == 0).unwrap() + 1;
+        }
+        let checksum = adler32(content);
+        file[start + 4..start + 8].copy_from_slice(&checksum.to_be_bytes());
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+// The records without the `\0` at the end, in the order they are found
+pub fn ordered_records(records: Vec<String>) -> Vec<String> {
+    records
+        .into_iter()
+        .map(|r| r.trim_end_matches('\0').to_owned())
+        .collect()
+}
@@ -0,0 +1,103 @@
+// Lookups of the disk index, which searches keyword blocks by their first and last keywords
+
+mod common;
+
+use common::*;
+use mdict_index::{MDictDiskIndex, MDictLookup};
+use std::io;
+use std::path::PathBuf;
+
+const KEYS: &[&str] = &["Apple", "Banana", "Zebra", "apple", "cherry", "zoo"];
+
+fn entries() -> Vec<(&'static str, String)> {
+    KEYS.iter().map(|k| (*k, format!("<p>{}</p>", k))).collect()
+}
+
+fn check_lookups(index: &MDictDiskIndex) {
+    for key in KEYS {
+        let records = records(index.lookup_word(&key.to_uppercase()).unwrap());
+        // both "Apple" and "apple" are found case-insensitively
+        assert!(records.contains(&format!("<p>{}</p>", key)), "{}", key);
+    }
+    assert_eq!(
+        records(index.lookup_word("apple").unwrap()),
+        ["<p>Apple</p>", "<p>apple</p>"]
+    );
+    let error = index.lookup_word("date").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert!(!index.word_exists("date").unwrap());
+}
+
+#[test]
+fn sorted_by_normalized_key() {
+    let dir = temp_dir("disk-sorted");
+    let entries = entries();
+    let entries: Vec<_> = entries.iter().map(|(k, r)| (*k, r.as_str())).collect();
+    let mdx = dir.join("dict.mdx");
+    let mut writer = mdx_writer(&entries);
+    writer.block_size(16, 16);
+    write_file(&mdx, &mut writer);
+    write_file(
+        &dir.join("dict.mdd"),
+        &mut mdd_writer(&[("/B.png", &[2]), ("/a.png", &[1]), ("/c.png", &[3])]),
+    );
+    let index = MDictDiskIndex::new(&mdx).unwrap();
+    check_lookups(&index);
+    for (path, data) in [("A.PNG", 1), ("b.png", 2), ("c.png", 3)] {
+        assert_eq!(&index.lookup_resource(path).unwrap()[..], [data]);
+    }
+}
+
+#[test]
+fn sorted_in_byte_order() {
+    // a file which is case-insensitive but sorted by the original keywords in byte order,
+    // the attribute added at last overrides the one written by the writer
+    let dir = temp_dir("disk-byte-order");
+    let entries = entries();
+    let entries: Vec<_> = entries.iter().map(|(k, r)| (*k, r.as_str())).collect();
+    let mdx = dir.join("dict.mdx");
+    let mut writer = mdx_writer(&entries);
+    writer
+        .block_size(16, 16)
+        .key_case_sensitive(true)
+        .attr("KeyCaseSensitive", "No");
+    write_file(&mdx, &mut writer);
+    let index = MDictDiskIndex::new(&mdx).unwrap();
+    assert!(!index.header().metadata().key_case_sensitive);
+    check_lookups(&index);
+}
+
+// "A" < "C" < "b" in byte order, so the first and last keywords of the block are in order
+// case-insensitively but "c" is out of them
+fn write_unsorted_block(name: &str) -> PathBuf {
+    let mdx = temp_dir(name).join("dict.mdx");
+    let mut writer = mdx_writer(&[("A", "<p>a</p>"), ("C", "<p>c</p>"), ("b", "<p>b</p>")]);
+    writer
+        .key_case_sensitive(true)
+        .attr("KeyCaseSensitive", "No");
+    write_file(&mdx, &mut writer);
+    mdx
+}
+
+#[test]
+fn unsorted_in_block() {
+    let index = MDictDiskIndex::new(write_unsorted_block("disk-unsorted-block")).unwrap();
+    // the block out of order is found when it is decoded,
+    // then the keywords out of the first and last keywords are found
+    assert_eq!(records(index.lookup_word("a").unwrap()), ["<p>a</p>"]);
+    assert_eq!(records(index.lookup_word("c").unwrap()), ["<p>c</p>"]);
+    assert_eq!(records(index.lookup_word("B").unwrap()), ["<p>b</p>"]);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn unsorted_in_block_async() {
+    use mdict_index::MDictAsyncLookup;
+
+    let mdx = write_unsorted_block("disk-unsorted-block-async");
+    let index = MDictDiskIndex::new_async(mdx).await.unwrap();
+    let found = MDictAsyncLookup::lookup_word(&index, "a").await.unwrap();
+    assert_eq!(records(found), ["<p>a</p>"]);
+    let found = MDictAsyncLookup::lookup_word(&index, "c").await.unwrap();
+    assert_eq!(records(found), ["<p>c</p>"]);
+}
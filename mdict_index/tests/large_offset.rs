@@ -0,0 +1,77 @@
+// Records beyond 4 GiB of the uncompressed record blocks
+
+mod common;
+
+use common::*;
+use mdict::*;
+use mdict_index::{MDictLookup, MDictMemIndex};
+use std::fs;
+use std::io::Cursor;
+
+const PADDING: u64 = 5 << 30;
+
+// Two record blocks, the records of the second block start beyond 5 GiB
+fn write_padded(path: &std::path::Path) -> Vec<u8> {
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer
+        .block_size(16, 16)
+        .compression(MDictCompression::None);
+    for key in ["a", "b", "c", "d"] {
+        writer.add_entry(key, &format!("<p>{}</p>", key)).unwrap();
+    }
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+    pad_first_record_block(&mut file, PADDING);
+    fs::write(path, &file).unwrap();
+    file
+}
+
+#[test]
+fn record_indexes_beyond_4_gib() {
+    let dir = temp_dir("large-offset");
+    let mdx = dir.join("dict.mdx");
+    let file = write_padded(&mdx);
+    let mut index = MDictIndex::new(Cursor::new(&file), MDictMode::Mdx).unwrap();
+    let (blocks, keys) = index.make_index().unwrap();
+    assert_eq!(blocks.len(), 2);
+    assert!(blocks[0].uncomp_size > PADDING);
+    // the last record of the first block runs over the padding
+    let (_, last) = keys.iter().rev().find(|(_, idx)| idx.block == 0).unwrap();
+    assert!(last.len > u32::MAX as u64);
+    // the records of the second block are found where they are
+    let (_, first) = keys.iter().find(|(_, idx)| idx.block == 1).unwrap();
+    assert_eq!(first.offset, 0);
+    let index = MDictMemIndex::new(&mdx).unwrap();
+    assert_eq!(records(index.lookup_word("d").unwrap()), ["<p>d</p>"]);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_columns_are_64_bit() {
+    use mdict_index::{MDictAsyncLookup, MDictSqliteIndex};
+
+    let dir = temp_dir("large-offset-sqlite");
+    let mdx = dir.join("dict.mdx");
+    write_padded(&mdx);
+    let index = MDictSqliteIndex::new(&mdx).await.unwrap();
+    let found = MDictAsyncLookup::lookup_word(&index, "d").await.unwrap();
+    assert_eq!(records(found), ["<p>d</p>"]);
+    index.close().await;
+
+    // the values are kept in the database as they are
+    let db = sqlx::SqlitePool::connect(&format!("sqlite://{}", mdx.with_extension("db").display()))
+        .await
+        .unwrap();
+    let size: i64 =
+        sqlx::query_scalar("select block_uncomp_size from mdx_block where block_index = 0")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert!(size as u64 > PADDING);
+    let len: i64 = sqlx::query_scalar("select max(record_size) from mdx_index")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(len as u64 > PADDING);
+    db.close().await;
+}
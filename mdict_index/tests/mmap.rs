@@ -0,0 +1,76 @@
+// Lookups of the indexes whose files are mapped into memory
+#![cfg(feature = "mmap")]
+
+mod common;
+
+use common::*;
+use mdict_index::{MDictDiskIndex, MDictLookup, MDictMemIndex};
+use std::fs;
+use std::path::Path;
+
+const ENTRIES: &[(&str, &str)] = &[("apple", "a fruit"), ("banana", "another fruit")];
+const RESOURCES: &[(&str, &[u8])] = &[("/a.png", &[1, 2, 3])];
+
+fn check_lookups(index: &dyn MDictLookup) {
+    assert_eq!(records(index.lookup_word("apple").unwrap()), ["a fruit"]);
+    assert_eq!(
+        records(index.lookup_word("Banana").unwrap()),
+        ["another fruit"]
+    );
+    assert!(!index.word_exists("cherry").unwrap());
+    assert_eq!(&index.lookup_resource("a.png").unwrap()[..], [1, 2, 3]);
+}
+
+// The mappings are kept after the files are removed on unix,
+// so the lookups never open the files again
+fn remove_files(mdx: &Path) {
+    if cfg!(unix) {
+        fs::remove_file(mdx).unwrap();
+        fs::remove_file(mdx.with_extension("mdd")).unwrap();
+    }
+}
+
+#[test]
+fn map_files_of_mem_index() {
+    let dir = temp_dir("mmap-mem");
+    let mdx = write_dictionary(&dir, ENTRIES, RESOURCES);
+    let mut index = MDictMemIndex::new(&mdx).unwrap();
+    check_lookups(&index);
+    unsafe { index.map_files().unwrap() };
+    // mapping again does nothing
+    unsafe { index.map_files().unwrap() };
+    remove_files(&mdx);
+    check_lookups(&index);
+}
+
+#[test]
+fn map_files_of_disk_index() {
+    let dir = temp_dir("mmap-disk");
+    let mdx = write_dictionary(&dir, ENTRIES, RESOURCES);
+    let mut index = MDictDiskIndex::new(&mdx).unwrap();
+    unsafe { index.map_files().unwrap() };
+    remove_files(&mdx);
+    // the keyword blocks are read from the mappings too
+    check_lookups(&index);
+}
+
+#[test]
+fn map_missing_files() {
+    let dir = temp_dir("mmap-missing");
+    let mdx = write_dictionary(&dir, ENTRIES, RESOURCES);
+    let mut index = MDictMemIndex::new(&mdx).unwrap();
+    fs::remove_file(dir.join("dict.mdd")).unwrap();
+    let error = unsafe { index.map_files().unwrap_err() };
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test(flavor = "multi_thread")]
+async fn map_files_of_sqlite_index() {
+    let dir = temp_dir("mmap-sqlite");
+    let mdx = write_dictionary(&dir, ENTRIES, RESOURCES);
+    let mut index = mdict_index::MDictSqliteIndex::new(&mdx).await.unwrap();
+    unsafe { index.map_files().unwrap() };
+    remove_files(&mdx);
+    check_lookups(&index);
+}
@@ -0,0 +1,134 @@
+// Keywords and resources matched by their normalized form
+
+mod common;
+
+use common::*;
+use mdict::{MDictMode, MDictWriter};
+use mdict_index::{MDictDiskIndex, MDictLookup, MDictMemIndex};
+use std::io;
+
+const ENTRIES: &[(&str, &str)] = &[
+    ("Rust", "a language"),
+    ("rust", "iron oxide"),
+    ("co-op", "a cooperative"),
+];
+const RESOURCES: &[(&str, &[u8])] = &[("/IMG/Logo.png", &[1, 2, 3])];
+
+#[test]
+fn case_insensitive_by_default() {
+    let dir = temp_dir("normalize-case");
+    let index = MDictMemIndex::new(write_dictionary(&dir, ENTRIES, RESOURCES)).unwrap();
+    for key in ["rust", "RUST", "Rust"] {
+        assert!(index.word_exists(key).unwrap());
+        assert_eq!(
+            records(index.lookup_word(key).unwrap()),
+            ["a language", "iron oxide"]
+        );
+    }
+    // punctuation is kept without StripKey
+    assert!(index.word_exists("co-op").unwrap());
+    assert!(!index.word_exists("coop").unwrap());
+    // the original keywords are kept for display
+    let mut keywords: Vec<_> = index.keyword_iter().collect();
+    keywords.sort();
+    assert_eq!(keywords, ["Rust", "co-op", "rust"]);
+
+    assert_eq!(
+        &index.lookup_resource("img/logo.png").unwrap()[..],
+        [1, 2, 3]
+    );
+    assert_eq!(
+        &index.lookup_resource("IMG/Logo.png").unwrap()[..],
+        [1, 2, 3]
+    );
+    let error = index.lookup_resource("img/other.png").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn case_sensitive_and_stripped() {
+    let dir = temp_dir("normalize-strip");
+    let mdx = dir.join("dict.mdx");
+    let mut writer = mdx_writer(ENTRIES);
+    writer.key_case_sensitive(true).strip_key(true);
+    write_file(&mdx, &mut writer);
+    let index = MDictMemIndex::new(&mdx).unwrap();
+    assert_eq!(records(index.lookup_word("Rust").unwrap()), ["a language"]);
+    assert_eq!(records(index.lookup_word("rust").unwrap()), ["iron oxide"]);
+    assert!(!index.word_exists("RUST").unwrap());
+    assert_eq!(
+        records(index.lookup_word("co op").unwrap()),
+        ["a cooperative"]
+    );
+    assert!(index.word_exists("coop").unwrap());
+}
+
+// Keywords of the same normalized form, whose records are in the reverse order of the file
+const DUPLICATES: &[(&str, &str)] = &[
+    ("RUST", "upper case"),
+    ("Rust", "title case"),
+    ("rust", "lower case"),
+];
+
+#[test]
+fn duplicates_in_file_order() {
+    let dir = temp_dir("normalize-duplicates");
+    let mdx = write_dictionary(&dir, DUPLICATES, &[]);
+    let expected = ["upper case", "title case", "lower case"];
+    let index = MDictMemIndex::new(&mdx).unwrap();
+    assert_eq!(
+        ordered_records(index.lookup_word("rust").unwrap()),
+        expected
+    );
+    let index = MDictDiskIndex::new(&mdx).unwrap();
+    assert_eq!(
+        ordered_records(index.lookup_word("rust").unwrap()),
+        expected
+    );
+}
+
+#[test]
+fn resource_key_without_separator() {
+    let dir = temp_dir("normalize-resource-key");
+    let mdx = write_dictionary(&dir, ENTRIES, &[]);
+    // a mdd file is parsed in UTF-16LE whatever its header says,
+    // so a resource file is written like a mdx file without the `\\` added to its keys
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    writer.encoding(encoding_rs::UTF_16LE);
+    writer.add_entry("a.png", "data").unwrap();
+    write_file(&dir.join("dict.mdd"), &mut writer);
+    let error = MDictMemIndex::new(&mdx).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use mdict_index::{MDictAsyncLookup, MDictSqliteIndex};
+
+    #[tokio::test]
+    async fn normalized_queries() {
+        let dir = temp_dir("normalize-sqlite");
+        let mdx = write_dictionary(&dir, ENTRIES, RESOURCES);
+        let index = MDictSqliteIndex::new(&mdx).await.unwrap();
+        assert!(MDictAsyncLookup::word_exists(&index, "RUST").await.unwrap());
+        let found = MDictAsyncLookup::lookup_word(&index, "rUST").await.unwrap();
+        assert_eq!(records(found), ["a language", "iron oxide"]);
+        let resource = MDictAsyncLookup::lookup_resource(&index, "Img/LOGO.png").await;
+        assert_eq!(&resource.unwrap()[..], [1, 2, 3]);
+        index.close().await;
+    }
+
+    #[tokio::test]
+    async fn duplicates_in_file_order() {
+        let dir = temp_dir("normalize-sqlite-duplicates");
+        let mdx = write_dictionary(&dir, DUPLICATES, &[]);
+        let index = MDictSqliteIndex::new(&mdx).await.unwrap();
+        let found = MDictAsyncLookup::lookup_word(&index, "rust").await.unwrap();
+        assert_eq!(
+            ordered_records(found),
+            ["upper case", "title case", "lower case"]
+        );
+        index.close().await;
+    }
+}
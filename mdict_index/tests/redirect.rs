@@ -0,0 +1,72 @@
+// Resolution of `@@@LINK=` redirects
+
+use mdict::*;
+// only the blocking lookup, the "async" feature adds methods of the same names
+use mdict_index::{
+    redirect_target, MDictLookup, MDictMemIndex, MDictResolved, DEFAULT_MAX_REDIRECTS,
+};
+use std::io;
+
+fn index(entries: &[(&str, &str)]) -> MDictMemIndex {
+    let mut entries = entries.to_vec();
+    entries.sort();
+    let mut writer = MDictWriter::new(MDictMode::Mdx);
+    for (key, record) in entries {
+        writer.add_entry(key, record).unwrap();
+    }
+    let mut file = Vec::new();
+    writer.write(&mut file).unwrap();
+    MDictMemIndex::from_sources(Box::new(file), Vec::new()).unwrap()
+}
+
+fn record(resolved: &MDictResolved) -> &str {
+    resolved
+        .record
+        .trim_end_matches(|c: char| c == '\0' || c.is_whitespace())
+}
+
+#[test]
+fn follow_redirects() {
+    let index = index(&[
+        ("went", "@@@LINK=go\r\n"),
+        ("gone", "@@@LINK=went\r\n"),
+        ("go", "<b>go</b> move"),
+        ("goes", "@@@LINK=go"),
+        ("going", "<b>going</b> see @@@LINK=go"),
+    ]);
+    let resolved = index.resolve_word("gone", DEFAULT_MAX_REDIRECTS).unwrap();
+    assert_eq!(resolved.len(), 1);
+    assert_eq!(resolved[0].chain, ["gone", "went", "go"]);
+    assert_eq!(record(&resolved[0]), "<b>go</b> move");
+
+    // a link in a record is not a redirect
+    let resolved = index.resolve_word("going", DEFAULT_MAX_REDIRECTS).unwrap();
+    assert_eq!(resolved[0].chain, ["going"]);
+    assert_eq!(redirect_target(&resolved[0].record), None);
+    assert_eq!(
+        redirect_target(&index.lookup_word("goes").unwrap()[0]),
+        Some("go")
+    );
+
+    let resolved = index.resolve_word("gone", 1);
+    assert_eq!(resolved.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert!(index.resolve_word("gone", 2).is_ok());
+}
+
+#[test]
+fn redirect_cycle_and_missing_target() {
+    let index = index(&[
+        ("a", "@@@LINK=b"),
+        ("b", "@@@LINK=c"),
+        ("c", "@@@LINK=a"),
+        ("d", "@@@LINK=nowhere"),
+    ]);
+    let error = index.resolve_word("a", 100).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("a -> b -> c -> a"));
+
+    // a redirect to nowhere is kept as a link
+    let resolved = index.resolve_word("d", DEFAULT_MAX_REDIRECTS).unwrap();
+    assert_eq!(resolved[0].chain, ["d"]);
+    assert_eq!(redirect_target(&resolved[0].record), Some("nowhere"));
+}
@@ -0,0 +1,103 @@
+// Blocking and asynchronous lookups of the sqlite index
+#![cfg(feature = "sqlite")]
+
+mod common;
+
+use common::*;
+use mdict_index::{MDictAsyncLookup, MDictLookup, MDictSqliteIndex};
+use std::io;
+use std::sync::Arc;
+
+const ENTRIES: &[(&str, &str)] = &[("apple", "a fruit"), ("banana", "another fruit")];
+const RESOURCES: &[(&str, &[u8])] = &[("/a.png", &[1, 2, 3])];
+
+async fn open(name: &str) -> MDictSqliteIndex {
+    let dir = temp_dir(name);
+    MDictSqliteIndex::new(write_dictionary(&dir, ENTRIES, RESOURCES))
+        .await
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn blocking_lookup_in_async_task() {
+    let index = open("sqlite-multi-thread").await;
+    assert!(MDictLookup::word_exists(&index, "apple").unwrap());
+    let found = MDictLookup::lookup_word(&index, "banana").unwrap();
+    assert_eq!(records(found), ["another fruit"]);
+    let resource = MDictLookup::lookup_resource(&index, "a.png").unwrap();
+    assert_eq!(&resource[..], [1, 2, 3]);
+    let error = MDictLookup::lookup_word(&index, "cherry").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+    // and in the blocking thread pool
+    let index = Arc::new(index);
+    let blocking = index.clone();
+    let found = tokio::task::spawn_blocking(move || MDictLookup::lookup_word(&*blocking, "apple"))
+        .await
+        .unwrap();
+    assert_eq!(records(found.unwrap()), ["a fruit"]);
+    let found = MDictAsyncLookup::lookup_word(&*index, "apple")
+        .await
+        .unwrap();
+    assert_eq!(records(found), ["a fruit"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn blocking_lookup_in_current_thread_runtime() {
+    let index = open("sqlite-current-thread").await;
+    let error = MDictLookup::lookup_word(&index, "apple").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    let found = MDictAsyncLookup::lookup_word(&index, "apple")
+        .await
+        .unwrap();
+    assert_eq!(records(found), ["a fruit"]);
+}
+
+#[test]
+fn blocking_lookup_outside_runtime() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let index = runtime.block_on(open("sqlite-outside"));
+    let found = MDictLookup::lookup_word(&index, "apple").unwrap();
+    assert_eq!(records(found), ["a fruit"]);
+}
+
+#[cfg(feature = "zip")]
+#[tokio::test(flavor = "multi_thread")]
+async fn index_opened_archive() {
+    use mdict_index::MDictArchive;
+    use std::io::Write;
+    use zip::write::{FileOptions, ZipWriter};
+    use zip::CompressionMethod;
+
+    let dir = temp_dir("sqlite-archive");
+    let mdx = write_dictionary(&dir, ENTRIES, RESOURCES);
+    std::fs::write(dir.join("style.css"), "p {}").unwrap();
+    let path = dir.join("dict.zip");
+    let mut zip = ZipWriter::new(std::fs::File::create(&path).unwrap());
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for name in ["dict.mdx", "dict.mdd", "style.css"] {
+        zip.start_file(name, deflated).unwrap();
+        zip.write_all(&std::fs::read(dir.join(name)).unwrap())
+            .unwrap();
+    }
+    zip.finish().unwrap();
+    std::fs::remove_file(&mdx).unwrap();
+
+    // the index is built, and then the database next to the archive is used
+    for _ in 0..2 {
+        let archive = Arc::new(MDictArchive::open(&path).unwrap());
+        let index = MDictSqliteIndex::from_archive(archive.clone())
+            .await
+            .unwrap();
+        assert!(dir.join("dict.db").exists());
+        let found = MDictAsyncLookup::lookup_word(&index, "banana")
+            .await
+            .unwrap();
+        assert_eq!(records(found), ["another fruit"]);
+        let resource = MDictAsyncLookup::lookup_resource(&index, "a.png").await;
+        assert_eq!(&resource.unwrap()[..], [1, 2, 3]);
+        // the archive is kept for the other files
+        assert_eq!(archive.read_file("style.css").unwrap(), b"p {}");
+        index.close().await;
+    }
+}
@@ -1,5 +1,6 @@
 use bytes::Bytes;
-use mdict_index::{MDictAsyncLookup, MDictSqliteIndex};
+use mdict::MDictBlockCache;
+use mdict_index::{MDictArchive, MDictAsyncLookup, MDictSqliteIndex, DEFAULT_MAX_REDIRECTS};
 use regex::Regex;
 use std::{
     env, fmt::Write as _, fs::File, io::{stderr, Read, Write}, path::{Path, PathBuf}, sync::Arc
@@ -10,12 +11,20 @@ use warp::{filters::path::Tail, http::Response, Filter};
 use tokio::io::AsyncReadExt;
 
 static MDICT_RESULT_HTML: &'static str = include_str!("../static/html/result.html");
+// Decompressed record blocks kept in memory, shared by all dictionaries
+const BLOCK_CACHE_SIZE: usize = 64 << 20;
 
 fn usage(program: &str) {
     let usage = format!("Usage: {} config-file port\n", program);
     stderr().write(usage.as_bytes()).unwrap();
 }
 
+// Where the files next to a dictionary, such as CSS and JS, are read
+enum MDictFiles {
+    Dir(PathBuf),
+    Archive(Arc<MDictArchive>),
+}
+
 #[derive(Serialize)]
 struct MDictContent {
     title: String,
@@ -86,16 +95,20 @@ async fn main() {
     let log = warp::log("main");
     let mut indexes = Vec::new();
     let mut paths = Vec::new();
+    let cache = Arc::new(MDictBlockCache::new(BLOCK_CACHE_SIZE));
     for path in config.lines() {
-        let mdict = MDictSqliteIndex::new(&path).await.unwrap();
+        let path = Path::new(&path).canonicalize().unwrap();
+        // an archive is opened once, for both the index and the other files in it
+        let mut mdict = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) {
+            let archive = Arc::new(MDictArchive::open(&path).unwrap());
+            paths.push(MDictFiles::Archive(archive.clone()));
+            MDictSqliteIndex::from_archive(archive).await.unwrap()
+        } else {
+            paths.push(MDictFiles::Dir(path.parent().unwrap().to_owned()));
+            MDictSqliteIndex::new(&path).await.unwrap()
+        };
+        mdict.set_block_cache(cache.clone());
         indexes.push(mdict);
-        let dir = Path::new(&path)
-            .canonicalize()
-            .unwrap()
-            .parent()
-            .unwrap()
-            .to_owned();
-        paths.push(dir);
     }
     let indexes = Arc::new(indexes);
     let paths = Arc::new(paths);
@@ -139,21 +152,35 @@ async fn main() {
         .and(warp::path::end())
         .and(paths_shared)
         .and_then(
-            |i: usize, uri: String, paths: Arc<Vec<PathBuf>>| async move {
+            |i: usize, uri: String, paths: Arc<Vec<MDictFiles>>| async move {
                 if i >= paths.len() {
                     return Err(warp::reject::not_found());
                 }
                 log::info!("load files: {:?}/{:?}", i, uri);
-                let mut file = paths[i].clone();
-                file.push(&uri);
-                if file.exists() {
-                    let mut file = tokio::fs::File::open(&file)
-                        .await
-                        .map_err(|_| warp::reject::not_found())?;
-                    let mut data = Vec::new();
-                    file.read_to_end(&mut data)
-                        .await
-                        .map_err(|_| warp::reject::not_found())?;
+                let data = match &paths[i] {
+                    MDictFiles::Dir(dir) => {
+                        let file = dir.join(&uri);
+                        if !file.exists() {
+                            return Err(warp::reject::not_found());
+                        }
+                        let mut file = tokio::fs::File::open(&file)
+                            .await
+                            .map_err(|_| warp::reject::not_found())?;
+                        let mut data = Vec::new();
+                        file.read_to_end(&mut data)
+                            .await
+                            .map_err(|_| warp::reject::not_found())?;
+                        Some(data)
+                    }
+                    MDictFiles::Archive(archive) => {
+                        let archive = archive.clone();
+                        let name = uri.clone();
+                        tokio::task::spawn_blocking(move || archive.read_file(&name).ok())
+                            .await
+                            .unwrap_or(None)
+                    }
+                };
+                if let Some(data) = data {
                     let mime = mime_guess::from_path(uri).first();
                     let mime = mime.unwrap_or(mime::TEXT_HTML_UTF_8);
                     let data = if mime == mime::TEXT_CSS || mime == mime::TEXT_CSS_UTF_8 {
@@ -206,7 +233,7 @@ async fn main() {
                 let mut no_result = true;
                 let mut mdict_contents = Vec::new();
                 for (i, dict) in mdict.iter().enumerate() {
-                    let result = dict.lookup_word(&key).await;
+                    let result = dict.resolve_word(&key, DEFAULT_MAX_REDIRECTS).await;
                     let contents = match result {
                         Ok(result) => result,
                         Err(e) => {
@@ -217,9 +244,9 @@ async fn main() {
                         },
                     };
                     no_result = false;
-                    let contents = contents.into_iter().map(|s| fix_content(s, i) ).collect();
+                    let contents = contents.into_iter().map(|r| fix_content(r.record, i) ).collect();
                     mdict_contents.push(MDictContent{
-                        title: dict.header.attrs.get("Title").unwrap_or(&"Unknown dictionary".to_string()).to_owned(),
+                        title: dict.header.metadata().title.clone().unwrap_or_else(|| "Unknown dictionary".to_string()),
                         index: i,
                         contents
                     });
@@ -1,4 +1,4 @@
-use mdict_index::{MDictAsyncLookup, MDictSqliteIndex};
+use mdict_index::{MDictAsyncLookup, MDictSqliteIndex, DEFAULT_MAX_REDIRECTS};
 use regex::Regex;
 use std::{env, path::Path, sync::Arc};
 use warp::{filters::path::Tail, http::Response, Filter};
@@ -22,7 +22,7 @@ async fn main() {
         .to_owned();
     let mdict = Arc::new(MDictSqliteIndex::new(&file).await.unwrap());
     let mdict_clone = mdict.clone();
-    let shared = warp::any().map(move || (mdict.clone()));
+    let shared = warp::any().map(move || mdict.clone());
     let mdict_server = warp::path::tail().and(shared).and_then(
         |path: Tail, mdict: Arc<MDictSqliteIndex>| async move {
             let path = path.as_str();
@@ -31,9 +31,9 @@ async fn main() {
                 Some(_) => mdict.lookup_resource(path).await,
                 None => {
                     let key = urlencoding::decode(path).unwrap();
-                    let result = mdict.lookup_word(&key).await;
+                    let result = mdict.resolve_word(&key, DEFAULT_MAX_REDIRECTS).await;
                     result.map(|opt| {
-                        let string = Regex::new("(entry|sound):/").unwrap().replace_all(&opt[0], "");
+                        let string = Regex::new("(entry|sound):/").unwrap().replace_all(&opt[0].record, "");
                         let string = Regex::new("@@@LINK=([\\w]+)").unwrap().replace_all(
                             &string,
                             |link: &regex::Captures| {